@@ -0,0 +1,64 @@
+// Minimal sparklines for the stats dashboard, drawn on a canvas so the page
+// needs no chart library from elsewhere.
+//
+//   const line = new Sparkline(canvas, { color: '#0f0', max: 100 });
+//   line.push(42);
+(function () {
+    class Sparkline {
+        constructor(canvas, options = {}) {
+            this.canvas = canvas;
+            this.color = options.color || 'currentColor';
+            // Fixed top of the scale, e.g. 100 for percentages; otherwise the
+            // largest value shown
+            this.max = options.max || 0;
+            this.points = options.points || 60;
+            this.values = [];
+        }
+
+        push(value) {
+            this.values.push(Number(value) || 0);
+            if (this.values.length > this.points) this.values.shift();
+            this.draw();
+        }
+
+        draw() {
+            const canvas = this.canvas;
+            const ratio = window.devicePixelRatio || 1;
+            const width = canvas.clientWidth, height = canvas.clientHeight;
+            if (canvas.width !== width * ratio || canvas.height !== height * ratio) {
+                canvas.width = width * ratio;
+                canvas.height = height * ratio;
+            }
+            const ctx = canvas.getContext('2d');
+            ctx.setTransform(ratio, 0, 0, ratio, 0, 0);
+            ctx.clearRect(0, 0, width, height);
+            if (this.values.length < 2) return;
+
+            const top = this.max || Math.max(...this.values) || 1;
+            const step = width / (this.points - 1);
+            const offset = (this.points - this.values.length) * step;
+            const y = v => height - 1 - Math.min(v / top, 1) * (height - 2);
+            const color = this.color === 'currentColor' ? getComputedStyle(canvas).color : this.color;
+
+            ctx.beginPath();
+            this.values.forEach((v, i) => {
+                if (i === 0) ctx.moveTo(offset, y(v));
+                else ctx.lineTo(offset + i * step, y(v));
+            });
+            ctx.strokeStyle = color;
+            ctx.lineWidth = 1.5;
+            ctx.stroke();
+
+            // Fill below the line, faintly
+            ctx.lineTo(offset + (this.values.length - 1) * step, height);
+            ctx.lineTo(offset, height);
+            ctx.closePath();
+            ctx.globalAlpha = 0.15;
+            ctx.fillStyle = color;
+            ctx.fill();
+            ctx.globalAlpha = 1;
+        }
+    }
+
+    window.Sparkline = Sparkline;
+})();
@@ -0,0 +1,62 @@
+// Service worker for installing the web UI as an app. Assets load from the
+// cache straight away and are refreshed in the background; pages come from
+// the network, falling back to the last copy when docker-direct can't be
+// reached. API requests are never cached, as they must be current.
+const ASSETS = 'docker-direct-assets';
+const PAGES = 'docker-direct-pages';
+const PRECACHE = ['/assets/icon.svg', '/assets/icon-192.png', '/assets/sparkline.js'];
+// The dashboard's pages; others, such as one-time action links, always need the network
+const PAGE_PATHS = ['/containers', '/stats', '/logs'];
+
+self.addEventListener('install', event => {
+    event.waitUntil(caches.open(ASSETS).then(cache => cache.addAll(PRECACHE)).then(() => self.skipWaiting()));
+});
+
+self.addEventListener('activate', event => {
+    event.waitUntil(self.clients.claim());
+});
+
+// Cached copy first, while fetching a fresh one for next time, so an upgraded
+// docker-direct's assets show up on the following load
+async function staleWhileRevalidate(request) {
+    const cache = await caches.open(ASSETS);
+    const cached = await cache.match(request);
+    const fresh = fetch(request).then(r => {
+        if (r.ok) cache.put(request, r.clone());
+        return r;
+    });
+    if (!cached) return fresh;
+    fresh.catch(() => {});
+    return cached;
+}
+
+// Only pages that loaded signed in are kept, not redirects to the login page
+async function networkFirst(request) {
+    const cache = await caches.open(PAGES);
+    try {
+        const r = await fetch(request);
+        if (r.ok && !r.redirected) cache.put(request, r.clone());
+        return r;
+    } catch (e) {
+        const cached = await cache.match(request);
+        if (cached) return cached;
+        throw e;
+    }
+}
+
+self.addEventListener('fetch', event => {
+    const request = event.request;
+    const url = new URL(request.url);
+    if (url.origin !== location.origin) return;
+    if (url.pathname === '/logout') {
+        // Signed-out browsers keep no copies of the pages
+        event.waitUntil(caches.delete(PAGES));
+        return;
+    }
+    if (request.method !== 'GET') return;
+    if (url.pathname.startsWith('/assets/')) {
+        event.respondWith(staleWhileRevalidate(request));
+    } else if (request.mode === 'navigate' && PAGE_PATHS.includes(url.pathname)) {
+        event.respondWith(networkFirst(request));
+    }
+});
@@ -1,28 +1,48 @@
 use anyhow::Result;
 use askama::Template;
 use axum::{
-    extract::{ConnectInfo, Query, State},
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, Path, State,
+    },
     http::StatusCode,
-    response::{Html, IntoResponse, Json},
-    routing::get,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, IntoResponse, Json,
+    },
+    routing::{get, post},
     Router,
 };
 use bollard::{
     container::{
-        InspectContainerOptions, ListContainersOptions, StartContainerOptions,
-        StatsOptions, StopContainerOptions,
+        Config, CreateContainerOptions, DownloadFromContainerOptions, InspectContainerOptions,
+        KillContainerOptions, ListContainersOptions, LogOutput, LogsOptions, NetworkingConfig,
+        RemoveContainerOptions, RenameContainerOptions, RestartContainerOptions,
+        StartContainerOptions, StatsOptions, StopContainerOptions, TopOptions,
+        UpdateContainerOptions,
+    },
+    exec::{CreateExecOptions, StartExecOptions, StartExecResults},
+    image::{CreateImageOptions, PruneImagesOptions},
+    models::{
+        CreateImageInfo, EndpointSettings, PortBinding, RestartPolicy, RestartPolicyNameEnum,
+    },
+    network::{
+        ConnectNetworkOptions, CreateNetworkOptions, DisconnectNetworkOptions, ListNetworksOptions,
     },
+    service::{ListServicesOptions, UpdateServiceOptions},
+    system::EventsOptions,
+    volume::{CreateVolumeOptions, ListVolumesOptions},
     Docker,
 };
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
     fs::read_to_string,
     net::SocketAddr,
     sync::Arc,
 };
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 use tracing::Level;
 use tracing_subscriber::FmtSubscriber;
 
@@ -30,18 +50,139 @@ use tracing_subscriber::FmtSubscriber;
 // Shared application state
 // ---------------------------------------------------------------------------
 
+/// Which containers, services and networks may be managed, and how. Built
+/// from the allowlist, the config file and the JSON files, and swapped as a
+/// whole when the configuration is reloaded.
+#[derive(Debug, Default, Clone)]
+struct Policy {
+    /// Listed containers plus those opted in through `allow_label`
+    allowed_containers: Vec<String>,
+    /// Containers from `-c`, `-f` or the config file's allowlist
+    listed_containers: Vec<String>,
+    /// Containers with this label set to `true` are allowed, from `--allow-label`
+    allow_label: Option<String>,
+    /// In deny-list mode every container except these is allowed, from `--deny`
+    denied_containers: Option<Vec<String>>,
+    /// Where the listed or denied containers came from, for persisting changes
+    allowlist_source: AllowlistSource,
+    /// container name -> command name -> argv, from `--exec-file`
+    exec_commands: HashMap<String, HashMap<String, Vec<String>>>,
+    /// container name -> download name -> path inside the container, from `--download-file`
+    download_paths: HashMap<String, HashMap<String, String>>,
+    /// Swarm services that may be listed and scaled, from `--services`
+    allowed_services: Vec<String>,
+    /// container name -> containers it depends on, from `--depends-file`
+    dependencies: HashMap<String, Vec<String>>,
+    /// stack name -> compose file, from `--stacks-file`
+    stacks: HashMap<String, String>,
+    /// exclusivity group name -> members of which only one may run, from `--exclusive-file`
+    exclusive_groups: HashMap<String, ExclusiveGroup>,
+    /// profile name -> containers that run while it is active, from `--profiles-file`
+    profiles: HashMap<String, Vec<String>>,
+    /// group name -> member containers, from `--groups-file`
+    groups: HashMap<String, Vec<String>>,
+    /// Networks containers may be connected to or disconnected from
+    managed_networks: Vec<String>,
+    /// container name -> actions it permits, from `--actions-file`; containers
+    /// without an entry permit every action
+    permitted_actions: HashMap<String, Vec<String>>,
+    /// container name -> display name, description, category and icon, from `--display-file`
+    display: HashMap<String, ContainerDisplay>,
+}
+
+/// Where the allowlist (or deny list) was read from.
+#[derive(Debug, Default, Clone)]
+enum AllowlistSource {
+    /// `-c`, `--deny` or `--config-dir` without `--config`; changes cannot be persisted
+    #[default]
+    CommandLine,
+    /// A text file with one container per line
+    File(String),
+    /// The `allowed` or `denied` key of the TOML config file
+    Config(String),
+}
+
 struct AppState {
     docker: Docker,
-    allowed_containers: Vec<String>,
+    /// Replaced on SIGHUP; take a snapshot with `current_policy`
+    policy: std::sync::RwLock<Arc<Policy>>,
     port: u64,
+    restart_timeout: isize,
+    resource_bounds: ResourceBounds,
+    /// Seconds to wait for each dependency to become running or healthy
+    dependency_timeout: u64,
+    admin_token: Option<String>,
+    auth: AuthSettings,
     container_cache: RwLock<Vec<ContainerInfo>>,
     cache_generation: std::sync::atomic::AtomicU64,
     common_filters: HashMap<String, Vec<String>>,
     mc_cache: RwLock<HashMap<String, MinecraftInfo>>,
+    /// Most recent Docker events for allowed containers, oldest first
+    event_log: RwLock<VecDeque<DockerEvent>>,
+    event_tx: broadcast::Sender<DockerEvent>,
+    /// Wakes `background_refresh` early when a Docker event changed a container
+    refresh_now: tokio::sync::Notify,
+    /// Sent after each refresh `refresh_now` asked for, see `containers_ws_handle`
+    list_changed: broadcast::Sender<()>,
+    /// Minutes between registry update checks; 0 disables them
+    update_check_interval: u64,
+    /// container name -> whether the registry has a newer image
+    update_cache: RwLock<HashMap<String, bool>>,
+    /// Include stopped containers in the container list
+    show_stopped: bool,
+    /// Reject every change, for status displays
+    read_only: bool,
+    /// Networks clients may connect from; any address when empty
+    allowed_clients: Vec<ipnet::IpNet>,
+    /// Reverse proxies whose `X-Forwarded-For` and `X-Real-IP` headers are believed
+    trusted_proxies: Vec<ipnet::IpNet>,
+    /// Action requests each client may make per minute; 0 disables the limit
+    rate_limit: u64,
+    /// client -> its remaining requests, see `rate_limit_actions`
+    rate_buckets: std::sync::Mutex<HashMap<String, RateBucket>>,
+    /// caller and `Idempotency-Key` -> the request, see `replay_idempotent`
+    idempotent_requests: std::sync::Mutex<HashMap<String, IdempotentRequest>>,
+    /// Seconds a request may take before it is answered with `504`; 0 disables
+    request_timeout: u64,
+    /// Requests handled at the same time, see `limit_requests`; `None` when unlimited
+    request_permits: Option<Arc<tokio::sync::Semaphore>>,
+    /// Failed sign-ins in a row before a client or user is locked out; 0 disables lockouts
+    login_attempts: u32,
+    /// `client:<address>` or `user:<name>` -> recent failed sign-ins
+    login_failures: std::sync::Mutex<HashMap<String, LoginFailures>>,
+    /// Key the web UI's CSRF tokens are derived with, see `csrf_token`
+    csrf_secret: String,
+    /// Name shown in the web UI's heading and browser tab
+    title: String,
+    /// CSS color replacing the theme's highlight color, from `--accent-color`
+    accent_color: Option<String>,
+    /// Theme of browsers that haven't picked one, one of `THEMES`
+    theme: String,
+    /// The web UI's texts per locale, from `--locale-dir`
+    locales: Locales,
+    /// Sites allowed to call the API from the browser, see `cors_layer`
+    cors_origins: Vec<String>,
+    /// Append-only JSON Lines file recording every change, from `--audit-log`
+    audit_log: Option<(String, tokio::sync::Mutex<tokio::fs::File>)>,
+    /// Key action links are signed with, see `create_link_handle`
+    link_secret: String,
+    /// nonce -> expiry, of action links that have been used
+    used_links: std::sync::Mutex<HashMap<String, i64>>,
+    /// File `used_links` is saved to, next to the audit log, see `save_used_links`
+    used_links_file: Option<(String, tokio::sync::Mutex<()>)>,
 }
 
 type SharedState = Arc<AppState>;
 
+/// Limits users may choose between when updating container resources, so a
+/// limit can be adjusted but never removed.
+struct ResourceBounds {
+    min_cpu_shares: isize,
+    max_cpu_shares: isize,
+    min_memory_mb: i64,
+    max_memory_mb: i64,
+}
+
 // ---------------------------------------------------------------------------
 // Data models
 // ---------------------------------------------------------------------------
@@ -85,25 +226,323 @@ pub struct ContainerInfo {
     pub name: String,
     pub state: String,
     pub status: String,
+    /// `starting`, `healthy` or `unhealthy` for running containers with a health check
+    pub health: Option<String>,
     pub image: String,
     pub created: String,
+    /// When the container last started; empty if it never did
+    pub started_at: String,
+    /// Time running since then, e.g. `2d 4h 10m`; empty unless running
     pub uptime: String,
+    /// The same in seconds, for sorting and formatting
+    pub uptime_seconds: Option<i64>,
     pub ports: Vec<PortMapping>,
+    pub mounts: Vec<MountInfo>,
+    pub networks: Vec<String>,
+    /// Compose project the container belongs to, from its labels
+    pub project: Option<String>,
     pub cpu_percent: f64,
     pub memory_usage: u64,
     pub memory_limit: u64,
+    pub network_rx_bytes: u64,
+    pub network_tx_bytes: u64,
     pub restart_count: i64,
+    /// Exit code of the last run, for exited containers
+    pub exit_code: Option<i64>,
+    /// Registry has a newer digest for the image tag this container runs
+    pub update_available: bool,
     pub minecraft: Option<MinecraftInfo>,
+    #[serde(flatten)]
+    pub display: ContainerDisplay,
     #[serde(skip)]
     pub rcon_password: String,
 }
 
+/// How a container is presented in the dashboard, from `--display-file`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContainerDisplay {
+    pub display_name: Option<String>,
+    pub description: Option<String>,
+    pub category: Option<String>,
+    /// URL of an icon image
+    pub icon: Option<String>,
+    /// `http` or `https` for links to published ports; setting it links
+    /// every published TCP port, not only the usual web ports
+    pub link_scheme: Option<String>,
+    /// Host for links to published ports, instead of the one the page was
+    /// opened on, e.g. a name a reverse proxy answers to
+    pub link_host: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ContainerStats {
+    pub cpu_percent: f64,
+    pub memory_usage: u64,
+    pub memory_limit: u64,
+    pub network_rx_bytes: u64,
+    pub network_tx_bytes: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct StatsSnapshot {
+    pub name: String,
+    pub state: String,
+    #[serde(flatten)]
+    pub stats: ContainerStats,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RestartPolicyInfo {
+    pub name: String,
+    pub maximum_retry_count: i64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MountInfo {
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Volume name; empty for bind mounts.
+    pub name: String,
+    pub source: String,
+    pub destination: String,
+    pub read_only: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct HealthSummary {
+    pub status: String,
+    pub failing_streak: i64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct HealthProbe {
+    pub start: String,
+    pub end: String,
+    pub exit_code: i64,
+    pub output: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct HealthDetail {
+    pub status: String,
+    pub failing_streak: i64,
+    pub probes: Vec<HealthProbe>,
+}
+
+/// Trimmed-down inspect document. Environment variable values, labels and
+/// the full config are left out since they commonly carry secrets.
+#[derive(Clone, Debug, Serialize)]
+pub struct ContainerInspect {
+    pub name: String,
+    pub image: String,
+    pub created: String,
+    pub state: String,
+    pub restart_policy: RestartPolicyInfo,
+    pub mounts: Vec<MountInfo>,
+    pub ports: Vec<PortMapping>,
+    pub health: Option<HealthSummary>,
+    pub env_keys: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct WaitOutcome {
+    pub reached: bool,
+    pub state: String,
+    pub health: Option<String>,
+    pub exit_code: Option<i64>,
+}
+
+/// One line of the audit log.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Unix timestamp (seconds).
+    pub time: i64,
+    pub client: String,
+    /// User or API key, when authenticated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// The route, e.g. `/containers/start`
+    pub action: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    /// HTTP status of the response
+    pub status: u16,
+    /// `ok` or `error`, or for sign-ins `failed` or `locked_out`
+    pub result: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DockerEvent {
+    /// Unix timestamp (seconds).
+    pub time: i64,
+    pub container: String,
+    pub action: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ImageInfo {
+    pub id: String,
+    pub repository: String,
+    pub tag: String,
+    pub digest: String,
+    pub size: i64,
+    pub created: String,
+    pub containers: Vec<String>,
+}
+
+/// Outcome of one step of a multi-step action such as recreate or update.
+#[derive(Clone, Debug, Serialize)]
+pub struct StepReport {
+    pub step: &'static str,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// One row of the disk usage summary, like a line of `docker system df`.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DiskUsageEntry {
+    pub total: usize,
+    pub active: usize,
+    pub size: i64,
+    pub reclaimable: i64,
+}
+
+/// Allowed containers sharing a compose project label.
+#[derive(Clone, Debug, Serialize)]
+pub struct ProjectInfo {
+    pub name: String,
+    pub running: usize,
+    pub containers: Vec<String>,
+}
+
+/// A Swarm service from the services allowlist.
+#[derive(Clone, Debug, Serialize)]
+pub struct ServiceInfo {
+    pub name: String,
+    pub image: String,
+    /// `replicated` or `global`
+    pub mode: String,
+    /// Desired replicas; only set for replicated services
+    pub replicas: Option<i64>,
+    pub running_tasks: u64,
+    pub desired_tasks: u64,
+}
+
+/// Containers of which at most one may run at a time.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ExclusiveGroup {
+    pub containers: Vec<String>,
+    /// What starting a member does while another member runs
+    #[serde(default)]
+    pub on_conflict: ExclusiveConflict,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExclusiveConflict {
+    /// Refuse to start the container
+    #[default]
+    Reject,
+    /// Stop the running members first
+    Stop,
+}
+
+/// A named group of containers from the groups file.
+#[derive(Clone, Debug, Serialize)]
+pub struct GroupInfo {
+    pub name: String,
+    pub running: usize,
+    pub containers: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DiskUsage {
+    pub images: DiskUsageEntry,
+    pub containers: DiskUsageEntry,
+    pub volumes: DiskUsageEntry,
+    pub build_cache: DiskUsageEntry,
+}
+
+/// The subset of a compose file that stack deploys understand. Unknown keys
+/// are ignored.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ComposeFile {
+    #[serde(default)]
+    pub services: HashMap<String, ComposeService>,
+    #[serde(default)]
+    pub networks: HashMap<String, Option<ComposeResource>>,
+    #[serde(default)]
+    pub volumes: HashMap<String, Option<ComposeResource>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ComposeService {
+    pub image: String,
+    pub container_name: Option<String>,
+    pub command: Option<ComposeCommand>,
+    pub environment: Option<ComposeListOrMap>,
+    pub labels: Option<ComposeListOrMap>,
+    #[serde(default)]
+    pub ports: Vec<String>,
+    #[serde(default)]
+    pub volumes: Vec<String>,
+    pub networks: Option<ComposeListOrMap>,
+    pub depends_on: Option<ComposeListOrMap>,
+    pub restart: Option<String>,
+}
+
+/// A top-level network or volume; external ones are used but never created.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ComposeResource {
+    #[serde(default)]
+    pub external: bool,
+    pub name: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ComposeCommand {
+    Line(String),
+    Args(Vec<String>),
+}
+
+/// Compose accepts several keys either as a list or as a mapping.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ComposeListOrMap {
+    List(Vec<String>),
+    Map(HashMap<String, Option<serde_json::Value>>),
+}
+
 // Keep the old Container struct for the template (initial HTML render)
 #[derive(Clone, Debug, Serialize)]
 pub struct Container {
     pub name: String,
     pub status: String,
     pub state: String,
+    pub health: Option<String>,
+    pub ports: Vec<PortMapping>,
+    pub created: String,
+    pub started_at: String,
+    pub uptime: String,
+}
+
+impl Container {
+    /// Badge color class: green when running or healthy, yellow while starting,
+    /// red when unhealthy or stopped
+    pub fn badge(&self) -> &str {
+        match (self.state.as_str(), self.health.as_deref()) {
+            ("running", Some(health)) => health,
+            ("running", None) => "running",
+            ("paused", _) => "paused",
+            ("created" | "restarting", _) => "starting",
+            _ => "exited",
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Template)]
@@ -111,6 +550,73 @@ pub struct Container {
 pub struct ContainersTemplate {
     pub containers: Vec<Container>,
     pub port: u64,
+    /// Show a sign-out link, for browsers signed in with a session
+    pub signed_in: bool,
+    /// The viewer's role, to hide controls it may not use
+    pub role: &'static str,
+    /// Sent back with every change, see `protect_from_csrf`
+    pub csrf_token: String,
+    pub title: String,
+    /// Hex color overriding the theme's highlight color; empty keeps it
+    pub accent_color: String,
+    /// Default theme, for browsers where none was picked
+    pub theme: String,
+    /// Locale of the texts, for the page's `lang`
+    pub lang: String,
+    #[serde(skip)]
+    pub t: Arc<Messages>,
+}
+
+#[derive(Clone, Debug, Template)]
+#[template(path = "logs.html")]
+pub struct LogsTemplate {
+    pub title: String,
+    pub container: String,
+    pub accent_color: String,
+    pub theme: String,
+    pub lang: String,
+    pub t: Arc<Messages>,
+}
+
+#[derive(Clone, Debug, Template)]
+#[template(path = "stats.html")]
+pub struct StatsTemplate {
+    pub title: String,
+    pub accent_color: String,
+    pub theme: String,
+    pub lang: String,
+    pub t: Arc<Messages>,
+}
+
+#[derive(Clone, Debug, Template)]
+#[template(path = "login.html")]
+pub struct LoginTemplate {
+    pub title: String,
+    pub csrf_token: String,
+    /// The previous attempt failed
+    pub failed: bool,
+    /// Too many attempts failed
+    pub locked: bool,
+    /// Offer signing in through the OpenID Connect provider
+    pub oidc: bool,
+    pub lang: String,
+    pub t: Arc<Messages>,
+}
+
+#[derive(Clone, Debug, Template)]
+#[template(path = "api_docs.html")]
+pub struct ApiDocsTemplate {
+    pub csrf_token: String,
+}
+
+#[derive(Clone, Debug, Template)]
+#[template(path = "link.html")]
+pub struct LinkTemplate {
+    pub container: String,
+    pub action: String,
+    pub token: String,
+    /// Outcome or why the link can't be used; the form is shown when empty
+    pub message: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -118,789 +624,10336 @@ pub struct ContainerName {
     pub name: String,
 }
 
-// ---------------------------------------------------------------------------
-// JSON error helper
-// ---------------------------------------------------------------------------
+#[derive(Debug, Deserialize)]
+pub struct ActionQuery {
+    pub name: String,
+    /// State to wait for before answering: `exited`, `running` or `healthy`.
+    pub wait_for: Option<String>,
+    /// Seconds to wait for it.
+    pub timeout: Option<u64>,
+}
 
-fn json_error(status: StatusCode, msg: &str) -> (StatusCode, Json<serde_json::Value>) {
-    (status, Json(serde_json::json!({ "error": msg })))
+#[derive(Debug, Default, Deserialize)]
+pub struct ListQuery {
+    /// Only containers in this state, e.g. `running`.
+    pub state: Option<String>,
+    /// Only containers whose name contains this, ignoring case.
+    pub q: Option<String>,
+    /// `name` (default) or `status`.
+    pub sort: Option<String>,
+    /// Page to return, starting at 1. Without it every match is returned.
+    pub page: Option<usize>,
+    /// Containers per page (default 50, at most 500).
+    pub per_page: Option<usize>,
 }
 
-// ---------------------------------------------------------------------------
-// Container data collection
-// ---------------------------------------------------------------------------
+#[derive(Debug, Deserialize)]
+pub struct KillQuery {
+    pub name: String,
+    pub signal: Option<String>,
+}
 
-fn common_filters() -> HashMap<String, Vec<String>> {
-    let mut filters = HashMap::new();
-    filters.insert(
-        "health".to_string(),
-        vec![
-            "starting".to_string(),
-            "healthy".to_string(),
-            "unhealthy".to_string(),
-            "none".to_string(),
-        ],
-    );
-    filters.insert(
-        "status".to_string(),
-        vec![
-            "created".to_string(),
-            "restarting".to_string(),
-            "running".to_string(),
-            "removing".to_string(),
-            "paused".to_string(),
-            "exited".to_string(),
-            "dead".to_string(),
-        ],
-    );
-    filters
+#[derive(Debug, Deserialize)]
+pub struct LogsQuery {
+    pub name: String,
+    /// Number of lines from the end of the log, or `all`.
+    pub tail: Option<String>,
+    /// Unix timestamp (seconds) or RFC3339 time to start from.
+    pub since: Option<String>,
+    /// `text` (default) or `json` for JSON lines.
+    pub format: Option<String>,
 }
 
-fn format_uptime(started_at: &str) -> String {
-    let Ok(start) = chrono_parse(started_at) else {
-        return String::new();
-    };
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs() as i64;
-    let diff = now - start;
-    if diff < 0 {
-        return String::new();
-    }
-    let days = diff / 86400;
-    let hours = (diff % 86400) / 3600;
-    let mins = (diff % 3600) / 60;
-    if days > 0 {
-        format!("{}d {}h {}m", days, hours, mins)
-    } else if hours > 0 {
-        format!("{}h {}m", hours, mins)
-    } else {
-        format!("{}m", mins)
-    }
+#[derive(Debug, Deserialize)]
+pub struct RestartPolicyQuery {
+    pub name: String,
+    pub policy: String,
+    pub max_retries: Option<i64>,
 }
 
-/// Minimal RFC3339 parser — returns Unix timestamp (seconds).
-fn chrono_parse(s: &str) -> Result<i64, ()> {
-    // Expected format: 2024-01-15T10:30:00.123456789Z (or +00:00 offset)
-    let s = s.trim();
-    if s.len() < 19 {
-        return Err(());
-    }
-    let year: i64 = s[0..4].parse().map_err(|_| ())?;
-    let month: i64 = s[5..7].parse().map_err(|_| ())?;
-    let day: i64 = s[8..10].parse().map_err(|_| ())?;
-    let hour: i64 = s[11..13].parse().map_err(|_| ())?;
-    let min: i64 = s[14..16].parse().map_err(|_| ())?;
-    let sec: i64 = s[17..19].parse().map_err(|_| ())?;
+#[derive(Debug, Deserialize)]
+pub struct ExecQuery {
+    pub name: String,
+    pub command: String,
+}
 
-    // Rough days-since-epoch (good enough for uptime display)
-    fn days_from_year(y: i64) -> i64 {
-        365 * y + y / 4 - y / 100 + y / 400
-    }
-    let month_days: [i64; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
-    let is_leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
-    let leap_adj = if month > 2 && is_leap { 1 } else { 0 };
-    let epoch_days = days_from_year(year - 1) - days_from_year(1969)
-        + month_days[(month - 1) as usize]
-        + day
-        - 1
-        + leap_adj;
-    Ok(epoch_days * 86400 + hour * 3600 + min * 60 + sec)
+#[derive(Debug, Deserialize)]
+pub struct HealthQuery {
+    pub name: String,
+    /// Number of most recent probe results to return.
+    pub limit: Option<usize>,
 }
 
-fn extract_ports(inspect: &bollard::models::ContainerInspectResponse) -> Vec<PortMapping> {
-    let mut ports = Vec::new();
-    let bindings = inspect
-        .host_config
-        .as_ref()
-        .and_then(|hc| hc.port_bindings.as_ref());
+#[derive(Debug, Deserialize)]
+pub struct ResourcesQuery {
+    pub name: String,
+    pub cpu_shares: Option<isize>,
+    pub memory_mb: Option<i64>,
+}
 
-    if let Some(bindings) = bindings {
-        for (container_spec, host_bindings) in bindings {
-            // container_spec is like "25565/tcp"
-            let parts: Vec<&str> = container_spec.split('/').collect();
-            let container_port: u16 = parts.first().and_then(|p| p.parse().ok()).unwrap_or(0);
-            let protocol = parts.get(1).unwrap_or(&"tcp").to_string();
+#[derive(Debug, Deserialize)]
+pub struct DownloadQuery {
+    pub name: String,
+    /// Name of a path configured in the download file.
+    pub file: String,
+}
 
-            if let Some(Some(bindings_vec)) = host_bindings.as_ref().map(Some) {
-                for binding in bindings_vec {
-                    let host_port: u16 = binding
-                        .host_port
-                        .as_ref()
-                        .and_then(|p| p.parse().ok())
-                        .unwrap_or(0);
-                    if host_port > 0 && container_port > 0 {
-                        ports.push(PortMapping {
-                            host_port,
-                            container_port,
-                            protocol: protocol.clone(),
-                        });
-                    }
-                }
-            }
-        }
-    }
-    ports
+#[derive(Debug, Deserialize)]
+pub struct WaitQuery {
+    pub name: String,
+    /// `exited`, `running` or `healthy`.
+    pub state: String,
+    /// Seconds to wait before giving up.
+    pub timeout: Option<u64>,
 }
 
-fn find_minecraft_host_port(ports: &[PortMapping]) -> Option<u16> {
-    ports
-        .iter()
-        .find(|p| p.container_port == 25565)
-        .map(|p| p.host_port)
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    pub container: Option<String>,
+    pub limit: Option<usize>,
 }
 
-async fn ping_minecraft(host_port: u16, seed: &str) -> Option<MinecraftInfo> {
-    let result = tokio::time::timeout(std::time::Duration::from_secs(3), async {
-        let mut stream =
-            tokio::net::TcpStream::connect(format!("127.0.0.1:{}", host_port)).await?;
+#[derive(Debug, Deserialize)]
+pub struct UpdateQuery {
+    pub name: String,
+    /// Recreate even when the pulled image is the one already in use.
+    pub force: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VolumeBackupQuery {
+    pub name: String,
+    pub volume: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NetworkQuery {
+    pub name: String,
+    pub network: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TeardownQuery {
+    pub name: String,
+    /// Also remove the stack's volumes
+    pub volumes: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScaleQuery {
+    pub name: String,
+    pub replicas: u64,
+}
+
+/// Body of `POST /admin/allowlist`.
+#[derive(Debug, Deserialize)]
+struct AllowlistChange {
+    #[serde(default)]
+    add: Vec<String>,
+    #[serde(default)]
+    remove: Vec<String>,
+    /// Also write the change back to the file the allowlist was read from
+    #[serde(default)]
+    persist: bool,
+}
+
+/// One item of the body of `POST /containers/batch`.
+#[derive(Debug, Deserialize)]
+struct BatchItem {
+    name: String,
+    action: String,
+}
+
+/// Signals accepted by the kill endpoint.
+const ALLOWED_SIGNALS: [&str; 3] = ["SIGTERM", "SIGKILL", "SIGHUP"];
+
+/// Actions that can be permitted per container. `pause` covers unpause,
+/// `inspect` the read-only detail endpoints, `exec` listing and running
+/// commands, `download` downloads and exports, and `update` pulling the image.
+const CONTAINER_ACTIONS: [&str; 16] = [
+    "start",
+    "stop",
+    "restart",
+    "pause",
+    "kill",
+    "recreate",
+    "update",
+    "restart-policy",
+    "resources",
+    "exec",
+    "download",
+    "backup",
+    "network",
+    "logs",
+    "stats",
+    "inspect",
+];
+
+const ACTION_NOT_PERMITTED: &str = "Action not permitted for this container";
+
+// ---------------------------------------------------------------------------
+// JSON errors
+// ---------------------------------------------------------------------------
+
+/// Body of every error response. `code` is for clients to match on and
+/// doesn't change between releases; `message` is for people.
+#[derive(Clone, Debug, Serialize)]
+pub struct ApiError {
+    pub code: &'static str,
+    pub message: String,
+    /// The container the request was about
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+}
+
+/// Every `code` an error may have, as listed in the README. `error_code`
+/// picks one by status; some statuses also stand for more particular ones,
+/// such as `busy` for a `503` that isn't about Docker.
+const ERROR_CODES: [&str; 15] = [
+    "invalid_request",
+    "unauthorized",
+    "forbidden",
+    "not_found",
+    "method_not_allowed",
+    "conflict",
+    "dependency_failed",
+    "timeout",
+    "too_many_requests",
+    "locked_out",
+    "busy",
+    "docker_error",
+    "docker_unavailable",
+    "upstream_error",
+    "internal_error",
+];
+
+fn error_code(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "invalid_request",
+        StatusCode::UNAUTHORIZED => "unauthorized",
+        StatusCode::FORBIDDEN => "forbidden",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+        StatusCode::REQUEST_TIMEOUT | StatusCode::GATEWAY_TIMEOUT => "timeout",
+        StatusCode::CONFLICT => "conflict",
+        StatusCode::FAILED_DEPENDENCY => "dependency_failed",
+        StatusCode::TOO_MANY_REQUESTS => "too_many_requests",
+        StatusCode::BAD_GATEWAY => "upstream_error",
+        StatusCode::SERVICE_UNAVAILABLE => "docker_unavailable",
+        _ => "internal_error",
+    }
+}
+
+fn error_body(
+    code: &'static str,
+    message: &str,
+    container: Option<&str>,
+) -> Json<serde_json::Value> {
+    debug_assert!(ERROR_CODES.contains(&code), "Unknown error code '{}'", code);
+    let error = ApiError {
+        code,
+        message: message.to_string(),
+        container: container.map(str::to_string),
+    };
+    Json(serde_json::to_value(error).unwrap_or_default())
+}
+
+fn json_error(status: StatusCode, msg: &str) -> (StatusCode, Json<serde_json::Value>) {
+    (status, error_body(error_code(status), msg, None))
+}
+
+/// `axum::extract::Query`, answering missing or malformed parameters with an
+/// `invalid_request` error like every other error instead of plain text.
+struct Query<T>(T);
+
+impl<T, S> axum::extract::FromRequestParts<S> for Query<T>
+where
+    T: serde::de::DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, Json<serde_json::Value>);
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        axum::extract::Query::from_request_parts(parts, state)
+            .await
+            .map(|axum::extract::Query(value)| Query(value))
+            .map_err(|rejection| {
+                (
+                    rejection.status(),
+                    error_body("invalid_request", &rejection.body_text(), None),
+                )
+            })
+    }
+}
+
+/// `axum::Form`, answering malformed form data like `Query` does.
+struct Form<T>(T);
+
+impl<T, S> axum::extract::FromRequest<S> for Form<T>
+where
+    T: serde::de::DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, Json<serde_json::Value>);
+
+    async fn from_request(
+        request: axum::extract::Request,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        axum::Form::from_request(request, state)
+            .await
+            .map(|axum::Form(value)| Form(value))
+            .map_err(|rejection| {
+                (
+                    rejection.status(),
+                    error_body("invalid_request", &rejection.body_text(), None),
+                )
+            })
+    }
+}
+
+/// An error about one container, naming it in the response.
+fn container_error(
+    status: StatusCode,
+    msg: &str,
+    container: &str,
+) -> (StatusCode, Json<serde_json::Value>) {
+    (status, error_body(error_code(status), msg, Some(container)))
+}
+
+/// An error returned by the Docker API, answered with the status it stands
+/// for: `404` for a container Docker doesn't know, `409` for conflicts and
+/// `503` when Docker can't be reached, rather than always `500`.
+fn docker_error(
+    e: &bollard::errors::Error,
+    context: &str,
+    container: Option<&str>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    use bollard::errors::Error;
+
+    let (status, code, message) = match e {
+        Error::DockerResponseServerError {
+            status_code,
+            message,
+        } => {
+            let (status, code) = match status_code {
+                400 => (StatusCode::BAD_REQUEST, "invalid_request"),
+                404 => (StatusCode::NOT_FOUND, "not_found"),
+                409 => (StatusCode::CONFLICT, "conflict"),
+                _ => (StatusCode::BAD_GATEWAY, "docker_error"),
+            };
+            (status, code, message.clone())
+        }
+        Error::RequestTimeoutError => (
+            StatusCode::GATEWAY_TIMEOUT,
+            "timeout",
+            "Docker did not answer in time".to_string(),
+        ),
+        Error::IOError { .. } | Error::HyperLegacyError { .. } | Error::SocketNotFoundError(_) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "docker_unavailable",
+            e.to_string(),
+        ),
+        _ => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            e.to_string(),
+        ),
+    };
+    (
+        status,
+        error_body(code, &format!("{}: {}", context, message), container),
+    )
+}
+
+/// Whether `name` is on the allowlist and, when a request is being handled,
+/// whether its caller has been granted access to it.
+fn is_container_allowed(state: &AppState, name: &str) -> bool {
+    current_policy(state)
+        .allowed_containers
+        .iter()
+        .any(|c| c == name)
+        && caller_may_access(state, name)
+}
+
+/// Whether an allowed container permits `action`.
+fn is_action_permitted(state: &AppState, name: &str, action: &str) -> bool {
+    current_policy(state)
+        .permitted_actions
+        .get(name)
+        .is_none_or(|actions| actions.iter().any(|a| a == action))
+}
+
+fn current_policy(state: &AppState) -> Arc<Policy> {
+    Arc::clone(&state.policy.read().expect("Policy lock poisoned"))
+}
+
+/// Swap in a copy of `based_on` with a new effective allowlist. Does nothing
+/// if the policy was replaced in the meantime, so a concurrent reload or
+/// allowlist change is not undone.
+fn update_allowed_containers(
+    state: &AppState,
+    based_on: &Arc<Policy>,
+    allowed: Vec<String>,
+) -> Arc<Policy> {
+    let mut guard = state.policy.write().expect("Policy lock poisoned");
+    if Arc::ptr_eq(&guard, based_on) {
+        let mut policy = Policy::clone(&guard);
+        policy.allowed_containers = allowed;
+        *guard = Arc::new(policy);
+    }
+    Arc::clone(&guard)
+}
+
+/// Whether the request carries `Authorization: Bearer <admin token>`, which
+/// grants the admin role. Never true when no admin token is configured.
+fn is_admin(state: &AppState, headers: &axum::http::HeaderMap) -> bool {
+    let Some(expected) = state.admin_token.as_deref() else {
+        return false;
+    };
+    headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|token| constant_time_eq(token.as_bytes(), expected.as_bytes()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// ---------------------------------------------------------------------------
+// Client address restrictions
+// ---------------------------------------------------------------------------
+
+/// Parse `--allowed-clients` entries: CIDR networks like `192.168.1.0/24`, or
+/// single addresses.
+fn parse_client_networks(entries: &[String]) -> Result<Vec<ipnet::IpNet>> {
+    entries
+        .iter()
+        .map(|entry| {
+            entry
+                .parse::<ipnet::IpNet>()
+                .or_else(|_| entry.parse::<std::net::IpAddr>().map(ipnet::IpNet::from))
+                .map_err(|_| anyhow::anyhow!("Invalid client address or network '{}'", entry))
+        })
+        .collect()
+}
+
+/// The address of the client behind `peer`: when `peer` is a trusted proxy,
+/// the last address in `X-Forwarded-For` that isn't a trusted proxy itself,
+/// or else `X-Real-IP`.
+fn forwarded_client(
+    proxies: &[ipnet::IpNet],
+    peer: std::net::IpAddr,
+    headers: &axum::http::HeaderMap,
+) -> std::net::IpAddr {
+    let trusted = |ip: &std::net::IpAddr| proxies.iter().any(|n| n.contains(&ip.to_canonical()));
+    if !trusted(&peer) {
+        return peer;
+    }
+
+    // Each proxy appends the address it received the request from, so read
+    // from the right and stop at the first one not added by a trusted proxy
+    let forwarded: Vec<std::net::IpAddr> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|ip| ip.trim().parse().ok())
+        .collect();
+    if let Some(client) = forwarded.iter().rev().find(|ip| !trusted(ip)) {
+        return *client;
+    }
+    if let Some(first) = forwarded.first() {
+        return *first;
+    }
+    headers
+        .get("x-real-ip")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(peer)
+}
+
+/// Marks requests that came through a trusted proxy.
+#[derive(Clone, Copy, Debug)]
+struct TrustedProxy;
+
+/// Middleware replacing the connection's address with the client's when the
+/// request came through a trusted proxy, so every later check, log line and
+/// audit entry sees the real client.
+async fn resolve_forwarded_client(
+    State(state): State<SharedState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    mut request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let peer_ip = peer.ip().to_canonical();
+    if state.trusted_proxies.iter().any(|n| n.contains(&peer_ip)) {
+        request.extensions_mut().insert(TrustedProxy);
+        let client = forwarded_client(&state.trusted_proxies, peer.ip(), request.headers());
+        if client != peer.ip() {
+            request
+                .extensions_mut()
+                .insert(ConnectInfo(SocketAddr::new(client, peer.port())));
+        }
+    }
+    next.run(request).await
+}
+
+/// Middleware rejecting clients outside `allowed_clients` before anything
+/// else, authentication included, looks at the request.
+async fn restrict_clients(
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    // Listening on `::` reports IPv4 clients as IPv4-mapped IPv6 addresses
+    let ip = addr.ip().to_canonical();
+    if !state.allowed_clients.is_empty() && !state.allowed_clients.iter().any(|n| n.contains(&ip)) {
+        tracing::warn!(
+            "Request for {} from {} rejected: address not allowed",
+            request.uri(),
+            ip
+        );
+        return json_error(StatusCode::FORBIDDEN, "Client address not allowed").into_response();
+    }
+    next.run(request).await
+}
+
+// ---------------------------------------------------------------------------
+// Cross-site request forgery protection
+// ---------------------------------------------------------------------------
+
+const CSRF_HEADER: &str = "x-csrf-token";
+/// Holds a random value the login form's token is derived from, as there is
+/// no session yet to tie it to.
+const LOGIN_COOKIE: &str = "docker_direct_login";
+
+fn csrf_hmac(state: &AppState, binding: &str) -> String {
+    use hmac::Mac;
+
+    let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(state.csrf_secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(binding.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The token a page must send back with changes: derived from the session
+/// cookie, or from the caller when signed in some other way, so a token one
+/// session can read is worthless to every other.
+fn csrf_token(state: &AppState, headers: &axum::http::HeaderMap) -> String {
+    let binding = match (cookie_value(headers, SESSION_COOKIE), current_caller()) {
+        (Some(session), _) => format!("session:{}", session),
+        (None, Some(caller)) => format!("caller:{}", identity_name(&caller)),
+        (None, None) => "anonymous".to_string(),
+    };
+    csrf_hmac(state, &binding)
+}
+
+/// The login form's token, for the value in its `LOGIN_COOKIE`.
+fn login_csrf_token(state: &AppState, login: &str) -> String {
+    csrf_hmac(state, &format!("login:{}", login))
+}
+
+/// Middleware rejecting changes that a browser sends on behalf of another
+/// site. Changes are only accepted as `POST` (or other non-`GET`) requests.
+/// Browsers mark those with `Origin` and `Sec-Fetch-Site`; they must come
+/// from this site and carry the token embedded in the web UI. Scripts send
+/// neither header and are not affected.
+async fn protect_from_csrf(
+    State(state): State<SharedState>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let method = request.method();
+    if method == axum::http::Method::GET || method == axum::http::Method::HEAD {
+        return next.run(request).await;
+    }
+
+    let headers = request.headers();
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let fetch_site = header("sec-fetch-site");
+    let origin = header("origin");
+    if fetch_site.is_none() && origin.is_none() {
+        return next.run(request).await;
+    }
+    // Sites listed in `--cors-origins` call the API the way scripts do
+    if origin.is_some_and(|o| state.cors_origins.iter().any(|allowed| allowed == o)) {
+        return next.run(request).await;
+    }
+
+    let same_site = match fetch_site {
+        Some(site) => site == "same-origin",
+        None => {
+            origin
+                .and_then(|o| o.split_once("://"))
+                .map(|(_, host)| host)
+                == header("host")
+        }
+    };
+    // The login and sign-out forms send their token in the body, where their
+    // handlers check it, and action links are secrets themselves
+    let token = header(CSRF_HEADER).unwrap_or("");
+    let token_valid = ["/login", "/logout", "/link"].contains(&request.uri().path())
+        || constant_time_eq(token.as_bytes(), csrf_token(&state, headers).as_bytes());
+    if !same_site || !token_valid {
+        tracing::warn!(
+            "Cross-site request for {} rejected (origin {})",
+            request.uri().path(),
+            origin.unwrap_or("unknown")
+        );
+        return json_error(StatusCode::FORBIDDEN, "Cross-site request rejected").into_response();
+    }
+    next.run(request).await
+}
+
+/// Whether a WebSocket may be opened from the page that asked. Browsers send
+/// the signed-in user's cookies along with any site's upgrade request and
+/// WebSockets aren't subject to CORS, so only this site and the sites in
+/// `--cors-origins` may open them. Scripts send no `Origin` and may.
+fn websocket_origin_allowed(state: &AppState, headers: &axum::http::HeaderMap) -> bool {
+    let Some(origin) = headers.get(axum::http::header::ORIGIN) else {
+        return true;
+    };
+    let Ok(origin) = origin.to_str() else {
+        return false;
+    };
+    let host = headers
+        .get(axum::http::header::HOST)
+        .and_then(|v| v.to_str().ok());
+    origin.split_once("://").map(|(_, host)| host) == host
+        || state.cors_origins.iter().any(|allowed| allowed == origin)
+}
+
+/// The answer to a WebSocket upgrade from a site that may not open one.
+fn websocket_origin_rejected(headers: &axum::http::HeaderMap) -> axum::response::Response {
+    let origin = headers
+        .get(axum::http::header::ORIGIN)
+        .and_then(|v| v.to_str().ok());
+    tracing::warn!(
+        "Cross-site WebSocket rejected (origin {})",
+        origin.unwrap_or("unknown")
+    );
+    json_error(StatusCode::FORBIDDEN, "Cross-site request rejected").into_response()
+}
+
+// ---------------------------------------------------------------------------
+// Cross-origin requests
+// ---------------------------------------------------------------------------
+
+/// Methods and request headers other sites may use when none are configured.
+const DEFAULT_CORS_METHODS: [&str; 3] = ["GET", "POST", "PUT"];
+const DEFAULT_CORS_HEADERS: [&str; 4] = [
+    "authorization",
+    "content-type",
+    "x-api-key",
+    "idempotency-key",
+];
+
+/// Response headers scripts on other sites may read.
+const CORS_EXPOSED_HEADERS: [&str; 4] = ["etag", "x-total-count", "retry-after", "deprecation"];
+
+/// CORS policy letting `origins` call the API from the browser, or `None`
+/// when no origins are configured. `*` allows every site, but only to read:
+/// changes from a site that isn't listed by name still fail the CSRF check.
+fn cors_layer(
+    origins: &[String],
+    methods: &[String],
+    headers: &[String],
+) -> Result<Option<tower_http::cors::CorsLayer>> {
+    use tower_http::cors::{AllowOrigin, CorsLayer};
+
+    if origins.is_empty() {
+        return Ok(None);
+    }
+    let allow_origin = if origins.iter().any(|o| o == "*") {
+        AllowOrigin::any()
+    } else {
+        let origins = origins
+            .iter()
+            .map(|o| {
+                axum::http::HeaderValue::from_str(o.trim_end_matches('/'))
+                    .map_err(|_| anyhow::anyhow!("Invalid CORS origin '{}'", o))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        AllowOrigin::list(origins)
+    };
+
+    let methods = if methods.is_empty() {
+        DEFAULT_CORS_METHODS.iter().map(|m| m.to_string()).collect()
+    } else {
+        methods.to_vec()
+    };
+    let methods = methods
+        .iter()
+        .map(|m| {
+            axum::http::Method::from_bytes(m.to_uppercase().as_bytes())
+                .map_err(|_| anyhow::anyhow!("Invalid CORS method '{}'", m))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let headers = if headers.is_empty() {
+        DEFAULT_CORS_HEADERS.iter().map(|h| h.to_string()).collect()
+    } else {
+        headers.to_vec()
+    };
+    let headers = headers
+        .iter()
+        .map(|h| {
+            axum::http::HeaderName::from_bytes(h.as_bytes())
+                .map_err(|_| anyhow::anyhow!("Invalid CORS header '{}'", h))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .expose_headers(CORS_EXPOSED_HEADERS.map(axum::http::HeaderName::from_static))
+            .max_age(std::time::Duration::from_secs(3600)),
+    ))
+}
+
+// ---------------------------------------------------------------------------
+// Audit log
+// ---------------------------------------------------------------------------
+
+async fn open_audit_log(path: &str) -> Result<tokio::fs::File> {
+    tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to open audit log '{}': {}", path, e))
+}
+
+/// Middleware appending a record of every request that changes something to
+/// the audit log, after it has been handled.
+async fn audit_actions(
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    if state.audit_log.is_none() {
+        return next.run(request).await;
+    }
+    let method = request.method();
+    // Sign-ins and action links are recorded by their handlers, which know
+    // the user name
+    if method == axum::http::Method::GET
+        || method == axum::http::Method::HEAD
+        || ["/login", "/link"].contains(&request.uri().path())
+    {
+        return next.run(request).await;
+    }
+
+    let user = request
+        .extensions()
+        .get::<Identity>()
+        .map(|i| identity_name(i).to_string());
+    let action = request.uri().path().to_string();
+    let container = axum::extract::Query::<HashMap<String, String>>::try_from_uri(request.uri())
+        .ok()
+        .and_then(|axum::extract::Query(mut params)| params.remove("name"));
+    let response = next.run(request).await;
+
+    let status = response.status();
+    let result = if status.is_success() { "ok" } else { "error" };
+    let client = addr.ip().to_canonical().to_string();
+    append_audit(&state, client, user, action, container, status, result).await;
+    response
+}
+
+/// Append an entry to the audit log, if there is one.
+async fn append_audit(
+    state: &AppState,
+    client: String,
+    user: Option<String>,
+    action: String,
+    container: Option<String>,
+    status: StatusCode,
+    result: &str,
+) {
+    use tokio::io::AsyncWriteExt;
+
+    let Some((_, file)) = &state.audit_log else {
+        return;
+    };
+    let entry = AuditEntry {
+        time: unix_now(),
+        client,
+        user,
+        action,
+        container,
+        status: status.as_u16(),
+        result: result.to_string(),
+    };
+    let mut line = serde_json::to_string(&entry).unwrap_or_default();
+    line.push('\n');
+    let mut file = file.lock().await;
+    if let Err(e) = file.write_all(line.as_bytes()).await {
+        tracing::error!("Failed to write audit log: {}", e);
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    container: Option<String>,
+    user: Option<String>,
+    /// Unix timestamp; only entries at or after it
+    since: Option<i64>,
+    limit: Option<usize>,
+}
+
+/// The most recent audit log entries matching the query, oldest first.
+async fn audit_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<AuditQuery>,
+) -> axum::response::Response {
+    let Some((path, _)) = &state.audit_log else {
+        return json_error(StatusCode::NOT_FOUND, "Audit log not configured").into_response();
+    };
+    let content = match tokio::fs::read_to_string(path).await {
+        Ok(content) => content,
+        Err(e) => {
+            tracing::error!("Failed to read audit log '{}': {}", path, e);
+            return json_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to read audit log",
+            )
+            .into_response();
+        }
+    };
+
+    let mut entries: Vec<AuditEntry> = content
+        .lines()
+        .rev()
+        .filter_map(|line| serde_json::from_str::<AuditEntry>(line).ok())
+        .filter(|e| {
+            query
+                .container
+                .as_ref()
+                .is_none_or(|c| e.container.as_ref() == Some(c))
+        })
+        .filter(|e| {
+            query
+                .user
+                .as_ref()
+                .is_none_or(|u| e.user.as_ref() == Some(u))
+        })
+        .filter(|e| query.since.is_none_or(|since| e.time >= since))
+        .take(query.limit.unwrap_or(100))
+        .collect();
+    entries.reverse();
+    Json(entries).into_response()
+}
+
+// ---------------------------------------------------------------------------
+// Rate limiting
+// ---------------------------------------------------------------------------
+
+/// A client's allowance of action requests, refilled continuously up to
+/// `rate_limit` per minute.
+#[derive(Debug)]
+struct RateBucket {
+    tokens: f64,
+    updated: std::time::Instant,
+}
+
+/// Middleware limiting how often each client may call routes that change
+/// containers, so a misbehaving script can't hammer the Docker daemon.
+/// Clients are told apart by user or API key, or else by address. Read-only
+/// routes are not limited.
+async fn rate_limit_actions(
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    if state.rate_limit == 0 || required_role(request.uri().path()) == Role::Viewer {
+        return next.run(request).await;
+    }
+
+    let client = match request.extensions().get::<Identity>() {
+        Some(identity) => identity_name(identity).to_string(),
+        None => addr.ip().to_canonical().to_string(),
+    };
+    let capacity = state.rate_limit as f64;
+    let per_second = capacity / 60.0;
+    let now = std::time::Instant::now();
+    let wait = {
+        let mut buckets = state.rate_buckets.lock().expect("Rate limit lock poisoned");
+        // Buckets that have refilled completely are the same as new ones
+        if buckets.len() > 1024 {
+            buckets.retain(|_, b| {
+                b.tokens + now.duration_since(b.updated).as_secs_f64() * per_second < capacity
+            });
+        }
+        let bucket = buckets.entry(client.clone()).or_insert(RateBucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let refill = now.duration_since(bucket.updated).as_secs_f64() * per_second;
+        bucket.tokens = (bucket.tokens + refill).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            None
+        } else {
+            Some(((1.0 - bucket.tokens) / per_second).ceil() as u64)
+        }
+    };
+
+    if let Some(wait) = wait {
+        tracing::warn!(
+            "Rate limit exceeded by {} for {}",
+            client,
+            request.uri().path()
+        );
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(axum::http::header::RETRY_AFTER, wait.to_string())],
+            error_body("too_many_requests", "Too many requests", None),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+// ---------------------------------------------------------------------------
+// Idempotency keys
+// ---------------------------------------------------------------------------
+
+const IDEMPOTENCY_HEADER: &str = "idempotency-key";
+
+/// How long the response to a request with an `Idempotency-Key` is kept.
+const IDEMPOTENCY_WINDOW: std::time::Duration = std::time::Duration::from_secs(24 * 3600);
+
+/// Largest response body that is kept for replaying; larger ones are not.
+const MAX_IDEMPOTENT_BODY: usize = 64 * 1024;
+
+/// Largest request body a request with an `Idempotency-Key` may have, the
+/// same as axum's default limit for the routes reading a body.
+const MAX_IDEMPOTENT_REQUEST: usize = 2 * 1024 * 1024;
+
+/// A request seen with an `Idempotency-Key`, and its response once it has one.
+#[derive(Debug)]
+struct IdempotentRequest {
+    /// Method, path, query and a hash of the body, so a key reused for
+    /// another request is caught
+    fingerprint: String,
+    expires: std::time::Instant,
+    response: Option<(
+        StatusCode,
+        Option<axum::http::HeaderValue>,
+        axum::body::Bytes,
+    )>,
+}
+
+/// Forgets a request that is still in progress when dropped: when it ended
+/// without a response worth keeping, or the client went away and the
+/// request was cancelled. A retry then acts again rather than being told
+/// the request is still in progress until the key expires.
+struct PendingIdempotent<'a> {
+    state: &'a AppState,
+    key: String,
+}
+
+impl Drop for PendingIdempotent<'_> {
+    fn drop(&mut self) {
+        let Ok(mut requests) = self.state.idempotent_requests.lock() else {
+            return;
+        };
+        if requests
+            .get(&self.key)
+            .is_some_and(|r| r.response.is_none())
+        {
+            requests.remove(&self.key);
+        }
+    }
+}
+
+/// Middleware answering a change retried with the same `Idempotency-Key`
+/// with the response to the first attempt instead of acting again, so a
+/// flaky connection can't restart a container twice. Keys belong to the
+/// user or API key, or else the client address, that sent them.
+async fn replay_idempotent(
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let method = request.method();
+    if method == axum::http::Method::GET || method == axum::http::Method::HEAD {
+        return next.run(request).await;
+    }
+    let Some(key) = request.headers().get(IDEMPOTENCY_HEADER) else {
+        return next.run(request).await;
+    };
+    let Some(key) = key
+        .to_str()
+        .ok()
+        .filter(|k| !k.is_empty() && k.len() <= 255)
+    else {
+        return json_error(StatusCode::BAD_REQUEST, "Invalid Idempotency-Key").into_response();
+    };
+
+    let client = match request.extensions().get::<Identity>() {
+        Some(identity) => identity_name(identity).to_string(),
+        None => addr.ip().to_canonical().to_string(),
+    };
+    let key = format!("{}\n{}", client, key);
+
+    let (parts, body) = request.into_parts();
+    let body = match axum::body::to_bytes(body, MAX_IDEMPOTENT_REQUEST).await {
+        Ok(body) => body,
+        Err(_) => {
+            return (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                error_body("invalid_request", "Request body too large", None),
+            )
+                .into_response();
+        }
+    };
+    let fingerprint = {
+        use sha2::Digest;
+
+        let hash: String = sha2::Sha256::digest(&body)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        format!("{} {} {}", parts.method, parts.uri, hash)
+    };
+    let request = axum::extract::Request::from_parts(parts, axum::body::Body::from(body));
+    let now = std::time::Instant::now();
+    {
+        let mut requests = state
+            .idempotent_requests
+            .lock()
+            .expect("Idempotency lock poisoned");
+        requests.retain(|_, r| r.expires > now);
+        match requests.get(&key) {
+            Some(seen) if seen.fingerprint != fingerprint => {
+                return (
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    error_body(
+                        "invalid_request",
+                        "Idempotency-Key was already used for a different request",
+                        None,
+                    ),
+                )
+                    .into_response();
+            }
+            Some(IdempotentRequest {
+                response: Some((status, content_type, body)),
+                ..
+            }) => {
+                tracing::info!("Replaying response to {} for {}", fingerprint, client);
+                let mut response = (*status, body.clone()).into_response();
+                if let Some(content_type) = content_type {
+                    response
+                        .headers_mut()
+                        .insert(axum::http::header::CONTENT_TYPE, content_type.clone());
+                }
+                response.headers_mut().insert(
+                    axum::http::HeaderName::from_static("idempotent-replayed"),
+                    axum::http::HeaderValue::from_static("true"),
+                );
+                return response;
+            }
+            Some(_) => {
+                return json_error(
+                    StatusCode::CONFLICT,
+                    "A request with this Idempotency-Key is still in progress",
+                )
+                .into_response();
+            }
+            None => {
+                requests.insert(
+                    key.clone(),
+                    IdempotentRequest {
+                        fingerprint,
+                        expires: now + IDEMPOTENCY_WINDOW,
+                        response: None,
+                    },
+                );
+            }
+        }
+    }
+    let _pending = PendingIdempotent {
+        state: &state,
+        key: key.clone(),
+    };
+
+    let response = next.run(request).await;
+    let status = response.status();
+    let size = axum::body::HttpBody::size_hint(response.body()).upper();
+    // Retries should act again after Docker was unreachable or the rate limit
+    // hit, and streams or large bodies aren't worth keeping
+    if status.is_server_error()
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || size.is_none_or(|size| size > MAX_IDEMPOTENT_BODY as u64)
+    {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match axum::body::to_bytes(body, MAX_IDEMPOTENT_BODY).await {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!("Failed to read response to keep it: {}", e);
+            return json_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read response")
+                .into_response();
+        }
+    };
+    if let Some(seen) = state
+        .idempotent_requests
+        .lock()
+        .expect("Idempotency lock poisoned")
+        .get_mut(&key)
+    {
+        let content_type = parts.headers.get(axum::http::header::CONTENT_TYPE).cloned();
+        seen.response = Some((status, content_type, body.clone()));
+    }
+    axum::response::Response::from_parts(parts, axum::body::Body::from(body))
+}
+
+// ---------------------------------------------------------------------------
+// Request limits
+// ---------------------------------------------------------------------------
+
+/// Middleware bounding how long a request may take and how many are handled
+/// at once, so a hung Docker daemon can't pile up requests without end.
+/// Streamed responses such as live logs only count until they start.
+async fn limit_requests(
+    State(state): State<SharedState>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let path = request.uri().path().to_string();
+    let _permit = match &state.request_permits {
+        Some(permits) => match permits.clone().try_acquire_owned() {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                tracing::warn!("Too many requests in progress, rejecting {}", path);
+                return (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    [(axum::http::header::RETRY_AFTER, "1")],
+                    error_body("busy", "Too many requests in progress", None),
+                )
+                    .into_response();
+            }
+        },
+        None => None,
+    };
+
+    if state.request_timeout == 0 {
+        return next.run(request).await;
+    }
+    let limit = std::time::Duration::from_secs(state.request_timeout);
+    match tokio::time::timeout(limit, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            tracing::error!("{} did not finish within {}s", path, state.request_timeout);
+            json_error(StatusCode::GATEWAY_TIMEOUT, "Request took too long").into_response()
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Failed sign-in lockout
+// ---------------------------------------------------------------------------
+
+/// Failed sign-ins in a row by one client address or for one user name.
+#[derive(Debug)]
+struct LoginFailures {
+    count: u32,
+    last: std::time::Instant,
+    locked_until: Option<std::time::Instant>,
+}
+
+/// Failed sign-ins are forgotten this long after the last one.
+const LOGIN_FAILURE_MEMORY: std::time::Duration = std::time::Duration::from_secs(24 * 3600);
+/// The first lockout; every further failure doubles it, up to `MAX_LOCKOUT`.
+const FIRST_LOCKOUT: std::time::Duration = std::time::Duration::from_secs(60);
+const MAX_LOCKOUT: std::time::Duration = std::time::Duration::from_secs(3600);
+
+/// Clients and user names whose failures are remembered at most, so failed
+/// sign-ins from ever new addresses can't use up the memory.
+const MAX_LOGIN_FAILURES: usize = 4096;
+
+/// Make `failures` hold at most `limit` entries: forgotten ones go first,
+/// then those not locked out, least recently failed first. Lockouts are only
+/// dropped when there is nothing else left to drop.
+fn evict_login_failures(
+    failures: &mut HashMap<String, LoginFailures>,
+    now: std::time::Instant,
+    limit: usize,
+) {
+    if failures.len() <= limit {
+        return;
+    }
+    failures.retain(|_, f| now.duration_since(f.last) < LOGIN_FAILURE_MEMORY);
+    if failures.len() <= limit {
+        return;
+    }
+    let mut oldest: Vec<(bool, std::time::Instant, String)> = failures
+        .iter()
+        .map(|(key, f)| {
+            (
+                f.locked_until.is_some_and(|until| until > now),
+                f.last,
+                key.clone(),
+            )
+        })
+        .collect();
+    oldest.sort();
+    let excess = failures.len() - limit;
+    for (_, _, key) in oldest.into_iter().take(excess) {
+        failures.remove(&key);
+    }
+}
+
+fn login_failure_keys(client: &str, user: Option<&str>) -> Vec<String> {
+    let mut keys = vec![format!("client:{}", client)];
+    keys.extend(user.map(|user| format!("user:{}", user)));
+    keys
+}
+
+/// Seconds until the client, or anyone signing in as `user`, may try again.
+fn login_lockout(state: &AppState, client: &str, user: Option<&str>) -> Option<u64> {
+    if state.login_attempts == 0 {
+        return None;
+    }
+    let now = std::time::Instant::now();
+    let failures = state
+        .login_failures
+        .lock()
+        .expect("Login failures lock poisoned");
+    login_failure_keys(client, user)
+        .iter()
+        .filter_map(|key| failures.get(key)?.locked_until)
+        .filter(|until| *until > now)
+        .map(|until| until.duration_since(now).as_secs() + 1)
+        .max()
+}
+
+/// Count a failed sign-in against the client and user. After
+/// `login_attempts` in a row they are locked out, for a minute at first and
+/// twice as long after every further failure.
+async fn record_login_failure(state: &AppState, client: &str, user: Option<&str>, action: &str) {
+    if state.login_attempts == 0 {
+        return;
+    }
+    let now = std::time::Instant::now();
+    let mut lockout = None;
+    {
+        let mut failures = state
+            .login_failures
+            .lock()
+            .expect("Login failures lock poisoned");
+        let keys = login_failure_keys(client, user);
+        let new_keys = keys
+            .iter()
+            .filter(|key| !failures.contains_key(*key))
+            .count();
+        evict_login_failures(
+            &mut failures,
+            now,
+            MAX_LOGIN_FAILURES.saturating_sub(new_keys),
+        );
+        for key in keys {
+            let entry = failures.entry(key).or_insert(LoginFailures {
+                count: 0,
+                last: now,
+                locked_until: None,
+            });
+            if now.duration_since(entry.last) >= LOGIN_FAILURE_MEMORY {
+                entry.count = 0;
+            }
+            entry.count += 1;
+            entry.last = now;
+            if entry.count >= state.login_attempts {
+                let doublings = (entry.count - state.login_attempts).min(16);
+                let duration = (FIRST_LOCKOUT * 2u32.pow(doublings)).min(MAX_LOCKOUT);
+                entry.locked_until = Some(now + duration);
+                lockout = lockout.max(Some(duration));
+            }
+        }
+    }
+
+    let (status, result) = match lockout {
+        Some(duration) => {
+            tracing::warn!(
+                "Locking out {} for {} seconds after repeated failed sign-ins{}",
+                client,
+                duration.as_secs(),
+                user.map(|u| format!(" as '{}'", u)).unwrap_or_default()
+            );
+            (StatusCode::TOO_MANY_REQUESTS, "locked_out")
+        }
+        None => (StatusCode::UNAUTHORIZED, "failed"),
+    };
+    let user = user.map(str::to_string);
+    append_audit(
+        state,
+        client.to_string(),
+        user,
+        action.to_string(),
+        None,
+        status,
+        result,
+    )
+    .await;
+}
+
+/// Forget earlier failures once the client signs in as `user`.
+fn clear_login_failures(state: &AppState, client: &str, user: Option<&str>) {
+    if state.login_attempts == 0 {
+        return;
+    }
+    let mut failures = state
+        .login_failures
+        .lock()
+        .expect("Login failures lock poisoned");
+    for key in login_failure_keys(client, user) {
+        failures.remove(&key);
+    }
+}
+
+fn locked_out_response(wait: u64) -> axum::response::Response {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(axum::http::header::RETRY_AFTER, wait.to_string())],
+        error_body("locked_out", "Too many failed sign-in attempts", None),
+    )
+        .into_response()
+}
+
+// ---------------------------------------------------------------------------
+// Authentication
+// ---------------------------------------------------------------------------
+
+/// Users who may sign in with HTTP Basic authentication, from `[auth.users]`
+/// and the htpasswd file, and API keys for automation clients. Authentication
+/// is off when there are neither.
+#[derive(Debug, Default)]
+struct AuthSettings {
+    /// user name -> bcrypt hash, `{SHA}` hash or plain password
+    users: HashMap<String, String>,
+    /// key name -> key, from `[auth.api_keys]`
+    api_keys: HashMap<String, String>,
+    /// user or API key name -> role, from `[auth.roles]`
+    roles: HashMap<String, Role>,
+    /// Role of users and API keys without an entry in `roles`
+    default_role: Role,
+    /// user or API key name -> containers it may access, from `[auth.permissions]`
+    permissions: HashMap<String, ContainerGrant>,
+    /// Clients present certificates, whose common name is their user name
+    client_certificates: bool,
+    /// Mark session cookies `Secure`, as docker-direct serves HTTPS itself
+    secure_cookies: bool,
+    /// Headers a forward-auth proxy names the user and groups in, from `[auth.forward_auth]`
+    forward_auth: Option<ForwardAuthConfig>,
+    /// group name -> role, for users whose groups come from a forward-auth
+    /// proxy or LDAP
+    group_roles: HashMap<String, Role>,
+    /// Provider for browser logins, from `[auth.oidc]`
+    oidc: Option<OidcConfig>,
+    /// session cookie value -> signed-in user
+    sessions: std::sync::Mutex<HashMap<String, Session>>,
+    /// OIDC `state` parameter -> logins in progress
+    pending_logins: std::sync::Mutex<HashMap<String, PendingLogin>>,
+    session_lifetime: std::time::Duration,
+    /// `Authorization` header -> user and when to check again, for
+    /// credentials already checked, so bcrypt or LDAP only run once in a
+    /// while per client rather than on every poll
+    verified: std::sync::Mutex<HashMap<String, (Identity, std::time::Instant)>>,
+    /// Directory users may sign in against, from `[auth.ldap]`
+    ldap: Option<LdapConfig>,
+}
+
+/// How long checked `Authorization: Basic` credentials are trusted.
+const VERIFIED_TTL: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// What a caller may do. Each role includes the ones before it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Role {
+    /// See containers, their logs and stats
+    Viewer,
+    /// Also start, stop and otherwise operate allowed containers
+    #[default]
+    Operator,
+    /// Also update, recreate, prune, deploy and change the configuration
+    Admin,
+}
+
+fn role_name(role: Role) -> &'static str {
+    match role {
+        Role::Viewer => "viewer",
+        Role::Operator => "operator",
+        Role::Admin => "admin",
+    }
+}
+
+/// Read-only routes, open to viewers.
+const VIEWER_PATHS: [&str; 31] = [
+    "/containers",
+    "/logs",
+    "/stats",
+    "/containers/statuses",
+    "/containers/wait",
+    "/containers/inspect",
+    "/containers/health",
+    "/containers/processes",
+    "/containers/diff",
+    "/containers/restart-policy",
+    "/containers/commands",
+    "/containers/downloads",
+    "/containers/stats",
+    "/containers/stats/stream",
+    "/containers/logs",
+    "/containers/logs/stream",
+    "/volumes",
+    "/projects",
+    "/profiles",
+    "/groups",
+    "/services",
+    "/stacks",
+    "/networks",
+    "/images",
+    "/images/history",
+    "/system/df",
+    "/events",
+    "/events/stream",
+    "/ws/containers",
+    "/api/docs",
+    "/api/openapi.json",
+];
+
+/// Routes that operate containers, open to operators.
+const OPERATOR_PATHS: [&str; 19] = [
+    "/containers/start",
+    "/containers/stop",
+    "/containers/restart",
+    "/containers/pause",
+    "/containers/unpause",
+    "/containers/kill",
+    "/containers/exec",
+    "/containers/download",
+    "/containers/stop-all",
+    "/containers/batch",
+    "/projects/start",
+    "/projects/stop",
+    "/profiles/activate",
+    "/groups/start",
+    "/groups/stop",
+    "/groups/restart",
+    "/services/scale",
+    "/networks/connect",
+    "/networks/disconnect",
+];
+
+/// The role a route requires. Routes that are not listed are admin-only.
+fn required_role(path: &str) -> Role {
+    if VIEWER_PATHS.contains(&path) {
+        Role::Viewer
+    } else if OPERATOR_PATHS.contains(&path) {
+        Role::Operator
+    } else {
+        Role::Admin
+    }
+}
+
+/// The caller's role. Without authentication everyone is an operator, and
+/// only the admin token grants more. A role in `[auth.roles]` takes
+/// precedence over one granted through groups.
+fn identity_role(state: &AppState, identity: Option<&Identity>) -> Role {
+    match identity {
+        None => Role::Operator,
+        Some(Identity::AdminToken) => Role::Admin,
+        Some(Identity::User(name) | Identity::ApiKey(name)) => state
+            .auth
+            .roles
+            .get(name)
+            .copied()
+            .unwrap_or(state.auth.default_role),
+        Some(Identity::GroupMember(name, group_role)) => state
+            .auth
+            .roles
+            .get(name)
+            .copied()
+            .or(*group_role)
+            .unwrap_or(state.auth.default_role),
+    }
+}
+
+/// The highest role any of `groups` is granted in `[auth.group_roles]`.
+fn group_role(state: &AppState, groups: &[String]) -> Option<Role> {
+    groups
+        .iter()
+        .filter_map(|g| state.auth.group_roles.get(g))
+        .max()
+        .copied()
+}
+
+/// A user's or API key's access to particular containers, from
+/// `[auth.permissions.<name>]`. Callers without an entry may access every
+/// allowed container.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ContainerGrant {
+    containers: Vec<String>,
+    /// Groups from `--groups-file` or `[groups]`, granting all their members
+    groups: Vec<String>,
+}
+
+tokio::task_local! {
+    /// The caller of the request being handled, set by `require_auth`
+    static CALLER: Option<Identity>;
+}
+
+fn current_caller() -> Option<Identity> {
+    CALLER.try_with(|caller| caller.clone()).ok().flatten()
+}
+
+/// Whether `caller` has been granted access to container `name`. Anonymous
+/// callers, the admin token and callers without a grant are not restricted.
+fn may_access(state: &AppState, caller: Option<&Identity>, name: &str) -> bool {
+    let Some(Identity::User(caller) | Identity::GroupMember(caller, _) | Identity::ApiKey(caller)) =
+        caller
+    else {
+        return true;
+    };
+    let Some(grant) = state.auth.permissions.get(caller) else {
+        return true;
+    };
+    let policy = current_policy(state);
+    grant.containers.iter().any(|c| c == name)
+        || grant.groups.iter().any(|g| {
+            policy
+                .groups
+                .get(g)
+                .is_some_and(|members| members.iter().any(|c| c == name))
+        })
+}
+
+/// `may_access` for the caller of the current request; always true outside
+/// of requests, e.g. in the background refresh.
+fn caller_may_access(state: &AppState, name: &str) -> bool {
+    may_access(state, current_caller().as_ref(), name)
+}
+
+/// The allowed containers the current caller may access.
+fn accessible_containers(state: &AppState) -> Vec<String> {
+    current_policy(state)
+        .allowed_containers
+        .iter()
+        .filter(|name| caller_may_access(state, name))
+        .cloned()
+        .collect()
+}
+
+/// Who made a request, as established by `require_auth`.
+#[derive(Clone, Debug)]
+enum Identity {
+    User(String),
+    /// A user whose groups come from elsewhere, e.g. a forward-auth proxy,
+    /// with the role `[auth.group_roles]` grants them
+    GroupMember(String, Option<Role>),
+    ApiKey(String),
+    AdminToken,
+}
+
+/// Name of the user or API key for logging.
+fn identity_name(identity: &Identity) -> &str {
+    match identity {
+        Identity::User(name) | Identity::GroupMember(name, _) | Identity::ApiKey(name) => name,
+        Identity::AdminToken => "admin token",
+    }
+}
+
+/// Load an htpasswd file. Supports bcrypt (`htpasswd -B`) and `{SHA}` hashes.
+fn load_htpasswd(filename: &str) -> Result<HashMap<String, String>> {
+    let content = read_to_string(filename)
+        .map_err(|e| anyhow::anyhow!("Failed to read htpasswd file '{}': {}", filename, e))?;
+    let mut users = HashMap::new();
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((user, hash)) = line.split_once(':') else {
+            anyhow::bail!("Invalid line in htpasswd file '{}': {}", filename, line);
+        };
+        if hash.starts_with("$apr1$") {
+            anyhow::bail!(
+                "User '{}' in '{}' uses an MD5 hash; recreate it with `htpasswd -B`",
+                user,
+                filename
+            );
+        }
+        users.insert(user.to_string(), hash.to_string());
+    }
+    Ok(users)
+}
+
+fn check_password(password: &str, stored: &str) -> bool {
+    use base64::Engine;
+    use sha1::Digest;
+
+    if stored.starts_with("$2") {
+        bcrypt::verify(password, stored).unwrap_or(false)
+    } else if let Some(hash) = stored.strip_prefix("{SHA}") {
+        let digest = base64::engine::general_purpose::STANDARD
+            .encode(sha1::Sha1::digest(password.as_bytes()));
+        constant_time_eq(digest.as_bytes(), hash.as_bytes())
+    } else {
+        constant_time_eq(password.as_bytes(), stored.as_bytes())
+    }
+}
+
+/// Check a user name and password against the configured users, then
+/// against the LDAP directory.
+async fn check_credentials(state: &AppState, user: &str, password: &str) -> Option<Identity> {
+    if let Some(stored) = state.auth.users.get(user) {
+        let stored = stored.clone();
+        let password = password.to_string();
+        let valid = tokio::task::spawn_blocking(move || check_password(&password, &stored))
+            .await
+            .unwrap_or(false);
+        return valid.then(|| Identity::User(user.to_string()));
+    }
+    let ldap = state.auth.ldap.as_ref()?;
+    match ldap_user_groups(ldap, user, password).await {
+        Ok(Some(groups)) => {
+            let role = group_role(state, &groups);
+            Some(Identity::GroupMember(user.to_string(), role))
+        }
+        Ok(None) => None,
+        Err(e) => {
+            tracing::error!("LDAP authentication for '{}' failed: {}", user, e);
+            None
+        }
+    }
+}
+
+/// The user name and password sent as `Authorization: Basic`.
+fn basic_credentials(headers: &axum::http::HeaderMap) -> Option<(String, String)> {
+    use base64::Engine;
+
+    let header = headers
+        .get(axum::http::header::AUTHORIZATION)?
+        .to_str()
+        .ok()?;
+    let encoded = header.strip_prefix("Basic ")?;
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
+
+/// The user named by valid `Authorization: Basic` credentials.
+async fn basic_auth_user(state: &AppState, headers: &axum::http::HeaderMap) -> Option<Identity> {
+    let header = headers
+        .get(axum::http::header::AUTHORIZATION)?
+        .to_str()
+        .ok()?;
+    if !header.starts_with("Basic ") {
+        return None;
+    }
+    let now = std::time::Instant::now();
+    if let Some((identity, expires)) = state
+        .auth
+        .verified
+        .lock()
+        .expect("Auth lock poisoned")
+        .get(header)
+    {
+        if *expires > now {
+            return Some(identity.clone());
+        }
+    }
+
+    let (user, password) = basic_credentials(headers)?;
+    let Some(identity) = check_credentials(state, &user, &password).await else {
+        tracing::warn!("Invalid password for user '{}'", user);
+        return None;
+    };
+
+    let mut verified = state.auth.verified.lock().expect("Auth lock poisoned");
+    verified.retain(|_, (_, expires)| *expires > now);
+    verified.insert(header.to_string(), (identity.clone(), now + VERIFIED_TTL));
+    Some(identity)
+}
+
+/// The name of the API key sent as `X-Api-Key` or `Authorization: Bearer`.
+fn api_key_name(state: &AppState, headers: &axum::http::HeaderMap) -> Option<String> {
+    let key = match headers.get("x-api-key") {
+        Some(value) => value.to_str().ok()?,
+        None => headers
+            .get(axum::http::header::AUTHORIZATION)?
+            .to_str()
+            .ok()?
+            .strip_prefix("Bearer ")?,
+    };
+    state
+        .auth
+        .api_keys
+        .iter()
+        .find(|(_, expected)| constant_time_eq(key.as_bytes(), expected.as_bytes()))
+        .map(|(name, _)| name.clone())
+}
+
+/// The user a forward-auth proxy such as Authelia or Authentik signed in,
+/// with the role their groups grant.
+fn forwarded_user(state: &AppState, headers: &axum::http::HeaderMap) -> Option<Identity> {
+    let config = state.auth.forward_auth.as_ref()?;
+    let user = headers
+        .get(config.user_header.as_str())?
+        .to_str()
+        .ok()?
+        .trim();
+    if user.is_empty() {
+        return None;
+    }
+    let groups: Vec<String> = headers
+        .get(config.groups_header.as_str())
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(',').map(|g| g.trim().to_string()).collect())
+        .unwrap_or_default();
+    Some(Identity::GroupMember(
+        user.to_string(),
+        group_role(state, &groups),
+    ))
+}
+
+/// `via_proxy` is whether the request came from a trusted proxy, whose
+/// forward-auth headers are believed.
+async fn identify(
+    state: &AppState,
+    headers: &axum::http::HeaderMap,
+    certificate: Option<&ClientCertificate>,
+    via_proxy: bool,
+) -> Option<Identity> {
+    if let Some(user) = certificate.and_then(|c| c.common_name.clone()) {
+        return Some(Identity::User(user));
+    }
+    if let Some(identity) = via_proxy.then(|| forwarded_user(state, headers)).flatten() {
+        return Some(identity);
+    }
+    if let Some(identity) = session_identity(state, headers) {
+        return Some(identity);
+    }
+    if let Some(identity) = basic_auth_user(state, headers).await {
+        return Some(identity);
+    }
+    if let Some(name) = api_key_name(state, headers) {
+        return Some(Identity::ApiKey(name));
+    }
+    is_admin(state, headers).then_some(Identity::AdminToken)
+}
+
+/// Whether users may sign in with a user name and password.
+fn password_login(state: &AppState) -> bool {
+    !state.auth.users.is_empty() || state.auth.ldap.is_some()
+}
+
+/// Routes reachable without signing in, so signing in and out works and
+/// browsers can install the web UI, which they do without credentials.
+const PUBLIC_PATHS: [&str; 7] = [
+    "/login",
+    "/auth/oidc/login",
+    "/auth/oidc/callback",
+    "/logout",
+    "/link",
+    "/manifest.webmanifest",
+    "/sw.js",
+];
+
+fn auth_required(state: &AppState) -> bool {
+    password_login(state)
+        || !state.auth.api_keys.is_empty()
+        || state.auth.oidc.is_some()
+        || state.auth.client_certificates
+        || state.auth.forward_auth.is_some()
+}
+
+/// Middleware requiring authentication on every route once users, API keys
+/// or OpenID Connect are configured: a session or HTTP Basic for people, an
+/// API key for scripts. The admin token is accepted too. Every route then
+/// checks the caller's role against `required_role`.
+async fn require_auth(
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    mut request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let path = request.uri().path().to_string();
+    if PUBLIC_PATHS.contains(&path.as_str()) || path.starts_with("/assets/") {
+        return next.run(request).await;
+    }
+
+    let method = request.method();
+    if state.read_only && method != axum::http::Method::GET && method != axum::http::Method::HEAD {
+        tracing::warn!("Request for {} rejected: read-only mode", path);
+        return json_error(StatusCode::FORBIDDEN, "docker-direct is in read-only mode")
+            .into_response();
+    }
+
+    // Credentials that can be guessed count towards a lockout when wrong
+    let client = addr.ip().to_canonical().to_string();
+    let guessable = (auth_required(&state) || state.admin_token.is_some())
+        && (request
+            .headers()
+            .contains_key(axum::http::header::AUTHORIZATION)
+            || request.headers().contains_key("x-api-key"));
+    let basic_user = basic_credentials(request.headers()).map(|(user, _)| user);
+    if guessable {
+        if let Some(wait) = login_lockout(&state, &client, basic_user.as_deref()) {
+            tracing::warn!("Request for {} from {} rejected: locked out", path, client);
+            return locked_out_response(wait);
+        }
+    }
+
+    let certificate = request.extensions().get::<ClientCertificate>();
+    let via_proxy = request.extensions().get::<TrustedProxy>().is_some();
+    let identity = identify(&state, request.headers(), certificate, via_proxy).await;
+    if guessable {
+        match &identity {
+            Some(identity) => clear_login_failures(&state, &client, Some(identity_name(identity))),
+            None => record_login_failure(&state, &client, basic_user.as_deref(), &path).await,
+        }
+    }
+    if identity.is_none() && auth_required(&state) {
+        tracing::warn!("Unauthenticated request for {}", path);
+        // Send browsers to a login page rather than showing an error
+        let wants_html = request
+            .headers()
+            .get(axum::http::header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("text/html"));
+        if request.method() == axum::http::Method::GET && wants_html {
+            if password_login(&state) {
+                return axum::response::Redirect::to("/login").into_response();
+            }
+            if state.auth.oidc.is_some() {
+                return axum::response::Redirect::to("/auth/oidc/login").into_response();
+            }
+        }
+        let challenge = if password_login(&state) {
+            "Basic realm=\"docker-direct\""
+        } else {
+            "Bearer"
+        };
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(axum::http::header::WWW_AUTHENTICATE, challenge)],
+            error_body("unauthorized", "Authentication required", None),
+        )
+            .into_response();
+    }
+
+    let role = identity_role(&state, identity.as_ref());
+    let required = required_role(&path);
+    if role < required {
+        let Some(identity) = identity else {
+            tracing::warn!(
+                "Request for {} rejected: missing or invalid admin token",
+                path
+            );
+            return json_error(StatusCode::UNAUTHORIZED, "Admin token required").into_response();
+        };
+        tracing::warn!(
+            "'{}' ({}) may not use {}, which requires the {} role",
+            identity_name(&identity),
+            role_name(role),
+            path,
+            role_name(required)
+        );
+        return json_error(
+            StatusCode::FORBIDDEN,
+            &format!("Requires the {} role", role_name(required)),
+        )
+        .into_response();
+    }
+
+    if let Some(identity) = &identity {
+        tracing::debug!(
+            "{} {} by '{}'",
+            request.method(),
+            request.uri(),
+            identity_name(identity)
+        );
+        request.extensions_mut().insert(identity.clone());
+    }
+    CALLER.scope(identity, next.run(request)).await
+}
+
+// ---------------------------------------------------------------------------
+// LDAP authentication
+// ---------------------------------------------------------------------------
+
+/// `[auth.ldap]`: an LDAP directory or Active Directory to check passwords
+/// against. Users are either bound to directly through `user_dn`, or looked
+/// up under `base_dn` with `user_filter`, using `bind_dn` if the directory
+/// doesn't allow anonymous searches.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LdapConfig {
+    /// `ldap://` or `ldaps://` URL of the server
+    url: String,
+    /// Upgrade `ldap://` connections with StartTLS
+    #[serde(default)]
+    starttls: bool,
+    /// The user's DN, with `{user}` standing for the user name, e.g.
+    /// `uid={user},ou=people,dc=example,dc=com`
+    user_dn: Option<String>,
+    bind_dn: Option<String>,
+    bind_password: Option<String>,
+    base_dn: Option<String>,
+    /// e.g. `(sAMAccountName={user})` for Active Directory
+    #[serde(default = "default_ldap_user_filter")]
+    user_filter: String,
+    /// Attribute listing the groups a user is a member of
+    #[serde(default = "default_ldap_group_attribute")]
+    group_attribute: String,
+}
+
+fn default_ldap_user_filter() -> String {
+    "(uid={user})".to_string()
+}
+
+fn default_ldap_group_attribute() -> String {
+    "memberOf".to_string()
+}
+
+/// Bind to the directory as `user` and return their groups, each both as
+/// its full DN and as its common name, or `None` if the password is wrong.
+async fn ldap_user_groups(
+    config: &LdapConfig,
+    user: &str,
+    password: &str,
+) -> Result<Option<Vec<String>>> {
+    // An empty password would be an anonymous bind, which always succeeds
+    if user.is_empty() || password.is_empty() {
+        return Ok(None);
+    }
+
+    let settings = ldap3::LdapConnSettings::new()
+        .set_conn_timeout(std::time::Duration::from_secs(10))
+        .set_starttls(config.starttls);
+    let (conn, mut ldap) = ldap3::LdapConnAsync::with_settings(settings, &config.url).await?;
+    ldap3::drive!(conn);
+
+    let attributes = vec![config.group_attribute.as_str()];
+    let dn = match &config.user_dn {
+        Some(template) => template.replace("{user}", &ldap3::dn_escape(user)),
+        None => {
+            if let Some(bind_dn) = &config.bind_dn {
+                let bind_password = config.bind_password.as_deref().unwrap_or("");
+                ldap.simple_bind(bind_dn, bind_password).await?.success()?;
+            }
+            let base_dn = config.base_dn.as_deref().unwrap_or("");
+            let filter = config
+                .user_filter
+                .replace("{user}", &ldap3::ldap_escape(user));
+            let (entries, _) = ldap
+                .search(base_dn, ldap3::Scope::Subtree, &filter, attributes.clone())
+                .await?
+                .success()?;
+            let [entry] = entries.as_slice() else {
+                ldap.unbind().await?;
+                return Ok(None);
+            };
+            ldap3::SearchEntry::construct(entry.clone()).dn
+        }
+    };
+
+    if ldap.simple_bind(&dn, password).await?.success().is_err() {
+        ldap.unbind().await?;
+        return Ok(None);
+    }
+    let (entries, _) = ldap
+        .search(&dn, ldap3::Scope::Base, "(objectClass=*)", attributes)
+        .await?
+        .success()?;
+    ldap.unbind().await?;
+
+    let mut groups = Vec::new();
+    for entry in entries {
+        let entry = ldap3::SearchEntry::construct(entry);
+        for group in entry
+            .attrs
+            .get(&config.group_attribute)
+            .into_iter()
+            .flatten()
+        {
+            let common_name = group
+                .split(',')
+                .next()
+                .and_then(|rdn| rdn.split_once('='))
+                .filter(|(attribute, _)| attribute.trim().eq_ignore_ascii_case("cn"))
+                .map(|(_, value)| value.trim().to_string());
+            groups.push(group.clone());
+            groups.extend(common_name);
+        }
+    }
+    Ok(Some(groups))
+}
+
+// ---------------------------------------------------------------------------
+// Sessions and OpenID Connect login
+// ---------------------------------------------------------------------------
+
+const SESSION_COOKIE: &str = "docker_direct_session";
+/// Ties an OpenID Connect login to the browser that started it: holds the
+/// `state` and the PKCE verifier until the provider redirects back.
+const OIDC_LOGIN_COOKIE: &str = "docker_direct_oidc";
+
+/// How long a user has to sign in at the provider.
+const OIDC_LOGIN_LIFETIME: std::time::Duration = std::time::Duration::from_secs(600);
+
+/// An OpenID Connect login waiting for the provider to redirect back.
+#[derive(Debug)]
+struct PendingLogin {
+    /// Must come back in the ID token, so a token issued for another login
+    /// is refused
+    nonce: String,
+    expires: std::time::Instant,
+}
+
+const OIDC_NOT_CONFIGURED: &str = "OpenID Connect is not configured";
+
+/// A signed-in browser, identified by the session cookie.
+#[derive(Debug, Clone)]
+struct Session {
+    identity: Identity,
+    expires: std::time::Instant,
+}
+
+/// `[auth.forward_auth]`: a reverse proxy that authenticates users itself,
+/// like Traefik with Authelia, and passes them on in headers.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ForwardAuthConfig {
+    #[serde(default = "default_user_header")]
+    user_header: String,
+    /// Comma-separated group names
+    #[serde(default = "default_groups_header")]
+    groups_header: String,
+}
+
+fn default_user_header() -> String {
+    "Remote-User".to_string()
+}
+
+fn default_groups_header() -> String {
+    "Remote-Groups".to_string()
+}
+
+/// `[auth.oidc]`: an OpenID Connect provider such as Keycloak or Authentik.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OidcConfig {
+    /// The provider's `.well-known/openid-configuration` URL
+    discovery_url: String,
+    client_id: String,
+    client_secret: String,
+    /// This server's `/auth/oidc/callback` URL, as registered with the provider
+    redirect_url: String,
+    #[serde(default = "default_oidc_scopes")]
+    scopes: Vec<String>,
+    /// Userinfo claim used as the user name
+    #[serde(default = "default_username_claim")]
+    username_claim: String,
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    vec![
+        "openid".to_string(),
+        "profile".to_string(),
+        "email".to_string(),
+    ]
+}
+
+fn default_username_claim() -> String {
+    "preferred_username".to_string()
+}
+
+/// The endpoints of the provider's discovery document that are used.
+#[derive(Debug, Deserialize)]
+struct OidcProvider {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: String,
+}
+
+fn random_token() -> String {
+    let bytes: [u8; 32] = rand::random();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn cookie_value(headers: &axum::http::HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(axum::http::header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+/// Start a session for `identity` and return the `Set-Cookie` header value.
+fn create_session(state: &AppState, identity: Identity, secure: bool) -> String {
+    let token = random_token();
+    let lifetime = state.auth.session_lifetime;
+    let now = std::time::Instant::now();
+    let mut sessions = state.auth.sessions.lock().expect("Session lock poisoned");
+    sessions.retain(|_, session| session.expires > now);
+    sessions.insert(
+        token.clone(),
+        Session {
+            identity,
+            expires: now + lifetime,
+        },
+    );
+    format!(
+        "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}{}",
+        SESSION_COOKIE,
+        token,
+        lifetime.as_secs(),
+        if secure { "; Secure" } else { "" }
+    )
+}
+
+fn session_identity(state: &AppState, headers: &axum::http::HeaderMap) -> Option<Identity> {
+    let token = cookie_value(headers, SESSION_COOKIE)?;
+    let sessions = state.auth.sessions.lock().expect("Session lock poisoned");
+    sessions
+        .get(&token)
+        .filter(|session| session.expires > std::time::Instant::now())
+        .map(|session| session.identity.clone())
+}
+
+async fn discover_oidc(oidc: &OidcConfig) -> Result<OidcProvider> {
+    let provider = reqwest::get(&oidc.discovery_url)
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(provider)
+}
+
+/// The PKCE `S256` challenge for a code verifier.
+fn pkce_challenge(verifier: &str) -> String {
+    use base64::Engine;
+    use sha2::Digest;
+
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(sha2::Sha256::digest(verifier))
+}
+
+/// The `nonce` claim of an ID token. The token comes straight from the token
+/// endpoint over TLS, which OpenID Connect accepts in place of checking its
+/// signature.
+fn id_token_nonce(id_token: &str) -> Option<String> {
+    use base64::Engine;
+
+    let payload = id_token.split('.').nth(1)?;
+    let claims = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&claims).ok()?;
+    claims["nonce"].as_str().map(str::to_string)
+}
+
+/// Exchange an authorization code for the signed-in user's name.
+async fn oidc_user(oidc: &OidcConfig, code: &str, verifier: &str, nonce: &str) -> Result<String> {
+    let provider = discover_oidc(oidc).await?;
+    let client = reqwest::Client::new();
+    let token: serde_json::Value = client
+        .post(&provider.token_endpoint)
+        .basic_auth(&oidc.client_id, Some(&oidc.client_secret))
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", oidc.redirect_url.as_str()),
+            ("code_verifier", verifier),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let id_token = token["id_token"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("Token response has no id_token"))?;
+    if !id_token_nonce(id_token).is_some_and(|n| constant_time_eq(n.as_bytes(), nonce.as_bytes())) {
+        anyhow::bail!("ID token nonce does not match the login");
+    }
+    let access_token = token["access_token"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("Token response has no access_token"))?;
+
+    let info: serde_json::Value = client
+        .get(&provider.userinfo_endpoint)
+        .bearer_auth(access_token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    info[oidc.username_claim.as_str()]
+        .as_str()
+        .or(info["sub"].as_str())
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("Userinfo has no '{}' claim", oidc.username_claim))
+}
+
+async fn oidc_login_handle(State(state): State<SharedState>) -> axum::response::Response {
+    let Some(oidc) = &state.auth.oidc else {
+        return json_error(StatusCode::NOT_FOUND, OIDC_NOT_CONFIGURED).into_response();
+    };
+    let provider = match discover_oidc(oidc).await {
+        Ok(provider) => provider,
+        Err(e) => {
+            tracing::error!("OpenID Connect discovery failed: {}", e);
+            return json_error(StatusCode::BAD_GATEWAY, "Identity provider unavailable")
+                .into_response();
+        }
+    };
+
+    let login_state = random_token();
+    let verifier = random_token();
+    let nonce = random_token();
+    {
+        let now = std::time::Instant::now();
+        let mut pending = state
+            .auth
+            .pending_logins
+            .lock()
+            .expect("Session lock poisoned");
+        pending.retain(|_, login| login.expires > now);
+        pending.insert(
+            login_state.clone(),
+            PendingLogin {
+                nonce: nonce.clone(),
+                expires: now + OIDC_LOGIN_LIFETIME,
+            },
+        );
+    }
+
+    let scopes = oidc.scopes.join(" ");
+    let challenge = pkce_challenge(&verifier);
+    let params = [
+        ("response_type", "code"),
+        ("client_id", oidc.client_id.as_str()),
+        ("redirect_uri", oidc.redirect_url.as_str()),
+        ("scope", scopes.as_str()),
+        ("state", login_state.as_str()),
+        ("nonce", nonce.as_str()),
+        ("code_challenge", challenge.as_str()),
+        ("code_challenge_method", "S256"),
+    ];
+    let secure = if oidc.redirect_url.starts_with("https://") {
+        "; Secure"
+    } else {
+        ""
+    };
+    let cookie = format!(
+        "{}={}.{}; Path=/auth/oidc; HttpOnly; SameSite=Lax; Max-Age={}{}",
+        OIDC_LOGIN_COOKIE,
+        login_state,
+        verifier,
+        OIDC_LOGIN_LIFETIME.as_secs(),
+        secure
+    );
+    match reqwest::Url::parse_with_params(&provider.authorization_endpoint, &params) {
+        Ok(url) => (
+            [(axum::http::header::SET_COOKIE, cookie)],
+            axum::response::Redirect::to(url.as_str()),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Invalid authorization endpoint: {}", e);
+            json_error(StatusCode::BAD_GATEWAY, "Identity provider unavailable").into_response()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+async fn oidc_callback_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<OidcCallbackQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: axum::http::HeaderMap,
+) -> axum::response::Response {
+    let Some(oidc) = &state.auth.oidc else {
+        return json_error(StatusCode::NOT_FOUND, OIDC_NOT_CONFIGURED).into_response();
+    };
+    if let Some(error) = query.error {
+        tracing::warn!("OpenID Connect login from {} failed: {}", addr, error);
+        return json_error(
+            StatusCode::UNAUTHORIZED,
+            &format!("Login failed: {}", error),
+        )
+        .into_response();
+    }
+    let (Some(code), Some(login_state)) = (query.code, query.state) else {
+        return json_error(StatusCode::BAD_REQUEST, "Missing code or state").into_response();
+    };
+
+    // The state must come back to the browser that was sent off with it,
+    // or someone could finish their own login in a victim's browser
+    let cookie = cookie_value(&headers, OIDC_LOGIN_COOKIE).unwrap_or_default();
+    let Some((cookie_state, verifier)) = cookie.split_once('.') else {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            "Login expired or invalid, try again",
+        )
+        .into_response();
+    };
+    if !constant_time_eq(cookie_state.as_bytes(), login_state.as_bytes()) {
+        tracing::warn!(
+            "OpenID Connect callback from {} for a login it didn't start",
+            addr
+        );
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            "Login expired or invalid, try again",
+        )
+        .into_response();
+    }
+    let pending = state
+        .auth
+        .pending_logins
+        .lock()
+        .expect("Session lock poisoned")
+        .remove(&login_state);
+    let Some(pending) = pending.filter(|p| p.expires > std::time::Instant::now()) else {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            "Login expired or invalid, try again",
+        )
+        .into_response();
+    };
+
+    let user = match oidc_user(oidc, &code, verifier, &pending.nonce).await {
+        Ok(user) => user,
+        Err(e) => {
+            tracing::error!("OpenID Connect login from {} failed: {}", addr, e);
+            return json_error(StatusCode::BAD_GATEWAY, "Login failed").into_response();
+        }
+    };
+    tracing::info!("User '{}' signed in from {}", user, addr);
+
+    let secure = oidc.redirect_url.starts_with("https://");
+    let cookie = create_session(&state, Identity::User(user), secure);
+    let cleared = format!(
+        "{}=; Path=/auth/oidc; HttpOnly; SameSite=Lax; Max-Age=0",
+        OIDC_LOGIN_COOKIE
+    );
+    (
+        axum::response::AppendHeaders([
+            (axum::http::header::SET_COOKIE, cookie),
+            (axum::http::header::SET_COOKIE, cleared),
+        ]),
+        axum::response::Redirect::to("/containers"),
+    )
+        .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginQuery {
+    #[serde(default)]
+    failed: bool,
+    #[serde(default)]
+    locked: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginForm {
+    username: String,
+    password: String,
+    csrf_token: String,
+}
+
+async fn login_page_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<LoginQuery>,
+    headers: axum::http::HeaderMap,
+) -> axum::response::Response {
+    // Kept while the cookie lasts, so several open login pages all work
+    let login = cookie_value(&headers, LOGIN_COOKIE)
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(random_token);
+    let (lang, t) = state.locales.pick(&headers);
+    let template = LoginTemplate {
+        title: state.title.clone(),
+        csrf_token: login_csrf_token(&state, &login),
+        failed: query.failed,
+        locked: query.locked,
+        oidc: state.auth.oidc.is_some(),
+        lang,
+        t,
+    };
+    let cookie = format!(
+        "{}={}; Path=/login; HttpOnly; SameSite=Strict; Max-Age=3600{}",
+        LOGIN_COOKIE,
+        login,
+        if state.auth.secure_cookies {
+            "; Secure"
+        } else {
+            ""
+        }
+    );
+    (
+        [(axum::http::header::SET_COOKIE, cookie)],
+        Html(template.render().unwrap_or_default()),
+    )
+        .into_response()
+}
+
+/// Check the login form and start a session for the user.
+async fn login_handle(
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: axum::http::HeaderMap,
+    Form(form): Form<LoginForm>,
+) -> axum::response::Response {
+    let login = cookie_value(&headers, LOGIN_COOKIE).unwrap_or_default();
+    let expected = login_csrf_token(&state, &login);
+    if login.is_empty() || !constant_time_eq(form.csrf_token.as_bytes(), expected.as_bytes()) {
+        tracing::warn!("Login from {} rejected: invalid CSRF token", addr);
+        return json_error(StatusCode::FORBIDDEN, "Cross-site request rejected").into_response();
+    }
+
+    let client = addr.ip().to_canonical().to_string();
+    if login_lockout(&state, &client, Some(&form.username)).is_some() {
+        tracing::warn!(
+            "Login for user '{}' from {} rejected: locked out",
+            form.username,
+            addr
+        );
+        return axum::response::Redirect::to("/login?locked=true").into_response();
+    }
+    let Some(identity) = check_credentials(&state, &form.username, &form.password).await else {
+        tracing::warn!("Failed login for user '{}' from {}", form.username, addr);
+        record_login_failure(&state, &client, Some(&form.username), "/login").await;
+        return axum::response::Redirect::to("/login?failed=true").into_response();
+    };
+
+    tracing::info!("User '{}' signed in from {}", form.username, addr);
+    clear_login_failures(&state, &client, Some(&form.username));
+    let user = Some(form.username.clone());
+    let action = "/login".to_string();
+    append_audit(&state, client, user, action, None, StatusCode::OK, "ok").await;
+    let cookie = create_session(&state, identity, state.auth.secure_cookies);
+    (
+        [(axum::http::header::SET_COOKIE, cookie)],
+        axum::response::Redirect::to("/containers"),
+    )
+        .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogoutForm {
+    csrf_token: String,
+}
+
+/// Sign out, from the web UI's sign-out form, so other sites can't sign
+/// users out by linking to it.
+async fn logout_handle(
+    State(state): State<SharedState>,
+    headers: axum::http::HeaderMap,
+    Form(form): Form<LogoutForm>,
+) -> axum::response::Response {
+    let expected = csrf_token(&state, &headers);
+    if !constant_time_eq(form.csrf_token.as_bytes(), expected.as_bytes()) {
+        tracing::warn!("Sign-out with an invalid CSRF token rejected");
+        return json_error(StatusCode::FORBIDDEN, "Cross-site request rejected").into_response();
+    }
+    if let Some(token) = cookie_value(&headers, SESSION_COOKIE) {
+        let session = state
+            .auth
+            .sessions
+            .lock()
+            .expect("Session lock poisoned")
+            .remove(&token);
+        if let Some(session) = session {
+            tracing::info!("User '{}' signed out", identity_name(&session.identity));
+        }
+    }
+    (
+        [(
+            axum::http::header::SET_COOKIE,
+            format!(
+                "{}=; Path=/; HttpOnly; SameSite=Lax; Max-Age=0",
+                SESSION_COOKIE
+            ),
+        )],
+        Html("<p>Signed out. <a href=\"/containers\">Sign in again</a></p>"),
+    )
+        .into_response()
+}
+
+// ---------------------------------------------------------------------------
+// Action links
+// ---------------------------------------------------------------------------
+
+/// Actions an action link may perform.
+const LINK_ACTIONS: [&str; 3] = ["start", "stop", "restart"];
+
+/// What an action link does, signed into its token.
+#[derive(Debug, Serialize, Deserialize)]
+struct ActionLink {
+    container: String,
+    action: String,
+    /// Unix timestamp (seconds) after which the link no longer works.
+    expires: i64,
+    /// Tells links apart, so each can only be used once
+    nonce: String,
+    /// User or API key that created the link
+    created_by: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateLinkQuery {
+    name: String,
+    action: String,
+    /// How long the link works [default: 1440, one day]
+    minutes: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LinkToken {
+    token: String,
+}
+
+/// Longest an action link may work, 30 days.
+const MAX_LINK_MINUTES: i64 = 30 * 24 * 60;
+
+fn link_signature(state: &AppState, payload: &str) -> String {
+    use base64::Engine;
+    use hmac::Mac;
+
+    let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(state.link_secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(payload.as_bytes());
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+}
+
+fn sign_link(state: &AppState, link: &ActionLink) -> String {
+    use base64::Engine;
+
+    let json = serde_json::to_string(link).unwrap_or_default();
+    let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json);
+    let signature = link_signature(state, &payload);
+    format!("{}.{}", payload, signature)
+}
+
+/// The link a token stands for, if it was signed by us and hasn't expired.
+fn verify_link(state: &AppState, token: &str) -> Result<ActionLink, &'static str> {
+    use base64::Engine;
+
+    let (payload, signature) = token.split_once('.').ok_or("This link is not valid.")?;
+    let expected = link_signature(state, payload);
+    if !constant_time_eq(signature.as_bytes(), expected.as_bytes()) {
+        return Err("This link is not valid.");
+    }
+    let link: ActionLink = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .ok_or("This link is not valid.")?;
+    if link.expires < unix_now() {
+        return Err("This link has expired.");
+    }
+    if state
+        .used_links
+        .lock()
+        .expect("Link lock poisoned")
+        .contains_key(&link.nonce)
+    {
+        return Err("This link has already been used.");
+    }
+    Ok(link)
+}
+
+/// Where used links are remembered across restarts: `used-links.json` next
+/// to the audit log.
+fn used_links_path(audit_log: &str) -> String {
+    std::path::Path::new(audit_log)
+        .with_file_name("used-links.json")
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The used links saved by `save_used_links` that haven't expired yet.
+fn load_used_links(path: &str) -> Result<HashMap<String, i64>> {
+    let content = match read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => anyhow::bail!("Failed to read used links '{}': {}", path, e),
+    };
+    let mut used: HashMap<String, i64> = serde_json::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("Failed to parse used links '{}': {}", path, e))?;
+    let now = unix_now();
+    used.retain(|_, expires| *expires >= now);
+    Ok(used)
+}
+
+/// Save the used links, if there is a file for them, so a link can't be used
+/// again after a restart.
+async fn save_used_links(state: &AppState) {
+    let Some((path, lock)) = &state.used_links_file else {
+        return;
+    };
+    // Saves happen one at a time, each writing the links used so far
+    let _saving = lock.lock().await;
+    let content = {
+        let used = state.used_links.lock().expect("Link lock poisoned");
+        serde_json::to_string(&*used).unwrap_or_default()
+    };
+    // Written aside and renamed, so a crash never leaves half a file
+    let temporary = format!("{}.tmp", path);
+    let result = match tokio::fs::write(&temporary, content).await {
+        Ok(()) => tokio::fs::rename(&temporary, path).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        tracing::error!("Failed to save used links to '{}': {}", path, e);
+    }
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// Create a signed link that performs one action on one container once,
+/// e.g. starting a game server, for someone without an account.
+async fn create_link_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<CreateLinkQuery>,
+    headers: axum::http::HeaderMap,
+) -> impl IntoResponse {
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !LINK_ACTIONS.contains(&query.action.as_str()) {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            "Action must be start, stop or restart",
+        );
+    }
+    if !is_action_permitted(&state, &query.name, &query.action) {
+        tracing::warn!(
+            "Action '{}' not permitted for '{}'",
+            query.action,
+            query.name
+        );
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+    let minutes = query.minutes.unwrap_or(24 * 60);
+    if !(1..=MAX_LINK_MINUTES).contains(&minutes) {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            &format!("minutes must be between 1 and {}", MAX_LINK_MINUTES),
+        );
+    }
+
+    let created_by = current_caller()
+        .map(|caller| identity_name(&caller).to_string())
+        .unwrap_or_else(|| "anonymous".to_string());
+    let link = ActionLink {
+        container: query.name,
+        action: query.action,
+        expires: unix_now() + minutes * 60,
+        nonce: random_token(),
+        created_by,
+    };
+    let token = sign_link(&state, &link);
+    let path = format!("/link?token={}", token);
+    let scheme = if state.auth.secure_cookies {
+        "https"
+    } else {
+        "http"
+    };
+    let url = headers
+        .get(axum::http::header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .map(|host| format!("{}://{}{}", scheme, host, path));
+    tracing::info!(
+        "'{}' created a link to {} '{}', valid for {} minutes",
+        link.created_by,
+        link.action,
+        link.container,
+        minutes
+    );
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "url": url,
+            "path": path,
+            "container": link.container,
+            "action": link.action,
+            "expires": link.expires,
+        })),
+    )
+}
+
+fn link_page(link: Option<&ActionLink>, token: &str, message: &str) -> Html<String> {
+    let template = LinkTemplate {
+        container: link.map(|l| l.container.clone()).unwrap_or_default(),
+        action: link.map(|l| l.action.clone()).unwrap_or_default(),
+        token: token.to_string(),
+        message: message.to_string(),
+    };
+    Html(template.render().unwrap_or_default())
+}
+
+/// Ask for confirmation before performing an action link, so link previews
+/// in chat apps don't use it up.
+async fn link_page_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<LinkToken>,
+) -> Html<String> {
+    match verify_link(&state, &query.token) {
+        Ok(link) => link_page(Some(&link), &query.token, ""),
+        Err(message) => link_page(None, "", message),
+    }
+}
+
+/// Perform the action of a link and mark it used.
+async fn link_handle(
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Form(form): Form<LinkToken>,
+) -> axum::response::Response {
+    if state.read_only {
+        return link_page(None, "", "docker-direct is in read-only mode.").into_response();
+    }
+    let link = match verify_link(&state, &form.token) {
+        Ok(link) => link,
+        Err(message) => {
+            tracing::warn!("Rejected action link from {}: {}", addr, message);
+            return link_page(None, "", message).into_response();
+        }
+    };
+    {
+        let now = unix_now();
+        let mut used = state.used_links.lock().expect("Link lock poisoned");
+        used.retain(|_, expires| *expires >= now);
+        // Another request may have used the link since it was verified
+        if used.insert(link.nonce.clone(), link.expires).is_some() {
+            return link_page(None, "", "This link has already been used.").into_response();
+        }
+    }
+    save_used_links(&state).await;
+
+    tracing::info!(
+        "Action link from '{}' used by {}: {} '{}'",
+        link.created_by,
+        addr,
+        link.action,
+        link.container
+    );
+    let query = Query(ActionQuery {
+        name: link.container.clone(),
+        wait_for: None,
+        timeout: None,
+    });
+    let response = match link.action.as_str() {
+        "start" => start_container_handle(State(state.clone()), query, ConnectInfo(addr))
+            .await
+            .into_response(),
+        "stop" => stop_container_handle(State(state.clone()), query, ConnectInfo(addr))
+            .await
+            .into_response(),
+        _ => {
+            let query = Query(ContainerName { name: query.0.name });
+            restart_container_handle(State(state.clone()), query, ConnectInfo(addr))
+                .await
+                .into_response()
+        }
+    };
+
+    let status = response.status();
+    append_audit(
+        &state,
+        addr.ip().to_canonical().to_string(),
+        Some(link.created_by.clone()),
+        format!("/containers/{}", link.action),
+        Some(link.container.clone()),
+        status,
+        if status.is_success() { "ok" } else { "error" },
+    )
+    .await;
+    let message = if status.is_success() {
+        format!("Done: {} {}.", link.container, past_tense(&link.action))
+    } else {
+        // Let the link be tried again after a failure
+        state
+            .used_links
+            .lock()
+            .expect("Link lock poisoned")
+            .remove(&link.nonce);
+        save_used_links(&state).await;
+        format!(
+            "Could not {} {}. Try again later.",
+            link.action, link.container
+        )
+    };
+    link_page(None, "", &message).into_response()
+}
+
+fn past_tense(action: &str) -> &str {
+    match action {
+        "start" => "started",
+        "stop" => "stopped",
+        _ => "restarted",
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Container data collection
+// ---------------------------------------------------------------------------
+
+fn common_filters() -> HashMap<String, Vec<String>> {
+    let mut filters = HashMap::new();
+    filters.insert(
+        "health".to_string(),
+        vec![
+            "starting".to_string(),
+            "healthy".to_string(),
+            "unhealthy".to_string(),
+            "none".to_string(),
+        ],
+    );
+    filters.insert(
+        "status".to_string(),
+        vec![
+            "created".to_string(),
+            "restarting".to_string(),
+            "running".to_string(),
+            "removing".to_string(),
+            "paused".to_string(),
+            "exited".to_string(),
+            "dead".to_string(),
+        ],
+    );
+    filters
+}
+
+/// Seconds from an RFC3339 time until now, if it's in the past.
+fn seconds_since(time: &str) -> Option<i64> {
+    let start = chrono_parse(time).ok()?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    (now >= start).then_some(now - start)
+}
+
+fn format_uptime(diff: i64) -> String {
+    let days = diff / 86400;
+    let hours = (diff % 86400) / 3600;
+    let mins = (diff % 3600) / 60;
+    if days > 0 {
+        format!("{}d {}h {}m", days, hours, mins)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, mins)
+    } else {
+        format!("{}m", mins)
+    }
+}
+
+/// Minimal RFC3339 parser — returns Unix timestamp (seconds).
+fn chrono_parse(s: &str) -> Result<i64, ()> {
+    // Expected format: 2024-01-15T10:30:00.123456789Z (or +00:00 offset)
+    let s = s.trim();
+    if s.len() < 19 {
+        return Err(());
+    }
+    let year: i64 = s[0..4].parse().map_err(|_| ())?;
+    let month: i64 = s[5..7].parse().map_err(|_| ())?;
+    let day: i64 = s[8..10].parse().map_err(|_| ())?;
+    let hour: i64 = s[11..13].parse().map_err(|_| ())?;
+    let min: i64 = s[14..16].parse().map_err(|_| ())?;
+    let sec: i64 = s[17..19].parse().map_err(|_| ())?;
+
+    // Rough days-since-epoch (good enough for uptime display)
+    fn days_from_year(y: i64) -> i64 {
+        365 * y + y / 4 - y / 100 + y / 400
+    }
+    let month_days: [i64; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+    let is_leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let leap_adj = if month > 2 && is_leap { 1 } else { 0 };
+    let epoch_days = days_from_year(year - 1) - days_from_year(1969)
+        + month_days[(month - 1) as usize]
+        + day
+        - 1
+        + leap_adj;
+    Ok(epoch_days * 86400 + hour * 3600 + min * 60 + sec)
+}
+
+fn extract_ports(inspect: &bollard::models::ContainerInspectResponse) -> Vec<PortMapping> {
+    let mut ports = Vec::new();
+    let bindings = inspect
+        .host_config
+        .as_ref()
+        .and_then(|hc| hc.port_bindings.as_ref());
+
+    if let Some(bindings) = bindings {
+        for (container_spec, host_bindings) in bindings {
+            // container_spec is like "25565/tcp"
+            let parts: Vec<&str> = container_spec.split('/').collect();
+            let container_port: u16 = parts.first().and_then(|p| p.parse().ok()).unwrap_or(0);
+            let protocol = parts.get(1).unwrap_or(&"tcp").to_string();
+
+            if let Some(Some(bindings_vec)) = host_bindings.as_ref().map(Some) {
+                for binding in bindings_vec {
+                    let host_port: u16 = binding
+                        .host_port
+                        .as_ref()
+                        .and_then(|p| p.parse().ok())
+                        .unwrap_or(0);
+                    if host_port > 0 && container_port > 0 {
+                        ports.push(PortMapping {
+                            host_port,
+                            container_port,
+                            protocol: protocol.clone(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    ports
+}
+
+/// Ports actually published by a running container, as reported by the list
+/// API. Docker lists IPv4 and IPv6 bindings separately, so duplicates are
+/// collapsed.
+fn extract_published_ports(summary: &bollard::models::ContainerSummary) -> Vec<PortMapping> {
+    let mut ports: Vec<PortMapping> = Vec::new();
+    for port in summary.ports.iter().flatten() {
+        let Some(host_port) = port.public_port else {
+            continue;
+        };
+        let protocol = port
+            .typ
+            .map(|t| t.to_string())
+            .filter(|t| !t.is_empty())
+            .unwrap_or_else(|| "tcp".to_string());
+        let duplicate = ports.iter().any(|p| {
+            p.host_port == host_port
+                && p.container_port == port.private_port
+                && p.protocol == protocol
+        });
+        if !duplicate {
+            ports.push(PortMapping {
+                host_port,
+                container_port: port.private_port,
+                protocol,
+            });
+        }
+    }
+    ports.sort_by_key(|p| (p.container_port, p.host_port));
+    ports
+}
+
+fn extract_mounts(inspect: &bollard::models::ContainerInspectResponse) -> Vec<MountInfo> {
+    inspect
+        .mounts
+        .as_ref()
+        .map(|mounts| {
+            mounts
+                .iter()
+                .map(|m| MountInfo {
+                    kind: m.typ.map(|t| t.to_string()).unwrap_or_default(),
+                    name: m.name.clone().unwrap_or_default(),
+                    source: m.source.clone().unwrap_or_default(),
+                    destination: m.destination.clone().unwrap_or_default(),
+                    read_only: !m.rw.unwrap_or(true),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn extract_networks(inspect: &bollard::models::ContainerInspectResponse) -> Vec<String> {
+    let mut networks: Vec<String> = inspect
+        .network_settings
+        .as_ref()
+        .and_then(|ns| ns.networks.as_ref())
+        .map(|n| n.keys().cloned().collect())
+        .unwrap_or_default();
+    networks.sort();
+    networks
+}
+
+fn compose_project(inspect: &bollard::models::ContainerInspectResponse) -> Option<String> {
+    inspect
+        .config
+        .as_ref()
+        .and_then(|c| c.labels.as_ref())
+        .and_then(|l| l.get(COMPOSE_PROJECT_LABEL).cloned())
+}
+
+fn find_minecraft_host_port(ports: &[PortMapping]) -> Option<u16> {
+    ports
+        .iter()
+        .find(|p| p.container_port == 25565)
+        .map(|p| p.host_port)
+}
+
+async fn ping_minecraft(host_port: u16, seed: &str) -> Option<MinecraftInfo> {
+    let result = tokio::time::timeout(std::time::Duration::from_secs(3), async {
+        let mut stream =
+            tokio::net::TcpStream::connect(format!("127.0.0.1:{}", host_port)).await?;
         craftping::tokio::ping(&mut stream, "127.0.0.1", host_port).await
     })
-    .await;
+    .await;
+
+    match result {
+        Ok(Ok(response)) => {
+            let players: Vec<PlayerInfo> = response
+                .sample
+                .unwrap_or_default()
+                .into_iter()
+                .map(|p| PlayerInfo {
+                    name: p.name,
+                    x: None,
+                    y: None,
+                    z: None,
+                })
+                .collect();
+
+            let mods = if let Some(mod_info) = &response.mod_info {
+                mod_info
+                    .mod_list
+                    .iter()
+                    .map(|m| ModEntry {
+                        id: m.mod_id.clone(),
+                        version: m.version.clone(),
+                    })
+                    .collect()
+            } else if let Some(forge_data) = &response.forge_data {
+                forge_data
+                    .mods
+                    .iter()
+                    .map(|m| ModEntry {
+                        id: m.mod_id.clone(),
+                        version: m.mod_marker.clone(),
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+
+            Some(MinecraftInfo {
+                version: response.version,
+                player_count: response.online_players as i32,
+                max_players: response.max_players as i32,
+                players,
+                mods,
+                seed: seed.to_string(),
+            })
+        }
+        Ok(Err(e)) => {
+            tracing::debug!("Minecraft ping failed on port {}: {}", host_port, e);
+            None
+        }
+        Err(_) => {
+            tracing::debug!("Minecraft ping timed out on port {}", host_port);
+            None
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// RCON — fetch player coordinates
+// ---------------------------------------------------------------------------
+
+fn find_rcon_host_port(ports: &[PortMapping]) -> Option<u16> {
+    ports
+        .iter()
+        .find(|p| p.container_port == 25575)
+        .map(|p| p.host_port)
+}
+
+/// Parse RCON response from `/data get entity <player> Pos`.
+/// Example: `PlayerName has the following entity data: [-123.45d, 64.0d, 789.12d]`
+fn parse_pos_response(response: &str) -> Option<(f64, f64, f64)> {
+    let bracket_start = response.find('[')?;
+    let bracket_end = response.find(']')?;
+    let inner = &response[bracket_start + 1..bracket_end];
+    let parts: Vec<&str> = inner.split(',').collect();
+    if parts.len() != 3 {
+        return None;
+    }
+    let x: f64 = parts[0].trim().trim_end_matches('d').parse().ok()?;
+    let y: f64 = parts[1].trim().trim_end_matches('d').parse().ok()?;
+    let z: f64 = parts[2].trim().trim_end_matches('d').parse().ok()?;
+    Some((x, y, z))
+}
+
+async fn fetch_player_positions(
+    rcon_port: u16,
+    rcon_password: &str,
+    players: &mut [PlayerInfo],
+) {
+    if players.is_empty() || rcon_password.is_empty() {
+        return;
+    }
+
+    let conn_result = tokio::time::timeout(std::time::Duration::from_secs(3), async {
+        let stream =
+            tokio::net::TcpStream::connect(format!("127.0.0.1:{}", rcon_port)).await?;
+        rcon::Builder::new()
+            .enable_minecraft_quirks(true)
+            .handshake(stream, rcon_password)
+            .await
+    })
+    .await;
+
+    let mut conn = match conn_result {
+        Ok(Ok(c)) => c,
+        Ok(Err(e)) => {
+            tracing::debug!("RCON connect failed on port {}: {}", rcon_port, e);
+            return;
+        }
+        Err(_) => {
+            tracing::debug!("RCON connect timed out on port {}", rcon_port);
+            return;
+        }
+    };
+
+    for player in players.iter_mut() {
+        let cmd = format!("data get entity {} Pos", player.name);
+        match tokio::time::timeout(std::time::Duration::from_secs(2), conn.cmd(&cmd)).await {
+            Ok(Ok(response)) => {
+                if let Some((x, y, z)) = parse_pos_response(&response) {
+                    player.x = Some(x);
+                    player.y = Some(y);
+                    player.z = Some(z);
+                }
+            }
+            Ok(Err(e)) => {
+                tracing::debug!("RCON cmd failed for '{}': {}", player.name, e);
+            }
+            Err(_) => {
+                tracing::debug!("RCON cmd timed out for '{}'", player.name);
+            }
+        }
+    }
+}
+
+async fn collect_container_info(docker: &Docker, name: &str) -> Option<ContainerInfo> {
+    // Inspect container
+    let inspect = docker
+        .inspect_container(name, None::<InspectContainerOptions>)
+        .await
+        .ok()?;
+
+    let state_obj = inspect.state.as_ref();
+    let state = state_obj
+        .and_then(|s| s.status)
+        .map(|s| format!("{:?}", s).to_lowercase())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    // Docker keeps the last health of stopped containers; it no longer applies
+    let health = if state == "running" {
+        state_obj
+            .and_then(|s| s.health.as_ref())
+            .and_then(|h| h.status)
+            .map(|s| format!("{:?}", s).to_lowercase())
+            .filter(|h| h != "none" && h != "empty")
+    } else {
+        None
+    };
+    let status_str = health.clone().unwrap_or_else(|| state.clone());
+
+    let image = inspect
+        .config
+        .as_ref()
+        .and_then(|c| c.image.clone())
+        .unwrap_or_default();
+
+    let created = inspect.created.clone().unwrap_or_default();
+
+    // Docker reports containers that never started as started in year one
+    let started_at = state_obj
+        .and_then(|s| s.started_at.clone())
+        .filter(|t| !t.starts_with("0001-"))
+        .unwrap_or_default();
+
+    let uptime_seconds = if state == "running" {
+        seconds_since(&started_at)
+    } else {
+        None
+    };
+    let uptime = uptime_seconds.map(format_uptime).unwrap_or_default();
+
+    let restart_count = inspect.restart_count.unwrap_or(0);
+    let exit_code = if state == "exited" || state == "dead" {
+        state_obj.and_then(|s| s.exit_code)
+    } else {
+        None
+    };
+
+    let ports = extract_ports(&inspect);
+    let mounts = extract_mounts(&inspect);
+    let networks = extract_networks(&inspect);
+    let project = compose_project(&inspect);
+
+    let running = state == "running";
+
+    // Only fetch stats for running containers — stats is expensive (cgroup reads)
+    let stats = if running {
+        get_container_stats(docker, name).await.unwrap_or_else(|e| {
+            tracing::debug!("Failed to get stats for '{}': {}", name, e);
+            ContainerStats::default()
+        })
+    } else {
+        ContainerStats::default()
+    };
+
+    // Extract env vars (itzg/minecraft-server convention)
+    let envs = inspect
+        .config
+        .as_ref()
+        .and_then(|c| c.env.as_ref());
+
+    let seed = envs
+        .and_then(|e| e.iter().find_map(|v| v.strip_prefix("SEED=").map(|s| s.to_string())))
+        .unwrap_or_default();
+
+    let rcon_password = envs
+        .and_then(|e| e.iter().find_map(|v| v.strip_prefix("RCON_PASSWORD=").map(|s| s.to_string())))
+        .unwrap_or_default();
+
+    // Mark as MC container (has port 25565) — actual ping data merged from mc_cache later
+    let is_mc = find_minecraft_host_port(&ports).is_some();
+    let minecraft = if is_mc {
+        Some(MinecraftInfo {
+            version: String::new(),
+            player_count: 0,
+            max_players: 0,
+            players: Vec::new(),
+            mods: Vec::new(),
+            seed,
+        })
+    } else {
+        None
+    };
+
+    Some(ContainerInfo {
+        name: name.to_string(),
+        state,
+        status: status_str,
+        health,
+        image,
+        created,
+        started_at,
+        uptime,
+        uptime_seconds,
+        ports,
+        mounts,
+        networks,
+        project,
+        cpu_percent: stats.cpu_percent,
+        memory_usage: stats.memory_usage,
+        memory_limit: stats.memory_limit,
+        network_rx_bytes: stats.network_rx_bytes,
+        network_tx_bytes: stats.network_tx_bytes,
+        restart_count,
+        exit_code,
+        update_available: false,
+        minecraft,
+        display: ContainerDisplay::default(),
+        rcon_password,
+    })
+}
+
+fn restart_policy_info(inspect: &bollard::models::ContainerInspectResponse) -> RestartPolicyInfo {
+    let policy = inspect
+        .host_config
+        .as_ref()
+        .and_then(|hc| hc.restart_policy.as_ref());
+    RestartPolicyInfo {
+        name: policy
+            .and_then(|p| p.name)
+            .map(|n| n.to_string())
+            .filter(|n| !n.is_empty())
+            .unwrap_or_else(|| "no".to_string()),
+        maximum_retry_count: policy.and_then(|p| p.maximum_retry_count).unwrap_or(0),
+    }
+}
+
+fn sanitize_inspect(
+    name: &str,
+    inspect: &bollard::models::ContainerInspectResponse,
+) -> ContainerInspect {
+    let state_obj = inspect.state.as_ref();
+    let state = state_obj
+        .and_then(|s| s.status)
+        .map(|s| format!("{:?}", s).to_lowercase())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let health = state_obj
+        .and_then(|s| s.health.as_ref())
+        .map(|h| HealthSummary {
+            status: h
+                .status
+                .map(|s| format!("{:?}", s).to_lowercase())
+                .unwrap_or_default(),
+            failing_streak: h.failing_streak.unwrap_or(0),
+        });
+
+    // Only expose variable names, never values
+    let env_keys = inspect
+        .config
+        .as_ref()
+        .and_then(|c| c.env.as_ref())
+        .map(|envs| {
+            envs.iter()
+                .map(|v| v.split('=').next().unwrap_or_default().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    ContainerInspect {
+        name: name.to_string(),
+        image: inspect
+            .config
+            .as_ref()
+            .and_then(|c| c.image.clone())
+            .unwrap_or_default(),
+        created: inspect.created.clone().unwrap_or_default(),
+        state,
+        restart_policy: restart_policy_info(inspect),
+        mounts: extract_mounts(inspect),
+        ports: extract_ports(inspect),
+        health,
+        env_keys,
+    }
+}
+
+/// Most recent healthcheck probes, newest first. Containers without a
+/// healthcheck report status `none` and no probes.
+fn health_detail(
+    inspect: &bollard::models::ContainerInspectResponse,
+    limit: usize,
+) -> HealthDetail {
+    let Some(health) = inspect.state.as_ref().and_then(|s| s.health.as_ref()) else {
+        return HealthDetail {
+            status: "none".to_string(),
+            failing_streak: 0,
+            probes: Vec::new(),
+        };
+    };
+
+    let probes = health
+        .log
+        .as_ref()
+        .map(|log| {
+            log.iter()
+                .rev()
+                .take(limit)
+                .map(|p| HealthProbe {
+                    start: p.start.clone().unwrap_or_default(),
+                    end: p.end.clone().unwrap_or_default(),
+                    exit_code: p.exit_code.unwrap_or(-1),
+                    output: p.output.clone().unwrap_or_default(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    HealthDetail {
+        status: health
+            .status
+            .map(|s| format!("{:?}", s).to_lowercase())
+            .unwrap_or_else(|| "none".to_string()),
+        failing_streak: health.failing_streak.unwrap_or(0),
+        probes,
+    }
+}
+
+async fn get_container_stats(
+    docker: &Docker,
+    name: &str,
+) -> Result<ContainerStats, bollard::errors::Error> {
+    use futures_util::StreamExt;
+
+    let stats_result = docker
+        .stats(
+            name,
+            Some(StatsOptions {
+                stream: false,
+                one_shot: true,
+            }),
+        )
+        .next()
+        .await;
+
+    match stats_result {
+        Some(Ok(stats)) => {
+            // CPU calculation
+            let cpu_delta = stats.cpu_stats.cpu_usage.total_usage as f64
+                - stats.precpu_stats.cpu_usage.total_usage as f64;
+            let system_delta = stats.cpu_stats.system_cpu_usage.unwrap_or(0) as f64
+                - stats.precpu_stats.system_cpu_usage.unwrap_or(0) as f64;
+            let num_cpus = stats
+                .cpu_stats
+                .cpu_usage
+                .percpu_usage
+                .as_ref()
+                .map(|v| v.len())
+                .unwrap_or(1) as f64;
+
+            let cpu_percent = if system_delta > 0.0 && cpu_delta > 0.0 {
+                (cpu_delta / system_delta) * num_cpus * 100.0
+            } else {
+                0.0
+            };
+
+            let memory_usage = stats.memory_stats.usage.unwrap_or(0);
+            let memory_limit = stats.memory_stats.limit.unwrap_or(0);
+
+            // Sum traffic over all attached networks
+            let (network_rx_bytes, network_tx_bytes) = stats
+                .networks
+                .as_ref()
+                .map(|nets| {
+                    nets.values()
+                        .fold((0, 0), |(rx, tx), n| (rx + n.rx_bytes, tx + n.tx_bytes))
+                })
+                .unwrap_or((0, 0));
+
+            Ok(ContainerStats {
+                cpu_percent,
+                memory_usage,
+                memory_limit,
+                network_rx_bytes,
+                network_tx_bytes,
+            })
+        }
+        Some(Err(e)) => Err(e),
+        None => Ok(ContainerStats::default()),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Background refresh task
+// ---------------------------------------------------------------------------
+
+async fn background_refresh(state: SharedState) {
+    let mut mc_tick: u32 = 0; // counts 5s ticks; ping MC every 6 ticks (30s)
+    let mut woken_by_event = false;
+
+    loop {
+        let docker = &state.docker;
+
+        let options = ListContainersOptions {
+            all: true,
+            filters: state.common_filters.clone(),
+            limit: Some(200),
+            size: false,
+        };
+
+        let containers = match docker.list_containers(Some(options)).await {
+            Ok(list) => list,
+            Err(e) => {
+                tracing::error!("Failed to list containers: {}", e);
+                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+
+        // Pick up containers that were created, removed or gained the opt-in label
+        let mut policy = current_policy(&state);
+        if policy.allow_label.is_some() || policy.denied_containers.is_some() {
+            let allowed = effective_allowlist(
+                &policy.listed_containers,
+                policy.allow_label.as_deref(),
+                policy.denied_containers.as_deref(),
+                &containers,
+            );
+            if allowed != policy.allowed_containers {
+                tracing::info!("Allowed containers: {:?}", allowed);
+                policy = update_allowed_containers(&state, &policy, allowed);
+            }
+        }
+        let allowed_set: HashSet<&str> = policy
+            .allowed_containers
+            .iter()
+            .map(|s| s.as_str())
+            .collect();
+
+        let names: Vec<String> = containers
+            .iter()
+            .filter_map(|c| {
+                c.names.as_ref().and_then(|names| {
+                    names
+                        .first()
+                        .map(|n| n.trim_start_matches('/').to_string())
+                })
+            })
+            .filter(|n| allowed_set.contains(n.as_str()))
+            .collect();
+
+        let published: HashMap<String, Vec<PortMapping>> = containers
+            .iter()
+            .filter_map(|c| {
+                let name = c
+                    .names
+                    .as_ref()?
+                    .first()?
+                    .trim_start_matches('/')
+                    .to_string();
+                Some((name, extract_published_ports(c)))
+            })
+            .collect();
+
+        let futures: Vec<_> = names
+            .iter()
+            .map(|name| collect_container_info(docker, name))
+            .collect();
+        let results = futures_util::future::join_all(futures).await;
+
+        let mut all_infos: Vec<ContainerInfo> = results.into_iter().flatten().collect();
+
+        let found_names: HashSet<String> = all_infos.iter().map(|i| i.name.clone()).collect();
+        for name in &policy.allowed_containers {
+            if !found_names.contains(name) {
+                if let Some(info) = collect_container_info(docker, name).await {
+                    all_infos.push(info);
+                }
+            }
+        }
+
+        // Prefer the live published ports over configured bindings; stopped
+        // containers publish nothing, so they keep their configured bindings
+        for info in &mut all_infos {
+            if let Some(ports) = published.get(&info.name) {
+                if !ports.is_empty() {
+                    info.ports = ports.clone();
+                }
+            }
+        }
+
+        all_infos.sort_by(|a, b| a.name.cmp(&b.name));
+
+        // MC ping on a 30s cycle (every 6th tick), or on first tick
+        #[allow(clippy::manual_is_multiple_of)]
+        let do_mc_ping = mc_tick % 6 == 0;
+        if do_mc_ping {
+            let mc_cache = state.mc_cache.read().await;
+            // Determine which containers need a ping:
+            // - must be running
+            // - must be MC (has minecraft field)
+            // - either first ping (not in cache) or has players connected
+            // (container_name, mc_port, seed, rcon_port, rcon_password)
+            let to_ping: Vec<(String, u16, String, Option<u16>, String)> = all_infos
+                .iter()
+                .filter(|c| c.state == "running" && c.minecraft.is_some())
+                .filter_map(|c| {
+                    find_minecraft_host_port(&c.ports).map(|port| {
+                        let seed = c
+                            .minecraft
+                            .as_ref()
+                            .map(|m| m.seed.clone())
+                            .unwrap_or_default();
+                        let rcon_port = find_rcon_host_port(&c.ports);
+                        (c.name.clone(), port, seed, rcon_port, c.rcon_password.clone())
+                    })
+                })
+                .filter(|(name, _, _, _, _)| {
+                    match mc_cache.get(name.as_str()) {
+                        None => true,
+                        Some(cached) => cached.player_count > 0,
+                    }
+                })
+                .collect();
+            drop(mc_cache);
+
+            for (name, port, seed, rcon_port, rcon_pass) in &to_ping {
+                if let Some(mut mc_info) = ping_minecraft(*port, seed).await {
+                    // Fetch player positions via RCON and log them
+                    if let Some(rp) = rcon_port {
+                        if !rcon_pass.is_empty() && !mc_info.players.is_empty() {
+                            fetch_player_positions(*rp, rcon_pass, &mut mc_info.players).await;
+                            for p in &mc_info.players {
+                                if let (Some(x), Some(y), Some(z)) = (p.x, p.y, p.z) {
+                                    tracing::info!(
+                                        "[{}] Player '{}' at x={:.1} y={:.1} z={:.1}",
+                                        name, p.name, x, y, z
+                                    );
+                                }
+                            }
+                        }
+                    }
+                    let mut mc_cache = state.mc_cache.write().await;
+                    mc_cache.insert(name.clone(), mc_info);
+                }
+            }
+        }
+        mc_tick = mc_tick.wrapping_add(1);
+
+        // Merge cached MC data into container infos
+        {
+            let mc_cache = state.mc_cache.read().await;
+            for info in &mut all_infos {
+                if info.minecraft.is_some() {
+                    if let Some(cached) = mc_cache.get(&info.name) {
+                        // Preserve seed from inspect, overlay ping data
+                        let seed = info
+                            .minecraft
+                            .as_ref()
+                            .map(|m| m.seed.clone())
+                            .unwrap_or_default();
+                        info.minecraft = Some(MinecraftInfo {
+                            seed,
+                            ..cached.clone()
+                        });
+                    }
+                }
+            }
+        }
+
+        {
+            let updates = state.update_cache.read().await;
+            for info in &mut all_infos {
+                info.update_available = updates.get(&info.name).copied().unwrap_or(false);
+            }
+        }
+
+        for info in &mut all_infos {
+            info.display = policy.display.get(&info.name).cloned().unwrap_or_default();
+        }
+
+        if !state.show_stopped {
+            all_infos
+                .retain(|info| matches!(info.state.as_str(), "running" | "paused" | "restarting"));
+        }
+
+        {
+            let mut cache = state.container_cache.write().await;
+            *cache = all_infos;
+        }
+        state
+            .cache_generation
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        // Only changes are pushed, not the usage figures of every refresh
+        if woken_by_event {
+            let _ = state.list_changed.send(());
+        }
+
+        woken_by_event = tokio::select! {
+            _ = tokio::time::sleep(std::time::Duration::from_secs(5)) => false,
+            _ = state.refresh_now.notified() => true,
+        };
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Update detection — compare local image digests with the registry
+// ---------------------------------------------------------------------------
+
+/// Whether the registry has a different digest for the tag a container runs.
+/// Returns `None` when this can't be determined, e.g. for locally built
+/// images, digest-pinned references or unreachable registries.
+async fn check_image_update(docker: &Docker, name: &str) -> Option<bool> {
+    let inspect = docker
+        .inspect_container(name, None::<InspectContainerOptions>)
+        .await
+        .ok()?;
+    let reference = inspect.config.as_ref()?.image.clone()?;
+    if reference.starts_with("sha256:") || reference.contains('@') {
+        return None;
+    }
+
+    let image = docker.inspect_image(inspect.image.as_deref()?).await.ok()?;
+    let local_digests = image.repo_digests.unwrap_or_default();
+    if local_digests.is_empty() {
+        return None;
+    }
+
+    let remote = match docker.inspect_registry_image(&reference, None).await {
+        Ok(remote) => remote,
+        Err(e) => {
+            tracing::debug!("Registry lookup for '{}' failed: {}", reference, e);
+            return None;
+        }
+    };
+    let remote_digest = remote.descriptor.digest?;
+
+    Some(
+        !local_digests
+            .iter()
+            .any(|d| d.ends_with(&format!("@{}", remote_digest))),
+    )
+}
+
+async fn update_checker(state: SharedState) {
+    if state.update_check_interval == 0 {
+        return;
+    }
+
+    loop {
+        let policy = current_policy(&state);
+        for name in &policy.allowed_containers {
+            if let Some(available) = check_image_update(&state.docker, name).await {
+                if available {
+                    tracing::info!("Update available for '{}'", name);
+                }
+                state
+                    .update_cache
+                    .write()
+                    .await
+                    .insert(name.clone(), available);
+            }
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(
+            state.update_check_interval * 60,
+        ))
+        .await;
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Docker events
+// ---------------------------------------------------------------------------
+
+/// Number of events kept in the in-memory event log.
+const EVENT_LOG_SIZE: usize = 500;
+
+/// Docker event actions that change what the container list shows.
+/// `health_status` events carry the status after a colon.
+const LIST_EVENTS: [&str; 10] = [
+    "create",
+    "start",
+    "restart",
+    "stop",
+    "die",
+    "pause",
+    "unpause",
+    "destroy",
+    "rename",
+    "health_status",
+];
+
+/// Follow the Docker event stream and record events for allowed containers.
+/// Reconnects after a short delay if the stream ends or fails.
+async fn watch_events(state: SharedState) {
+    use futures_util::StreamExt;
+
+    loop {
+        let mut filters = HashMap::new();
+        filters.insert("type".to_string(), vec!["container".to_string()]);
+        let options = EventsOptions::<String> {
+            filters,
+            ..Default::default()
+        };
+        let mut stream = state.docker.events(Some(options));
+
+        while let Some(item) = stream.next().await {
+            let message = match item {
+                Ok(m) => m,
+                Err(e) => {
+                    tracing::error!("Docker event stream failed: {}", e);
+                    break;
+                }
+            };
+
+            let attributes = message.actor.and_then(|a| a.attributes).unwrap_or_default();
+            let Some(container) = attributes.get("name") else {
+                continue;
+            };
+            if !is_container_allowed(&state, container) {
+                continue;
+            }
+
+            let event = DockerEvent {
+                time: message.time.unwrap_or_default(),
+                container: container.clone(),
+                action: message.action.unwrap_or_default(),
+                exit_code: attributes.get("exitCode").cloned(),
+            };
+            tracing::debug!("Docker event: {} {}", event.container, event.action);
+
+            {
+                let mut log = state.event_log.write().await;
+                if log.len() == EVENT_LOG_SIZE {
+                    log.pop_front();
+                }
+                log.push_back(event.clone());
+            }
+            if LIST_EVENTS.iter().any(|a| event.action.starts_with(a)) {
+                state.refresh_now.notify_one();
+            }
+            // No subscribers is fine
+            let _ = state.event_tx.send(event);
+        }
+
+        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+    }
+}
+
+async fn events_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<EventsQuery>,
+) -> Json<Vec<DockerEvent>> {
+    let log = state.event_log.read().await;
+    let mut events: Vec<DockerEvent> = log
+        .iter()
+        .rev()
+        .filter(|e| query.container.as_ref().is_none_or(|c| &e.container == c))
+        .filter(|e| caller_may_access(&state, &e.container))
+        .take(query.limit.unwrap_or(100))
+        .cloned()
+        .collect();
+    events.reverse();
+    Json(events)
+}
+
+/// Server-Sent Events stream of Docker events as they happen.
+async fn events_stream_handle(
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> Sse<impl futures_util::Stream<Item = Result<Event, std::convert::Infallible>>> {
+    tracing::debug!("Event stream opened from {}", addr);
+
+    // The stream outlives the request, so remember whose it is
+    let caller = current_caller();
+    let initial = (state.event_tx.subscribe(), state, caller);
+    let stream = futures_util::stream::unfold(initial, |(mut rx, state, caller)| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) if !may_access(&state, caller.as_ref(), &event.container) => {}
+                Ok(event) => {
+                    let sse = Event::default()
+                        .event("docker")
+                        .json_data(&event)
+                        .unwrap_or_default();
+                    return Some((Ok(sse), (rx, state, caller)));
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::debug!("Event stream client lagged, skipped {} events", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// The containers `caller` may see, as a JSON array like `/containers/statuses`.
+async fn container_list_json(state: &AppState, caller: Option<&Identity>) -> String {
+    let cache = state.container_cache.read().await;
+    let visible: Vec<&ContainerInfo> = cache
+        .iter()
+        .filter(|c| may_access(state, caller, &c.name))
+        .collect();
+    serde_json::to_string(&visible).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// WebSocket sending the container list when it opens and again whenever a
+/// Docker event changed a container, such as a start, stop or health change.
+async fn containers_ws_handle(
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: axum::http::HeaderMap,
+    ws: WebSocketUpgrade,
+) -> axum::response::Response {
+    if !websocket_origin_allowed(&state, &headers) {
+        return websocket_origin_rejected(&headers);
+    }
+    tracing::debug!("Container list socket opened from {}", addr);
+    // The socket outlives the request, so remember whose it is
+    let caller = current_caller();
+    ws.on_upgrade(move |socket| push_container_list(state, caller, socket))
+}
+
+async fn push_container_list(state: SharedState, caller: Option<Identity>, mut socket: WebSocket) {
+    let mut rx = state.list_changed.subscribe();
+    let mut last = String::new();
+    loop {
+        let list = container_list_json(&state, caller.as_ref()).await;
+        // Events that don't change what this caller sees aren't pushed
+        if list != last {
+            if socket
+                .send(Message::Text(list.clone().into()))
+                .await
+                .is_err()
+            {
+                return;
+            }
+            last = list;
+        }
+
+        tokio::select! {
+            changed = rx.recv() => match changed {
+                Ok(()) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Close(_))) | None | Some(Err(_)) => {
+                    tracing::debug!("Container list socket closed");
+                    return;
+                }
+                Some(Ok(_)) => continue,
+            },
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Graceful shutdown
+// ---------------------------------------------------------------------------
+
+/// Longest requests in progress may keep running after a shutdown signal.
+/// Streams such as live logs would otherwise hold the server open forever.
+const SHUTDOWN_GRACE_SECS: u64 = 30;
+
+/// Resolves on SIGTERM or SIGINT, which `docker stop`, systemd and Ctrl-C
+/// send.
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let terminate = async {
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
+    tracing::info!(
+        "Shutting down, waiting up to {}s for requests in progress",
+        SHUTDOWN_GRACE_SECS
+    );
+}
+
+/// Write out everything appended to the audit log, so entries of the last
+/// requests survive the exit.
+async fn flush_audit_log(state: &AppState) {
+    use tokio::io::AsyncWriteExt;
+
+    let Some((path, file)) = &state.audit_log else {
+        return;
+    };
+    let mut file = file.lock().await;
+    if let Err(e) = file.flush().await {
+        tracing::error!("Failed to flush audit log '{}': {}", path, e);
+    }
+    if let Err(e) = file.sync_all().await {
+        tracing::error!("Failed to sync audit log '{}': {}", path, e);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Configuration reload
+// ---------------------------------------------------------------------------
+
+/// Rebuild the policy on SIGHUP, so allowlist and config changes apply
+/// without a restart. If the new configuration doesn't load, the current
+/// one stays in place. Server settings such as the port still need a restart.
+async fn reload_on_sighup(state: SharedState, args: Args) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            tracing::error!("Failed to listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        tracing::info!("SIGHUP received, reloading configuration");
+        let policy = match load_configuration(&args) {
+            Ok(config) => load_policy(&args, &config, &state.docker).await,
+            Err(e) => Err(e),
+        };
+        match policy {
+            Ok(policy) => {
+                *state.policy.write().expect("Policy lock poisoned") = Arc::new(policy);
+                tracing::info!("Configuration reloaded");
+            }
+            Err(e) => tracing::error!("Reload failed, keeping the current configuration: {}", e),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// API v1 — resource paths, served by the original routes
+// ---------------------------------------------------------------------------
+
+/// `/api/v1` routes: method, path below `/api/v1` with `{parameter}`
+/// segments, the route serving it, which gets the parameters in its query,
+/// and a summary for the API documentation. `GET` routes are served by
+/// `GET` routes and all others by `POST` routes. Earlier paths match first.
+const API_V1_ROUTES: [(&str, &str, &str, &str); 55] = [
+    (
+        "GET",
+        "/containers",
+        "/containers/statuses",
+        "List allowed containers with state, health, usage and ports",
+    ),
+    (
+        "POST",
+        "/containers/stop-all",
+        "/containers/stop-all",
+        "Stop every allowed container",
+    ),
+    (
+        "POST",
+        "/containers/batch",
+        "/containers/batch",
+        "Run start, stop, restart, pause or unpause on several containers",
+    ),
+    (
+        "GET",
+        "/containers/{name}",
+        "/containers/inspect",
+        "Inspect a container",
+    ),
+    (
+        "POST",
+        "/containers/{name}/start",
+        "/containers/start",
+        "Start a container after its dependencies",
+    ),
+    (
+        "POST",
+        "/containers/{name}/stop",
+        "/containers/stop",
+        "Stop a container",
+    ),
+    (
+        "POST",
+        "/containers/{name}/restart",
+        "/containers/restart",
+        "Restart a container",
+    ),
+    (
+        "POST",
+        "/containers/{name}/pause",
+        "/containers/pause",
+        "Pause a container",
+    ),
+    (
+        "POST",
+        "/containers/{name}/unpause",
+        "/containers/unpause",
+        "Unpause a container",
+    ),
+    (
+        "POST",
+        "/containers/{name}/kill",
+        "/containers/kill",
+        "Send a signal to a container",
+    ),
+    (
+        "POST",
+        "/containers/{name}/recreate",
+        "/containers/recreate",
+        "Recreate a container from its image and configuration",
+    ),
+    (
+        "POST",
+        "/containers/{name}/update",
+        "/containers/update",
+        "Pull a container's image and recreate it if it changed",
+    ),
+    (
+        "GET",
+        "/containers/{name}/wait",
+        "/containers/wait",
+        "Wait until a container reaches a state",
+    ),
+    (
+        "GET",
+        "/containers/{name}/health",
+        "/containers/health",
+        "Recent healthcheck results",
+    ),
+    (
+        "GET",
+        "/containers/{name}/processes",
+        "/containers/processes",
+        "Processes running in a container",
+    ),
+    (
+        "GET",
+        "/containers/{name}/diff",
+        "/containers/diff",
+        "Filesystem changes of a container",
+    ),
+    (
+        "GET",
+        "/containers/{name}/restart-policy",
+        "/containers/restart-policy",
+        "A container's restart policy",
+    ),
+    (
+        "PUT",
+        "/containers/{name}/restart-policy",
+        "/containers/restart-policy/set",
+        "Change a container's restart policy",
+    ),
+    (
+        "PUT",
+        "/containers/{name}/resources",
+        "/containers/resources",
+        "Change a container's CPU shares and memory limit",
+    ),
+    (
+        "GET",
+        "/containers/{name}/commands",
+        "/containers/commands",
+        "Commands that may run in a container",
+    ),
+    (
+        "POST",
+        "/containers/{name}/commands/{command}",
+        "/containers/exec",
+        "Run a predefined command",
+    ),
+    (
+        "GET",
+        "/containers/{name}/downloads",
+        "/containers/downloads",
+        "Paths that may be downloaded from a container",
+    ),
+    (
+        "GET",
+        "/containers/{name}/downloads/{file}",
+        "/containers/download",
+        "Download a path as a tar archive",
+    ),
+    (
+        "GET",
+        "/containers/{name}/export",
+        "/containers/export",
+        "Export a container's filesystem as a tar archive",
+    ),
+    (
+        "GET",
+        "/containers/{name}/stats",
+        "/containers/stats",
+        "CPU, memory and network usage of a container",
+    ),
+    (
+        "GET",
+        "/containers/{name}/logs",
+        "/containers/logs",
+        "A container's logs",
+    ),
+    (
+        "GET",
+        "/containers/{name}/logs/stream",
+        "/containers/logs/stream",
+        "Follow a container's logs over a WebSocket",
+    ),
+    (
+        "GET",
+        "/containers/{name}/volumes/{volume}/backup",
+        "/volumes/backup",
+        "Back up a named volume as a tar archive",
+    ),
+    (
+        "PUT",
+        "/containers/{name}/networks/{network}",
+        "/networks/connect",
+        "Connect a container to a network",
+    ),
+    (
+        "DELETE",
+        "/containers/{name}/networks/{network}",
+        "/networks/disconnect",
+        "Disconnect a container from a network",
+    ),
+    (
+        "POST",
+        "/containers/{name}/image/pull",
+        "/images/pull",
+        "Pull a container's image, streaming progress as Server-Sent Events",
+    ),
+    (
+        "GET",
+        "/containers/{name}/image/history",
+        "/images/history",
+        "Layers of a container's image",
+    ),
+    (
+        "GET",
+        "/stats/stream",
+        "/containers/stats/stream",
+        "Usage of every allowed container as Server-Sent Events",
+    ),
+    (
+        "GET",
+        "/volumes",
+        "/volumes",
+        "Volumes and bind mounts of allowed containers",
+    ),
+    (
+        "GET",
+        "/networks",
+        "/networks",
+        "Networks of allowed containers",
+    ),
+    (
+        "GET",
+        "/images",
+        "/images",
+        "Images used by allowed containers",
+    ),
+    (
+        "POST",
+        "/images/prune",
+        "/images/prune",
+        "Remove dangling images",
+    ),
+    ("GET", "/projects", "/projects", "Compose projects"),
+    (
+        "POST",
+        "/projects/{name}/start",
+        "/projects/start",
+        "Start a compose project",
+    ),
+    (
+        "POST",
+        "/projects/{name}/stop",
+        "/projects/stop",
+        "Stop a compose project",
+    ),
+    ("GET", "/profiles", "/profiles", "Profiles"),
+    (
+        "POST",
+        "/profiles/{name}/activate",
+        "/profiles/activate",
+        "Activate a profile",
+    ),
+    ("GET", "/groups", "/groups", "Named groups"),
+    (
+        "POST",
+        "/groups/{name}/start",
+        "/groups/start",
+        "Start a group",
+    ),
+    (
+        "POST",
+        "/groups/{name}/stop",
+        "/groups/stop",
+        "Stop a group",
+    ),
+    (
+        "POST",
+        "/groups/{name}/restart",
+        "/groups/restart",
+        "Restart a group",
+    ),
+    ("GET", "/services", "/services", "Swarm services"),
+    (
+        "PUT",
+        "/services/{name}/replicas",
+        "/services/scale",
+        "Scale a swarm service",
+    ),
+    ("GET", "/stacks", "/stacks", "Stacks"),
+    ("POST", "/stacks/{name}", "/stacks/deploy", "Deploy a stack"),
+    (
+        "DELETE",
+        "/stacks/{name}",
+        "/stacks/teardown",
+        "Tear down a stack",
+    ),
+    ("GET", "/system/df", "/system/df", "Docker disk usage"),
+    ("GET", "/events", "/events", "Recent Docker events"),
+    (
+        "GET",
+        "/events/stream",
+        "/events/stream",
+        "Docker events as Server-Sent Events",
+    ),
+    ("GET", "/audit", "/audit", "Audit log entries"),
+];
+
+/// `/api/v1` routes for changing docker-direct itself.
+const API_V1_ADMIN_ROUTES: [(&str, &str, &str, &str); 3] = [
+    (
+        "PATCH",
+        "/allowlist",
+        "/admin/allowlist",
+        "Change the allowlist",
+    ),
+    (
+        "GET",
+        "/allowlist/validate",
+        "/admin/allowlist/validate",
+        "Allowlist entries matching no container",
+    ),
+    (
+        "POST",
+        "/links",
+        "/admin/links",
+        "Create a one-time action link",
+    ),
+];
+
+/// The parameters of `path` if it matches `pattern`, as `name=value` query
+/// pairs. Path segments are percent-encoded like query values, except that
+/// `+`, `&` and `=` may appear unencoded.
+fn match_api_path(pattern: &str, path: &str) -> Option<Vec<String>> {
+    let pattern: Vec<&str> = pattern.split('/').collect();
+    let path: Vec<&str> = path.split('/').collect();
+    if pattern.len() != path.len() {
+        return None;
+    }
+    let mut params = Vec::new();
+    for (expected, segment) in pattern.iter().zip(&path) {
+        match expected.strip_prefix('{').and_then(|p| p.strip_suffix('}')) {
+            Some(_) if segment.is_empty() => return None,
+            Some(param) => {
+                let value = segment
+                    .replace('+', "%2B")
+                    .replace('&', "%26")
+                    .replace('=', "%3D");
+                params.push(format!("{}={}", param, value));
+            }
+            None if expected != segment => return None,
+            None => {}
+        }
+    }
+    Some(params)
+}
+
+/// Serve `/api/v1` requests with the routes they stand for, and mark
+/// responses to those routes' own paths as deprecated. Runs before routing,
+/// so the role, audit and rate limit checks see the original routes.
+async fn route_api_v1(
+    mut request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let path = request.uri().path().to_string();
+    let Some(api_path) = path.strip_prefix("/api/v1") else {
+        let method = request.method().clone();
+        let deprecated = API_V1_ROUTES
+            .iter()
+            .chain(&API_V1_ADMIN_ROUTES)
+            .any(|(_, _, target, _)| *target == path);
+        let mut response = next.run(request).await;
+        if deprecated {
+            tracing::debug!("Deprecated route {} {} used", method, path);
+            response
+                .headers_mut()
+                .insert("deprecation", axum::http::HeaderValue::from_static("true"));
+        }
+        return response;
+    };
+
+    let method = match request.method().as_str() {
+        "HEAD" => "GET".to_string(),
+        method => method.to_string(),
+    };
+    let mut allowed = Vec::new();
+    for (route_method, pattern, target, _) in API_V1_ROUTES.iter().chain(&API_V1_ADMIN_ROUTES) {
+        let Some(mut params) = match_api_path(pattern, api_path) else {
+            continue;
+        };
+        if *route_method != method.as_str() {
+            allowed.push(*route_method);
+            continue;
+        }
+        params.extend(request.uri().query().map(str::to_string));
+        let uri = if params.is_empty() {
+            target.to_string()
+        } else {
+            format!("{}?{}", target, params.join("&"))
+        };
+        let Ok(uri) = uri.parse() else {
+            return json_error(StatusCode::BAD_REQUEST, "Invalid request path").into_response();
+        };
+        *request.uri_mut() = uri;
+        if method != "GET" {
+            *request.method_mut() = axum::http::Method::POST;
+        }
+        return next.run(request).await;
+    }
+
+    if allowed.is_empty() {
+        return json_error(StatusCode::NOT_FOUND, "No such API route").into_response();
+    }
+    (
+        StatusCode::METHOD_NOT_ALLOWED,
+        [(axum::http::header::ALLOW, allowed.join(", "))],
+        error_body("method_not_allowed", "Method not allowed", None),
+    )
+        .into_response()
+}
+
+// ---------------------------------------------------------------------------
+// API documentation — OpenAPI generated from the v1 routes
+// ---------------------------------------------------------------------------
+
+/// Query parameters of the routes serving `/api/v1`: route, parameter,
+/// whether it is required, and its description.
+const API_V1_QUERY_PARAMETERS: [(&str, &str, bool, &str); 33] = [
+    (
+        "/containers/statuses",
+        "state",
+        false,
+        "Only containers in this state, e.g. running",
+    ),
+    (
+        "/containers/statuses",
+        "q",
+        false,
+        "Only containers whose name contains this",
+    ),
+    (
+        "/containers/statuses",
+        "sort",
+        false,
+        "name (default) or status",
+    ),
+    (
+        "/containers/statuses",
+        "page",
+        false,
+        "Page to return, starting at 1",
+    ),
+    (
+        "/containers/statuses",
+        "per_page",
+        false,
+        "Containers per page (default 50, at most 500)",
+    ),
+    (
+        "/containers/start",
+        "wait_for",
+        false,
+        "running, healthy or exited; answer once reached",
+    ),
+    (
+        "/containers/start",
+        "timeout",
+        false,
+        "Seconds to wait, at most 300 (default 60)",
+    ),
+    (
+        "/containers/stop",
+        "wait_for",
+        false,
+        "exited, running or healthy; answer once reached",
+    ),
+    (
+        "/containers/stop",
+        "timeout",
+        false,
+        "Seconds to wait, at most 300 (default 60)",
+    ),
+    (
+        "/containers/kill",
+        "signal",
+        false,
+        "SIGTERM, SIGKILL (default) or SIGHUP",
+    ),
+    (
+        "/containers/update",
+        "force",
+        false,
+        "Recreate even if the image didn't change",
+    ),
+    (
+        "/containers/wait",
+        "state",
+        true,
+        "exited, running or healthy",
+    ),
+    (
+        "/containers/wait",
+        "timeout",
+        false,
+        "Seconds to wait, at most 300 (default 60)",
+    ),
+    (
+        "/containers/health",
+        "limit",
+        false,
+        "Number of most recent results (default 5)",
+    ),
+    (
+        "/containers/restart-policy/set",
+        "policy",
+        true,
+        "no, on-failure, unless-stopped or always",
+    ),
+    (
+        "/containers/restart-policy/set",
+        "max_retries",
+        false,
+        "Retries for on-failure",
+    ),
+    (
+        "/containers/resources",
+        "cpu_shares",
+        false,
+        "Relative CPU weight",
+    ),
+    (
+        "/containers/resources",
+        "memory_mb",
+        false,
+        "Memory limit in MiB",
+    ),
+    (
+        "/containers/logs",
+        "tail",
+        false,
+        "Number of lines from the end, or all (default 200)",
+    ),
+    (
+        "/containers/logs",
+        "since",
+        false,
+        "Unix timestamp or RFC3339 time to start from",
+    ),
+    (
+        "/containers/logs",
+        "format",
+        false,
+        "text (default) or json",
+    ),
+    (
+        "/containers/logs/stream",
+        "tail",
+        false,
+        "Number of earlier lines to send first",
+    ),
+    (
+        "/services/scale",
+        "replicas",
+        true,
+        "Number of tasks; 0 stops all",
+    ),
+    (
+        "/stacks/teardown",
+        "volumes",
+        false,
+        "Also remove the stack's volumes",
+    ),
+    (
+        "/events",
+        "container",
+        false,
+        "Only events of this container",
+    ),
+    (
+        "/events",
+        "limit",
+        false,
+        "Number of most recent events (default 100)",
+    ),
+    (
+        "/audit",
+        "container",
+        false,
+        "Only entries for this container",
+    ),
+    (
+        "/audit",
+        "user",
+        false,
+        "Only entries by this user or API key",
+    ),
+    (
+        "/audit",
+        "since",
+        false,
+        "Unix timestamp; only entries at or after it",
+    ),
+    (
+        "/audit",
+        "limit",
+        false,
+        "Number of most recent entries (default 100)",
+    ),
+    ("/admin/links", "name", true, "Container the link acts on"),
+    ("/admin/links", "action", true, "start, stop or restart"),
+    (
+        "/admin/links",
+        "minutes",
+        false,
+        "How long the link works (default 1440)",
+    ),
+];
+
+fn path_parameter_description(path: &str, parameter: &str) -> String {
+    match parameter {
+        "command" => "Name of a command from the exec file".to_string(),
+        "file" => "Name of a path from the download file".to_string(),
+        "volume" => "Name of a volume mounted by the container".to_string(),
+        "network" => "Name of a network configured with --networks".to_string(),
+        _ => {
+            let resource = path.split('/').nth(1).unwrap_or_default();
+            format!("Name of the {}", resource.trim_end_matches('s'))
+        }
+    }
+}
+
+/// The OpenAPI 3 description of `/api/v1`.
+fn openapi_document() -> serde_json::Value {
+    let mut paths = serde_json::Map::new();
+    for (method, path, target, summary) in API_V1_ROUTES.iter().chain(&API_V1_ADMIN_ROUTES) {
+        let mut parameters: Vec<serde_json::Value> = path
+            .split('/')
+            .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+            .map(|name| {
+                serde_json::json!({
+                    "name": name,
+                    "in": "path",
+                    "required": true,
+                    "description": path_parameter_description(path, name),
+                    "schema": { "type": "string" },
+                })
+            })
+            .collect();
+        parameters.extend(
+            API_V1_QUERY_PARAMETERS
+                .iter()
+                .filter(|(route, ..)| route == target)
+                .map(|(_, name, required, description)| {
+                    serde_json::json!({
+                        "name": name,
+                        "in": "query",
+                        "required": required,
+                        "description": description,
+                        "schema": { "type": "string" },
+                    })
+                }),
+        );
+
+        let role = role_name(required_role(target));
+        let mut operation = serde_json::json!({
+            "summary": summary,
+            "description": format!("Requires the {} role.", role),
+            "tags": [path.split('/').nth(1).unwrap_or_default()],
+            "parameters": parameters,
+            "responses": {
+                "200": { "description": "Success" },
+                "400": { "description": "Invalid parameters" },
+                "401": { "description": "Authentication required" },
+                "403": { "description": "Container not allowed, or role too low" },
+                "429": { "description": "Too many requests" },
+            },
+        });
+        let body = match *target {
+            "/admin/allowlist" => Some(serde_json::json!({
+                "required": true,
+                "content": { "application/json": { "schema": {
+                    "type": "object",
+                    "properties": {
+                        "add": { "type": "array", "items": { "type": "string" } },
+                        "remove": { "type": "array", "items": { "type": "string" } },
+                        "persist": { "type": "boolean" },
+                    },
+                } } },
+            })),
+            "/containers/batch" => Some(serde_json::json!({
+                "required": true,
+                "content": { "application/json": { "schema": {
+                    "type": "array",
+                    "maxItems": MAX_BATCH_ITEMS,
+                    "items": {
+                        "type": "object",
+                        "required": ["name", "action"],
+                        "properties": {
+                            "name": { "type": "string" },
+                            "action": { "type": "string", "enum": BATCH_ACTIONS },
+                        },
+                    },
+                } } },
+            })),
+            "/stacks/deploy" => Some(serde_json::json!({
+                "description": "Compose file in YAML, or JSON, which is valid YAML too; \
+                    the configured one when empty",
+                "content": {
+                    "application/yaml": { "schema": { "type": "object" } },
+                    "application/json": { "schema": { "type": "object" } },
+                },
+            })),
+            _ => None,
+        };
+        if let Some(body) = body {
+            operation["requestBody"] = body;
+        }
+
+        let entry = paths
+            .entry(path.to_string())
+            .or_insert_with(|| serde_json::json!({}));
+        entry[method.to_lowercase()] = operation;
+    }
+
+    serde_json::json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "docker-direct",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Start, stop and inspect allowed Docker containers.",
+        },
+        "servers": [{ "url": "/api/v1" }],
+        "components": {
+            "securitySchemes": {
+                "apiKey": { "type": "apiKey", "in": "header", "name": "X-Api-Key" },
+                "bearer": { "type": "http", "scheme": "bearer" },
+                "basic": { "type": "http", "scheme": "basic" },
+            },
+        },
+        "security": [{ "apiKey": [] }, { "bearer": [] }, { "basic": [] }],
+        "paths": paths,
+    })
+}
+
+async fn openapi_handle() -> Json<serde_json::Value> {
+    Json(openapi_document())
+}
+
+/// Swagger UI for the OpenAPI document, sending the CSRF token so requests
+/// tried out from it are accepted.
+async fn api_docs_handle(
+    State(state): State<SharedState>,
+    headers: axum::http::HeaderMap,
+) -> Html<String> {
+    let template = ApiDocsTemplate {
+        csrf_token: csrf_token(&state, &headers),
+    };
+    Html(template.render().unwrap_or_default())
+}
+
+// ---------------------------------------------------------------------------
+// Route handlers
+// ---------------------------------------------------------------------------
+
+/// The weight an `Accept` header gives `media_type`, counting `type/*` and
+/// `*/*` when it isn't named. `0` when it isn't acceptable.
+fn accept_weight(accept: &str, media_type: &str) -> f32 {
+    let main_type = media_type.split('/').next().unwrap_or_default();
+    let mut best: Option<(u8, f32)> = None;
+    for entry in accept.split(',') {
+        let mut parts = entry.split(';').map(str::trim);
+        let range = parts.next().unwrap_or_default();
+        let specificity = if range.eq_ignore_ascii_case(media_type) {
+            2
+        } else if range.eq_ignore_ascii_case(&format!("{}/*", main_type)) {
+            1
+        } else if range == "*/*" {
+            0
+        } else {
+            continue;
+        };
+        let weight = parts
+            .filter_map(|p| p.strip_prefix("q="))
+            .find_map(|q| q.parse().ok())
+            .unwrap_or(1.0);
+        // The most specific range decides, as in RFC 9110
+        if best.is_none_or(|(s, _)| specificity > s) {
+            best = Some((specificity, weight));
+        }
+    }
+    best.map_or(0.0, |(_, weight)| weight)
+}
+
+/// Whether the client would rather have JSON than HTML. Browsers ask for
+/// `text/html` first; a missing `Accept` header or a tie gets the page.
+fn prefers_json(headers: &axum::http::HeaderMap) -> bool {
+    let accept = headers
+        .get(axum::http::header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    accept_weight(accept, "application/json") > accept_weight(accept, "text/html")
+}
+
+/// `/containers`, answered with the dashboard or the JSON container list
+/// depending on the `Accept` header.
+async fn containers_handle(
+    state: State<SharedState>,
+    query: Query<ListQuery>,
+    identity: Option<axum::Extension<Identity>>,
+    headers: axum::http::HeaderMap,
+) -> axum::response::Response {
+    let mut response = if prefers_json(&headers) {
+        get_container_statuses(state, query, headers).await
+    } else {
+        render_containers_html(state, identity, headers)
+            .await
+            .into_response()
+    };
+    // Caches must keep the two representations apart
+    response.headers_mut().insert(
+        axum::http::header::VARY,
+        axum::http::HeaderValue::from_static("accept"),
+    );
+    response
+}
+
+/// Themes the web UI offers; `auto` picks light or dark to match the system.
+const THEMES: [&str; 5] = ["auto", "light", "dark", "cyberpunk", "kanagawa"];
+
+/// Accent colors must be hex, such as `#f80` or `#ff8800`, as they are
+/// written into the page's style sheet.
+fn check_accent_color(color: &str) -> Result<()> {
+    let valid = color.strip_prefix('#').is_some_and(|hex| {
+        matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit())
+    });
+    if !valid {
+        anyhow::bail!(
+            "Invalid accent color '{}', expected a hex color such as #ff8800",
+            color
+        );
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Web UI translations — message catalogs per locale
+// ---------------------------------------------------------------------------
+
+/// The built-in English messages, which other catalogs fall back to.
+const ENGLISH_MESSAGES: &str = include_str!("../locales/en.json");
+
+/// The web UI's texts in one language, by message key.
+#[derive(Clone, Debug, Default)]
+pub struct Messages(HashMap<String, String>);
+
+impl Messages {
+    /// The text for `key`, or the key itself if it has none.
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        self.0.get(key).map_or(key, String::as_str)
+    }
+
+    /// The name of a container state or health, such as `running`.
+    pub fn status<'a>(&'a self, status: &'a str) -> &'a str {
+        self.0
+            .get(&format!("status_{}", status))
+            .map_or(status, String::as_str)
+    }
+
+    /// The text for `key` with its `{name}` placeholder filled in.
+    pub fn with(&self, key: &str, name: &str, value: &str) -> String {
+        self.get(key).replace(&format!("{{{}}}", name), value)
+    }
+
+    /// The messages as a JSON object for the page's scripts, safe to place
+    /// in a `<script>` element.
+    pub fn json(&self) -> String {
+        serde_json::to_string(&self.0)
+            .unwrap_or_default()
+            .replace("</", "<\\/")
+    }
+}
+
+/// Message catalogs by lowercase locale, e.g. `en` or `pt-br`.
+struct Locales {
+    catalogs: HashMap<String, Arc<Messages>>,
+    /// For browsers asking for no locale there is a catalog for, from `--locale`
+    default: String,
+}
+
+impl Locales {
+    /// The locale and catalog an `Accept-Language` header prefers.
+    fn pick(&self, headers: &axum::http::HeaderMap) -> (String, Arc<Messages>) {
+        let locale = headers
+            .get(axum::http::header::ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| preferred_locale(v, &self.catalogs))
+            .unwrap_or_else(|| self.default.clone());
+        let messages = self.catalogs.get(&locale).cloned().unwrap_or_default();
+        (locale, messages)
+    }
+}
+
+/// The locale of `catalogs` an `Accept-Language` header ranks highest,
+/// falling back from a regional one such as `de-at` to `de`.
+fn preferred_locale(
+    accept_language: &str,
+    catalogs: &HashMap<String, Arc<Messages>>,
+) -> Option<String> {
+    let mut ranges: Vec<(f32, String)> = accept_language
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';').map(str::trim);
+            let range = parts.next()?.to_ascii_lowercase();
+            let weight = parts
+                .filter_map(|p| p.strip_prefix("q="))
+                .find_map(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            (!range.is_empty() && weight > 0.0).then_some((weight, range))
+        })
+        .collect();
+    // Stable, so ranges of equal weight keep the client's order
+    ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+    ranges.into_iter().find_map(|(_, range)| {
+        if catalogs.contains_key(&range) {
+            return Some(range);
+        }
+        let language = range.split('-').next()?;
+        catalogs
+            .contains_key(language)
+            .then(|| language.to_string())
+    })
+}
+
+/// Load the built-in English catalog and every `<locale>.json` in `dir`,
+/// each a flat `{ "<key>": "<text>" }` object. Keys a catalog leaves out
+/// are shown in English; an `en.json` rewords the English texts.
+fn load_locales(dir: Option<&str>, default: &str) -> Result<Locales> {
+    let english: HashMap<String, String> = serde_json::from_str(ENGLISH_MESSAGES)?;
+    let mut catalogs = HashMap::from([("en".to_string(), english.clone())]);
+    if let Some(dir) = dir {
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| anyhow::anyhow!("Failed to read locale directory '{}': {}", dir, e))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(locale) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let messages: HashMap<String, String> =
+                read_json_file(&path.display().to_string(), "locale")?;
+            catalogs
+                .entry(locale.to_ascii_lowercase())
+                .or_insert_with(|| english.clone())
+                .extend(messages);
+        }
+    }
+    let default = default.to_ascii_lowercase();
+    if !catalogs.contains_key(&default) {
+        anyhow::bail!(
+            "No messages for locale '{}'; expected en or a {}.json in --locale-dir",
+            default,
+            default
+        );
+    }
+    let mut names: Vec<&String> = catalogs.keys().collect();
+    names.sort();
+    tracing::info!("Web UI locales: {:?}", names);
+    Ok(Locales {
+        catalogs: catalogs
+            .into_iter()
+            .map(|(k, v)| (k, Arc::new(Messages(v))))
+            .collect(),
+        default,
+    })
+}
+
+async fn render_containers_html(
+    State(state): State<SharedState>,
+    identity: Option<axum::Extension<Identity>>,
+    headers: axum::http::HeaderMap,
+) -> Html<String> {
+    let cache = state.container_cache.read().await;
+    let containers: Vec<Container> = cache
+        .iter()
+        .filter(|c| caller_may_access(&state, &c.name))
+        .map(|c| Container {
+            name: c.name.clone(),
+            status: c.status.clone(),
+            state: c.state.clone(),
+            health: c.health.clone(),
+            ports: c.ports.clone(),
+            created: c.created.clone(),
+            started_at: c.started_at.clone(),
+            uptime: c.uptime.clone(),
+        })
+        .collect();
+    // In read-only mode everyone only gets to look
+    let role = if state.read_only {
+        Role::Viewer
+    } else {
+        identity_role(&state, identity.as_ref().map(|e| &e.0))
+    };
+    let (lang, t) = state.locales.pick(&headers);
+    let template = ContainersTemplate {
+        containers,
+        port: state.port,
+        signed_in: session_identity(&state, &headers).is_some(),
+        role: role_name(role),
+        csrf_token: csrf_token(&state, &headers),
+        title: state.title.clone(),
+        accent_color: state.accent_color.clone().unwrap_or_default(),
+        theme: state.theme.clone(),
+        lang,
+        t,
+    };
+    Html(template.render().unwrap_or_default())
+}
+
+/// Containers per page when `per_page` isn't given, and the most allowed.
+const DEFAULT_PER_PAGE: usize = 50;
+const MAX_PER_PAGE: usize = 500;
+
+async fn get_container_statuses(
+    State(state): State<SharedState>,
+    Query(query): Query<ListQuery>,
+    headers: axum::http::HeaderMap,
+) -> axum::response::Response {
+    let sort = query.sort.as_deref().unwrap_or("name");
+    if sort != "name" && sort != "status" {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            &format!("Unsupported sort '{}', expected name or status", sort),
+        )
+        .into_response();
+    }
+    if query.page == Some(0) || query.per_page == Some(0) {
+        return json_error(StatusCode::BAD_REQUEST, "page and per_page start at 1").into_response();
+    }
+
+    let cache = state.container_cache.read().await;
+    let needle = query.q.as_deref().map(str::to_lowercase);
+    let mut visible: Vec<&ContainerInfo> = cache
+        .iter()
+        .filter(|c| caller_may_access(&state, &c.name))
+        .filter(|c| {
+            query
+                .state
+                .as_deref()
+                .is_none_or(|s| c.state.eq_ignore_ascii_case(s))
+        })
+        .filter(|c| {
+            needle
+                .as_deref()
+                .is_none_or(|n| c.name.to_lowercase().contains(n))
+        })
+        .collect();
+    // The cache is kept sorted by name; a stable sort keeps that within a state
+    if sort == "status" {
+        visible.sort_by(|a, b| a.state.cmp(&b.state));
+    }
+
+    // Total before paging, so clients know how many pages there are
+    let total = visible.len();
+    if let Some(page) = query.page {
+        let per_page = query.per_page.unwrap_or(DEFAULT_PER_PAGE).min(MAX_PER_PAGE);
+        visible = visible
+            .into_iter()
+            .skip((page - 1).saturating_mul(per_page))
+            .take(per_page)
+            .collect();
+    }
+
+    let body = serde_json::to_string(&visible).unwrap_or_else(|_| "[]".to_string());
+    let mut response = conditional_json(&headers, body);
+    response.headers_mut().insert(
+        axum::http::header::HeaderName::from_static("x-total-count"),
+        axum::http::HeaderValue::from(total),
+    );
+    response
+}
+
+/// An ETag for a response body: the start of its SHA-256, quoted. It is weak
+/// because the same body is also sent compressed.
+fn body_etag(body: &[u8]) -> String {
+    use sha2::Digest;
+
+    let digest = sha2::Sha256::digest(body);
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("W/\"{}\"", hex)
+}
+
+/// Whether an `If-None-Match` header names `etag`, or `*`. Weak and strong
+/// validators match alike, as the comparison for `GET` is the weak one.
+fn etag_matches(headers: &axum::http::HeaderMap, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    headers
+        .get_all(axum::http::header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// A JSON body tagged with its ETag, or `304 Not Modified` without the body
+/// when the client already holds it. `no-cache` has browsers revalidate on
+/// every poll instead of reusing a stale copy.
+fn conditional_json(headers: &axum::http::HeaderMap, body: String) -> axum::response::Response {
+    let etag = body_etag(body.as_bytes());
+    let cache = (axum::http::header::CACHE_CONTROL, "no-cache".to_string());
+    if etag_matches(headers, &etag) {
+        let tag = (axum::http::header::ETAG, etag);
+        return (StatusCode::NOT_MODIFIED, [tag, cache]).into_response();
+    }
+    let content_type = (
+        axum::http::header::CONTENT_TYPE,
+        "application/json".to_string(),
+    );
+    (
+        StatusCode::OK,
+        [(axum::http::header::ETAG, etag), cache, content_type],
+        body,
+    )
+        .into_response()
+}
+
+/// Reject a `wait_for` state that can't be waited for, before acting.
+fn check_wait_for(query: &ActionQuery) -> Option<(StatusCode, Json<serde_json::Value>)> {
+    let target = query.wait_for.as_deref()?;
+    if WAIT_STATES.contains(&target) {
+        return None;
+    }
+    Some(json_error(
+        StatusCode::BAD_REQUEST,
+        &format!(
+            "Invalid wait_for '{}', expected one of {:?}",
+            target, WAIT_STATES
+        ),
+    ))
+}
+
+/// Wait for the state `wait_for` asks for after an action, like the wait
+/// endpoint. `None` when no wait was asked for, else whether it was reached.
+async fn wait_after_action(
+    state: &AppState,
+    query: &ActionQuery,
+) -> Result<Option<bool>, (StatusCode, Json<serde_json::Value>)> {
+    let Some(target) = query.wait_for.as_deref() else {
+        return Ok(None);
+    };
+    let timeout = std::time::Duration::from_secs(query.timeout.unwrap_or(60).min(MAX_WAIT_SECS));
+    match wait_for_state(&state.docker, &query.name, target, timeout).await {
+        Ok(outcome) => Ok(Some(outcome.reached)),
+        Err(e) => {
+            tracing::error!("Failed to wait for '{}': {}", query.name, e);
+            Err(docker_error(
+                &e,
+                "Failed to wait for container",
+                Some(&query.name),
+            ))
+        }
+    }
+}
+
+/// The answer to a start or stop, with whether the container reached the
+/// state asked for in time in `reached`. Not reaching it is no error: the
+/// action itself succeeded.
+fn action_response(
+    mut body: serde_json::Value,
+    reached: Option<bool>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(reached) = reached {
+        body["reached"] = reached.into();
+    }
+    (StatusCode::OK, Json(body))
+}
+
+/// A container's state, e.g. `running`, or `None` if it can't be inspected.
+async fn container_state(state: &AppState, name: &str) -> Option<String> {
+    let inspect = state
+        .docker
+        .inspect_container(name, None::<InspectContainerOptions>)
+        .await
+        .ok()?;
+    Some(wait_outcome(&inspect, "").state)
+}
+
+/// The container's ID, state and health after an action, along with whether
+/// the action changed anything: whether the state differs from `before`.
+/// Docker answers `304` when there is nothing to do, but bollard treats that
+/// as success, so comparing is the only way to tell. Fields are `null` if
+/// the re-inspect fails.
+async fn action_outcome(state: &AppState, name: &str, before: Option<String>) -> serde_json::Value {
+    match state
+        .docker
+        .inspect_container(name, None::<InspectContainerOptions>)
+        .await
+    {
+        Ok(inspect) => {
+            let outcome = wait_outcome(&inspect, "");
+            serde_json::json!({
+                "changed": before.as_deref() != Some(outcome.state.as_str()),
+                "id": inspect.id,
+                "state": outcome.state,
+                "health": outcome.health,
+            })
+        }
+        Err(e) => {
+            tracing::warn!("Failed to inspect '{}' after action: {}", name, e);
+            serde_json::json!({ "changed": null, "id": null, "state": null, "health": null })
+        }
+    }
+}
+
+async fn start_container_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ActionQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!("Start request for '{}' from {}", query.name, addr);
+
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "start") {
+        tracing::warn!("Action 'start' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+    if let Some(error) = check_wait_for(&query) {
+        return error;
+    }
+
+    let stopped = match resolve_exclusions(&state, &query.name).await {
+        Ok(stopped) => stopped,
+        Err(e) => {
+            tracing::warn!("{}", e);
+            return json_error(StatusCode::CONFLICT, &e);
+        }
+    };
+
+    let dependencies = match start_dependencies(&state, &query.name).await {
+        Ok(dependencies) => dependencies,
+        Err(e) => {
+            tracing::error!("Dependencies of '{}' failed: {}", query.name, e);
+            return json_error(StatusCode::FAILED_DEPENDENCY, &e);
+        }
+    };
+
+    let before = container_state(&state, &query.name).await;
+    if let Err(e) = state
+        .docker
+        .start_container(&query.name, None::<StartContainerOptions<String>>)
+        .await
+    {
+        tracing::error!("Failed to start '{}': {}", query.name, e);
+        return docker_error(&e, "Failed to start container", Some(&query.name));
+    }
+
+    let reached = match wait_after_action(&state, &query).await {
+        Ok(reached) => reached,
+        Err(error) => return error,
+    };
+    let mut body = action_outcome(&state, &query.name, before).await;
+    body["status"] = "started".into();
+    body["dependencies"] = serde_json::json!(dependencies);
+    body["stopped"] = serde_json::json!(stopped);
+    action_response(body, reached)
+}
+
+async fn stop_container_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ActionQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!("Stop request for '{}' from {}", query.name, addr);
+
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "stop") {
+        tracing::warn!("Action 'stop' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+    if let Some(error) = check_wait_for(&query) {
+        return error;
+    }
+
+    let before = container_state(&state, &query.name).await;
+    if let Err(e) = state
+        .docker
+        .stop_container(&query.name, None::<StopContainerOptions>)
+        .await
+    {
+        tracing::error!("Failed to stop '{}': {}", query.name, e);
+        return docker_error(&e, "Failed to stop container", Some(&query.name));
+    }
+
+    let reached = match wait_after_action(&state, &query).await {
+        Ok(reached) => reached,
+        Err(error) => return error,
+    };
+    let mut body = action_outcome(&state, &query.name, before).await;
+    body["status"] = "stopped".into();
+    action_response(body, reached)
+}
+
+async fn restart_container_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!("Restart request for '{}' from {}", query.name, addr);
+
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "restart") {
+        tracing::warn!("Action 'restart' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+
+    let options = RestartContainerOptions {
+        t: state.restart_timeout,
+    };
+    match state
+        .docker
+        .restart_container(&query.name, Some(options))
+        .await
+    {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "status": "restarted" })),
+        ),
+        Err(e) => {
+            tracing::error!("Failed to restart '{}': {}", query.name, e);
+            docker_error(&e, "Failed to restart container", Some(&query.name))
+        }
+    }
+}
+
+async fn pause_container_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!("Pause request for '{}' from {}", query.name, addr);
+
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "pause") {
+        tracing::warn!("Action 'pause' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+
+    match state.docker.pause_container(&query.name).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "status": "paused" })),
+        ),
+        Err(e) => {
+            tracing::error!("Failed to pause '{}': {}", query.name, e);
+            docker_error(&e, "Failed to pause container", Some(&query.name))
+        }
+    }
+}
+
+async fn unpause_container_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!("Unpause request for '{}' from {}", query.name, addr);
+
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "pause") {
+        tracing::warn!("Action 'pause' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+
+    match state.docker.unpause_container(&query.name).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "status": "unpaused" })),
+        ),
+        Err(e) => {
+            tracing::error!("Failed to unpause '{}': {}", query.name, e);
+            docker_error(&e, "Failed to unpause container", Some(&query.name))
+        }
+    }
+}
+
+async fn kill_container_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<KillQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    let signal = query.signal.as_deref().unwrap_or("SIGKILL").to_uppercase();
+    tracing::info!(
+        "Kill ({}) request for '{}' from {}",
+        signal,
+        query.name,
+        addr
+    );
+
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "kill") {
+        tracing::warn!("Action 'kill' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+
+    if !ALLOWED_SIGNALS.contains(&signal.as_str()) {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "Unsupported signal '{}', expected one of {:?}",
+                signal, ALLOWED_SIGNALS
+            ),
+        );
+    }
+
+    match state
+        .docker
+        .kill_container(
+            &query.name,
+            Some(KillContainerOptions {
+                signal: signal.as_str(),
+            }),
+        )
+        .await
+    {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "status": "killed", "signal": signal })),
+        ),
+        Err(e) => {
+            tracing::error!("Failed to kill '{}': {}", query.name, e);
+            docker_error(&e, "Failed to kill container", Some(&query.name))
+        }
+    }
+}
+
+async fn stats_container_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+) -> impl IntoResponse {
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "stats") {
+        tracing::warn!("Action 'stats' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+
+    match get_container_stats(&state.docker, &query.name).await {
+        Ok(stats) => (
+            StatusCode::OK,
+            Json(serde_json::to_value(stats).unwrap_or_default()),
+        ),
+        Err(e) => {
+            tracing::error!("Failed to get stats for '{}': {}", query.name, e);
+            docker_error(&e, "Failed to get container stats", Some(&query.name))
+        }
+    }
+}
+
+/// Server-Sent Events stream of stats for all allowed containers.
+///
+/// Snapshots come from the background cache, so a new event is pushed each
+/// time the cache is refreshed rather than querying Docker per client.
+async fn stats_stream_handle(
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> Sse<impl futures_util::Stream<Item = Result<Event, std::convert::Infallible>>> {
+    tracing::debug!("Stats stream opened from {}", addr);
+
+    // The stream outlives the request, so remember whose it is
+    let caller = current_caller();
+    let initial = (state, caller, None);
+    let stream = futures_util::stream::unfold(initial, |(state, caller, last_gen)| async move {
+        loop {
+            let gen = state
+                .cache_generation
+                .load(std::sync::atomic::Ordering::Relaxed);
+            if last_gen != Some(gen) {
+                let cache = state.container_cache.read().await;
+                let snapshot: Vec<StatsSnapshot> = cache
+                    .iter()
+                    .filter(|c| may_access(&state, caller.as_ref(), &c.name))
+                    .map(|c| StatsSnapshot {
+                        name: c.name.clone(),
+                        state: c.state.clone(),
+                        stats: ContainerStats {
+                            cpu_percent: c.cpu_percent,
+                            memory_usage: c.memory_usage,
+                            memory_limit: c.memory_limit,
+                            network_rx_bytes: c.network_rx_bytes,
+                            network_tx_bytes: c.network_tx_bytes,
+                        },
+                    })
+                    .collect();
+                drop(cache);
+                let event = Event::default()
+                    .event("stats")
+                    .id(gen.to_string())
+                    .json_data(&snapshot)
+                    .unwrap_or_default();
+                return Some((Ok(event), (state, caller, Some(gen))));
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// Dashboard page with CPU and memory sparklines, fed by the stats stream.
+async fn stats_page_handle(
+    State(state): State<SharedState>,
+    headers: axum::http::HeaderMap,
+) -> Html<String> {
+    let (lang, t) = state.locales.pick(&headers);
+    let template = StatsTemplate {
+        title: state.title.clone(),
+        accent_color: state.accent_color.clone().unwrap_or_default(),
+        theme: state.theme.clone(),
+        lang,
+        t,
+    };
+    Html(template.render().unwrap_or_default())
+}
+
+/// Scripts, styles and icons for the web UI, built into the binary so it
+/// needs no CDN and works on hosts without internet access.
+#[derive(rust_embed::Embed)]
+#[folder = "assets/"]
+struct Assets;
+
+/// A file from `assets/`, with an ETag so browsers revalidate a cached copy
+/// for just a `304` once a day, e.g. after docker-direct was upgraded.
+async fn asset_handle(
+    Path(path): Path<String>,
+    headers: axum::http::HeaderMap,
+) -> axum::response::Response {
+    embedded_asset(&path, &headers, "public, max-age=86400")
+}
+
+/// The service worker, served from the root so it controls every page.
+/// `no-cache` has browsers pick up a new one as soon as docker-direct is
+/// upgraded.
+async fn service_worker_handle(headers: axum::http::HeaderMap) -> axum::response::Response {
+    embedded_asset("sw.js", &headers, "no-cache")
+}
+
+fn embedded_asset(
+    path: &str,
+    headers: &axum::http::HeaderMap,
+    cache_control: &str,
+) -> axum::response::Response {
+    let Some(file) = Assets::get(path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let hex: String = file.metadata.sha256_hash()[..16]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let etag = format!("\"{}\"", hex);
+    let cache = (axum::http::header::CACHE_CONTROL, cache_control.to_string());
+    if etag_matches(headers, &etag) {
+        let tag = (axum::http::header::ETAG, etag);
+        return (StatusCode::NOT_MODIFIED, [tag, cache]).into_response();
+    }
+    let content_type = (
+        axum::http::header::CONTENT_TYPE,
+        file.metadata.mimetype().to_string(),
+    );
+    (
+        StatusCode::OK,
+        [(axum::http::header::ETAG, etag), cache, content_type],
+        file.data,
+    )
+        .into_response()
+}
+
+/// Web app manifest, so the web UI can be installed to a phone's home screen
+/// and opens in its own window, named after `--title`.
+async fn manifest_handle(State(state): State<SharedState>) -> impl IntoResponse {
+    let manifest = serde_json::json!({
+        "name": state.title,
+        "short_name": state.title,
+        "start_url": "/containers",
+        "scope": "/",
+        "display": "standalone",
+        "background_color": "#1e1e1e",
+        "theme_color": state.accent_color.as_deref().unwrap_or("#1e1e1e"),
+        "icons": [
+            { "src": "/assets/icon.svg", "sizes": "any", "type": "image/svg+xml" },
+            { "src": "/assets/icon-192.png", "sizes": "192x192", "type": "image/png" },
+            { "src": "/assets/icon-512.png", "sizes": "512x512", "type": "image/png" },
+        ],
+    });
+    (
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "application/manifest+json",
+        )],
+        manifest.to_string(),
+    )
+}
+
+async fn inspect_container_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+) -> impl IntoResponse {
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "inspect") {
+        tracing::warn!("Action 'inspect' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+
+    match state
+        .docker
+        .inspect_container(&query.name, None::<InspectContainerOptions>)
+        .await
+    {
+        Ok(inspect) => {
+            let sanitized = sanitize_inspect(&query.name, &inspect);
+            (
+                StatusCode::OK,
+                Json(serde_json::to_value(sanitized).unwrap_or_default()),
+            )
+        }
+        Err(e) => {
+            tracing::error!("Failed to inspect '{}': {}", query.name, e);
+            docker_error(&e, "Failed to inspect container", Some(&query.name))
+        }
+    }
+}
+
+async fn processes_container_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+) -> impl IntoResponse {
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "inspect") {
+        tracing::warn!("Action 'inspect' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+
+    match state
+        .docker
+        .top_processes(&query.name, Some(TopOptions { ps_args: "aux" }))
+        .await
+    {
+        Ok(top) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "titles": top.titles.unwrap_or_default(),
+                "processes": top.processes.unwrap_or_default(),
+            })),
+        ),
+        Err(e) => {
+            tracing::error!("Failed to list processes for '{}': {}", query.name, e);
+            docker_error(&e, "Failed to list container processes", Some(&query.name))
+        }
+    }
+}
+
+async fn restart_policy_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+) -> impl IntoResponse {
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "inspect") {
+        tracing::warn!("Action 'inspect' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+
+    match state
+        .docker
+        .inspect_container(&query.name, None::<InspectContainerOptions>)
+        .await
+    {
+        Ok(inspect) => (
+            StatusCode::OK,
+            Json(serde_json::to_value(restart_policy_info(&inspect)).unwrap_or_default()),
+        ),
+        Err(e) => {
+            tracing::error!("Failed to inspect '{}': {}", query.name, e);
+            docker_error(&e, "Failed to read restart policy", Some(&query.name))
+        }
+    }
+}
+
+async fn set_restart_policy_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<RestartPolicyQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!(
+        "Restart policy '{}' request for '{}' from {}",
+        query.policy,
+        query.name,
+        addr
+    );
+
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "restart-policy") {
+        tracing::warn!("Action 'restart-policy' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+
+    let policy_name = match query.policy.as_str() {
+        "no" => RestartPolicyNameEnum::NO,
+        "on-failure" => RestartPolicyNameEnum::ON_FAILURE,
+        "unless-stopped" => RestartPolicyNameEnum::UNLESS_STOPPED,
+        "always" => RestartPolicyNameEnum::ALWAYS,
+        other => {
+            return json_error(
+                StatusCode::BAD_REQUEST,
+                &format!(
+                    "Invalid policy '{}', expected no, on-failure, unless-stopped or always",
+                    other
+                ),
+            )
+        }
+    };
+
+    // Docker only accepts a retry count together with on-failure
+    if query.max_retries.is_some() && policy_name != RestartPolicyNameEnum::ON_FAILURE {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            "max_retries is only valid with the on-failure policy",
+        );
+    }
+
+    let options = UpdateContainerOptions::<String> {
+        restart_policy: Some(RestartPolicy {
+            name: Some(policy_name),
+            maximum_retry_count: query.max_retries,
+        }),
+        ..Default::default()
+    };
+
+    match state.docker.update_container(&query.name, options).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "status": "updated", "policy": query.policy })),
+        ),
+        Err(e) => {
+            tracing::error!(
+                "Failed to update restart policy for '{}': {}",
+                query.name,
+                e
+            );
+            docker_error(&e, "Failed to update restart policy", Some(&query.name))
+        }
+    }
+}
+
+async fn health_container_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<HealthQuery>,
+) -> impl IntoResponse {
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "inspect") {
+        tracing::warn!("Action 'inspect' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+
+    match state
+        .docker
+        .inspect_container(&query.name, None::<InspectContainerOptions>)
+        .await
+    {
+        Ok(inspect) => {
+            let detail = health_detail(&inspect, query.limit.unwrap_or(5));
+            (
+                StatusCode::OK,
+                Json(serde_json::to_value(detail).unwrap_or_default()),
+            )
+        }
+        Err(e) => {
+            tracing::error!("Failed to inspect '{}': {}", query.name, e);
+            docker_error(&e, "Failed to read container health", Some(&query.name))
+        }
+    }
+}
+
+async fn update_resources_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ResourcesQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!(
+        "Resource update (cpu_shares={:?}, memory_mb={:?}) for '{}' from {}",
+        query.cpu_shares,
+        query.memory_mb,
+        query.name,
+        addr
+    );
+
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "resources") {
+        tracing::warn!("Action 'resources' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+
+    if query.cpu_shares.is_none() && query.memory_mb.is_none() {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            "Specify cpu_shares and/or memory_mb",
+        );
+    }
+
+    let bounds = &state.resource_bounds;
+    if let Some(shares) = query.cpu_shares {
+        if !(bounds.min_cpu_shares..=bounds.max_cpu_shares).contains(&shares) {
+            return json_error(
+                StatusCode::BAD_REQUEST,
+                &format!(
+                    "cpu_shares must be between {} and {}",
+                    bounds.min_cpu_shares, bounds.max_cpu_shares
+                ),
+            );
+        }
+    }
+    if let Some(mb) = query.memory_mb {
+        if !(bounds.min_memory_mb..=bounds.max_memory_mb).contains(&mb) {
+            return json_error(
+                StatusCode::BAD_REQUEST,
+                &format!(
+                    "memory_mb must be between {} and {}",
+                    bounds.min_memory_mb, bounds.max_memory_mb
+                ),
+            );
+        }
+    }
+
+    let memory = query.memory_mb.map(|mb| mb * 1024 * 1024);
+    let options = UpdateContainerOptions::<String> {
+        cpu_shares: query.cpu_shares,
+        memory,
+        // Match Docker's default of swap = 2x memory; an existing swap limit
+        // lower than the new memory limit would otherwise be rejected
+        memory_swap: memory.map(|m| m * 2),
+        ..Default::default()
+    };
+
+    match state.docker.update_container(&query.name, options).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "status": "updated",
+                "cpu_shares": query.cpu_shares,
+                "memory_mb": query.memory_mb,
+            })),
+        ),
+        Err(e) => {
+            tracing::error!("Failed to update resources for '{}': {}", query.name, e);
+            docker_error(
+                &e,
+                "Failed to update container resources",
+                Some(&query.name),
+            )
+        }
+    }
+}
+
+async fn diff_container_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+) -> impl IntoResponse {
+    use bollard::models::ChangeType;
+
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "inspect") {
+        tracing::warn!("Action 'inspect' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+
+    match state.docker.container_changes(&query.name).await {
+        Ok(changes) => {
+            let (mut added, mut changed, mut deleted) = (Vec::new(), Vec::new(), Vec::new());
+            for change in changes.unwrap_or_default() {
+                // Docker reports 0 = modified, 1 = added, 2 = deleted
+                match change.kind {
+                    ChangeType::_0 => changed.push(change.path),
+                    ChangeType::_1 => added.push(change.path),
+                    ChangeType::_2 => deleted.push(change.path),
+                }
+            }
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({
+                    "added": added,
+                    "changed": changed,
+                    "deleted": deleted,
+                })),
+            )
+        }
+        Err(e) => {
+            tracing::error!("Failed to diff '{}': {}", query.name, e);
+            docker_error(&e, "Failed to read container changes", Some(&query.name))
+        }
+    }
+}
+
+async fn stop_all_handle(
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!("Stop-all request from {}", addr);
+
+    let mut results = Vec::new();
+    for name in &accessible_containers(&state) {
+        if !is_action_permitted(&state, name, "stop") {
+            results.push(serde_json::json!({ "name": name, "error": ACTION_NOT_PERMITTED }));
+            continue;
+        }
+        tracing::info!("Stopping '{}'...", name);
+        match state
+            .docker
+            .stop_container(name, None::<StopContainerOptions>)
+            .await
+        {
+            Ok(_) => {
+                tracing::info!("Stopped '{}'", name);
+                results.push(serde_json::json!({ "name": name, "status": "stopped" }));
+            }
+            Err(e) => {
+                tracing::error!("Failed to stop '{}': {}", name, e);
+                results.push(serde_json::json!({ "name": name, "error": e.to_string() }));
+            }
+        }
+    }
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({ "results": results })),
+    )
+}
+
+// ---------------------------------------------------------------------------
+// Batch actions — several container actions in one request
+// ---------------------------------------------------------------------------
+
+/// Actions a batch item may ask for.
+const BATCH_ACTIONS: [&str; 5] = ["start", "stop", "restart", "pause", "unpause"];
+
+/// Most items one batch may hold.
+const MAX_BATCH_ITEMS: usize = 100;
+
+/// How many batch items run against Docker at the same time.
+const BATCH_CONCURRENCY: usize = 4;
+
+/// Run one batch item with the same checks as the single-container routes.
+async fn batch_item_outcome(
+    state: &AppState,
+    item: &BatchItem,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let name = item.name.as_str();
+    if !BATCH_ACTIONS.contains(&item.action.as_str()) {
+        return container_error(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "Unsupported action '{}', expected one of {:?}",
+                item.action, BATCH_ACTIONS
+            ),
+            name,
+        );
+    }
+    if !is_container_allowed(state, name) {
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", name);
+    }
+    // Unpausing is covered by the pause permission
+    let permission = if item.action == "unpause" {
+        "pause"
+    } else {
+        &item.action
+    };
+    if !is_action_permitted(state, name, permission) {
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, name);
+    }
+
+    if item.action == "start" {
+        if let Err(e) = resolve_exclusions(state, name).await {
+            return container_error(StatusCode::CONFLICT, &e, name);
+        }
+        if let Err(e) = start_dependencies(state, name).await {
+            return container_error(StatusCode::FAILED_DEPENDENCY, &e, name);
+        }
+    }
+
+    let before = container_state(state, name).await;
+    let result = match item.action.as_str() {
+        "start" => state
+            .docker
+            .start_container(name, None::<StartContainerOptions<String>>)
+            .await
+            .map(|_| "started"),
+        "stop" => state
+            .docker
+            .stop_container(name, None::<StopContainerOptions>)
+            .await
+            .map(|_| "stopped"),
+        "restart" => state
+            .docker
+            .restart_container(
+                name,
+                Some(RestartContainerOptions {
+                    t: state.restart_timeout,
+                }),
+            )
+            .await
+            .map(|_| "restarted"),
+        "pause" => state.docker.pause_container(name).await.map(|_| "paused"),
+        _ => state
+            .docker
+            .unpause_container(name)
+            .await
+            .map(|_| "unpaused"),
+    };
+    match result {
+        Ok(result) => {
+            // A restart changes things even though the state stays `running`
+            let changed = item.action == "restart" || container_state(state, name).await != before;
+            let result = if changed { result } else { "unchanged" };
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({ "result": result, "changed": changed })),
+            )
+        }
+        Err(e) => {
+            tracing::error!("Batch {} of '{}' failed: {}", item.action, name, e);
+            docker_error(&e, &format!("Failed to {} container", item.action), None)
+        }
+    }
+}
+
+/// Run a list of `{name, action}` items, a few at a time, and answer with a
+/// result per item in the order they were given. Each item is audited like
+/// the single-container route it stands for.
+async fn batch_handle(
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    identity: Option<axum::Extension<Identity>>,
+    body: axum::body::Bytes,
+) -> impl IntoResponse {
+    use futures_util::StreamExt;
+
+    tracing::info!("Batch request from {}", addr);
+
+    let items: Vec<BatchItem> = match serde_json::from_slice(&body) {
+        Ok(items) => items,
+        Err(e) => {
+            return json_error(StatusCode::BAD_REQUEST, &format!("Invalid batch: {}", e));
+        }
+    };
+    if items.len() > MAX_BATCH_ITEMS {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            &format!("A batch holds at most {} items", MAX_BATCH_ITEMS),
+        );
+    }
+
+    let client = addr.ip().to_canonical().to_string();
+    let user = identity.as_ref().map(|e| identity_name(&e.0).to_string());
+    let results: Vec<serde_json::Value> = futures_util::stream::iter(items)
+        .map(|item| {
+            let (state, client, user) = (state.clone(), client.clone(), user.clone());
+            async move {
+                let (status, Json(mut outcome)) = batch_item_outcome(&state, &item).await;
+                let result = if status.is_success() { "ok" } else { "error" };
+                let action = format!("/containers/{}", item.action);
+                let container = Some(item.name.clone());
+                append_audit(&state, client, user, action, container, status, result).await;
+
+                if let Some(fields) = outcome.as_object_mut() {
+                    fields.remove("container");
+                }
+                outcome["name"] = item.name.into();
+                outcome["action"] = item.action.into();
+                outcome["status"] = status.as_u16().into();
+                outcome
+            }
+        })
+        .buffered(BATCH_CONCURRENCY)
+        .collect()
+        .await;
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({ "results": results })),
+    )
+}
+
+// ---------------------------------------------------------------------------
+// Waiting for a target state
+// ---------------------------------------------------------------------------
+
+/// States that can be waited for.
+const WAIT_STATES: [&str; 3] = ["exited", "running", "healthy"];
+
+/// Longest a single wait request may block, in seconds.
+const MAX_WAIT_SECS: u64 = 300;
+
+fn wait_outcome(inspect: &bollard::models::ContainerInspectResponse, target: &str) -> WaitOutcome {
+    let state_obj = inspect.state.as_ref();
+    let state = state_obj
+        .and_then(|s| s.status)
+        .map(|s| format!("{:?}", s).to_lowercase())
+        .unwrap_or_else(|| "unknown".to_string());
+    let health = state_obj
+        .and_then(|s| s.health.as_ref())
+        .and_then(|h| h.status)
+        .map(|s| format!("{:?}", s).to_lowercase());
+    let exit_code = state_obj.and_then(|s| s.exit_code);
+
+    let reached = match target {
+        "healthy" => state == "running" && health.as_deref() == Some("healthy"),
+        "exited" => state == "exited" || state == "dead",
+        other => state == other,
+    };
+
+    WaitOutcome {
+        reached,
+        state,
+        health,
+        exit_code: if reached || target == "exited" {
+            exit_code
+        } else {
+            None
+        },
+    }
+}
+
+/// Poll a container until it reaches `target` or `timeout` elapses. Returns
+/// the last observed outcome either way.
+async fn wait_for_state(
+    docker: &Docker,
+    name: &str,
+    target: &str,
+    timeout: std::time::Duration,
+) -> Result<WaitOutcome, bollard::errors::Error> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        let inspect = docker
+            .inspect_container(name, None::<InspectContainerOptions>)
+            .await?;
+        let outcome = wait_outcome(&inspect, target);
+        if outcome.reached || tokio::time::Instant::now() >= deadline {
+            return Ok(outcome);
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    }
+}
+
+async fn wait_container_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<WaitQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!(
+        "Wait for '{}' on '{}' from {}",
+        query.state,
+        query.name,
+        addr
+    );
+
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "inspect") {
+        tracing::warn!("Action 'inspect' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+
+    if !WAIT_STATES.contains(&query.state.as_str()) {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "Invalid state '{}', expected one of {:?}",
+                query.state, WAIT_STATES
+            ),
+        );
+    }
+
+    let timeout = std::time::Duration::from_secs(query.timeout.unwrap_or(60).min(MAX_WAIT_SECS));
+    match wait_for_state(&state.docker, &query.name, &query.state, timeout).await {
+        // Reaching the state in time or not, the wait itself succeeded
+        Ok(outcome) => (
+            StatusCode::OK,
+            Json(serde_json::to_value(outcome).unwrap_or_default()),
+        ),
+        Err(e) => {
+            tracing::error!("Failed to wait for '{}': {}", query.name, e);
+            docker_error(&e, "Failed to wait for container", Some(&query.name))
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Dependencies — start what a container depends on before the container itself
+// ---------------------------------------------------------------------------
+
+/// Everything `name` depends on, directly or indirectly, in start order.
+fn dependency_order(dependencies: &HashMap<String, Vec<String>>, name: &str) -> Vec<String> {
+    fn visit(dependencies: &HashMap<String, Vec<String>>, name: &str, order: &mut Vec<String>) {
+        for dep in dependencies.get(name).into_iter().flatten() {
+            if !order.contains(dep) {
+                visit(dependencies, dep, order);
+                order.push(dep.clone());
+            }
+        }
+    }
+
+    let mut order = Vec::new();
+    visit(dependencies, name, &mut order);
+    order
+}
+
+/// Reject dependency declarations that contain a cycle, since they could
+/// never be started in order.
+fn check_dependency_cycles(dependencies: &HashMap<String, Vec<String>>) -> Result<()> {
+    fn visit<'a>(
+        dependencies: &'a HashMap<String, Vec<String>>,
+        name: &'a str,
+        path: &mut Vec<&'a str>,
+        done: &mut HashSet<&'a str>,
+    ) -> Result<()> {
+        if let Some(start) = path.iter().position(|n| *n == name) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(name);
+            anyhow::bail!("Dependency cycle: {}", cycle.join(" -> "));
+        }
+        if !done.insert(name) {
+            return Ok(());
+        }
+        path.push(name);
+        for dep in dependencies.get(name).into_iter().flatten() {
+            visit(dependencies, dep, path, done)?;
+        }
+        path.pop();
+        Ok(())
+    }
+
+    let mut done = HashSet::new();
+    for name in dependencies.keys() {
+        let mut path = Vec::new();
+        visit(dependencies, name, &mut path, &mut done)?;
+    }
+    Ok(())
+}
+
+/// Start the dependencies of `name` one by one, waiting for each to become
+/// healthy (or running, without a healthcheck) before the next. Returns the
+/// dependencies in the order they were brought up.
+async fn start_dependencies(state: &AppState, name: &str) -> Result<Vec<String>, String> {
+    let order = dependency_order(&current_policy(state).dependencies, name);
+    let timeout = std::time::Duration::from_secs(state.dependency_timeout);
+
+    for dep in &order {
+        if !is_container_allowed(state, dep) {
+            return Err(format!("Dependency '{}' is not an allowed container", dep));
+        }
+        if !is_action_permitted(state, dep, "start") {
+            return Err(format!("Dependency '{}' may not be started", dep));
+        }
+
+        resolve_exclusions(state, dep).await?;
+        tracing::info!("Starting dependency '{}' of '{}'", dep, name);
+        state
+            .docker
+            .start_container(dep, None::<StartContainerOptions<String>>)
+            .await
+            .map_err(|e| format!("Failed to start dependency '{}': {}", dep, e))?;
+
+        let has_health = state
+            .docker
+            .inspect_container(dep, None::<InspectContainerOptions>)
+            .await
+            .map(|i| i.state.and_then(|s| s.health).is_some())
+            .map_err(|e| format!("Failed to inspect dependency '{}': {}", dep, e))?;
+        let target = if has_health { "healthy" } else { "running" };
+        let outcome = wait_for_state(&state.docker, dep, target, timeout)
+            .await
+            .map_err(|e| format!("Failed to wait for dependency '{}': {}", dep, e))?;
+        if !outcome.reached {
+            return Err(format!(
+                "Dependency '{}' did not become {} within {}s",
+                dep, target, state.dependency_timeout
+            ));
+        }
+    }
+    Ok(order)
+}
+
+// ---------------------------------------------------------------------------
+// Exclusivity — members of an exclusive group never run at the same time
+// ---------------------------------------------------------------------------
+
+/// Make way for starting `name`: running members of its exclusive groups are
+/// stopped, or the start is refused for groups that reject conflicts and when
+/// a running member may not be stopped. Returns the containers that were stopped.
+async fn resolve_exclusions(state: &AppState, name: &str) -> Result<Vec<String>, String> {
+    let policy = current_policy(state);
+    let mut groups: Vec<(&String, &ExclusiveGroup)> = policy
+        .exclusive_groups
+        .iter()
+        .filter(|(_, g)| g.containers.iter().any(|c| c == name))
+        .collect();
+    groups.sort_by(|a, b| a.0.cmp(b.0));
+
+    // Find every conflict before stopping anything, so a rejecting group
+    // doesn't leave containers of another group stopped
+    let mut conflicts = Vec::new();
+    for (group_name, group) in groups {
+        for other in group.containers.iter().filter(|c| *c != name) {
+            let running = state
+                .docker
+                .inspect_container(other, None::<InspectContainerOptions>)
+                .await
+                .ok()
+                .and_then(|i| i.state)
+                .and_then(|s| s.running)
+                .unwrap_or(false);
+            if !running {
+                continue;
+            }
+            if group.on_conflict == ExclusiveConflict::Reject {
+                return Err(format!(
+                    "Cannot start '{}' while '{}' is running (exclusive group '{}')",
+                    name, other, group_name
+                ));
+            }
+            // Stopping a rival is a stop like any other, so it needs the same
+            // allowlist, per-container and per-caller permissions
+            if !is_container_allowed(state, other) || !is_action_permitted(state, other, "stop") {
+                return Err(format!(
+                    "Cannot start '{}' while '{}' is running (exclusive group '{}'), and '{}' may not be stopped",
+                    name, other, group_name, other
+                ));
+            }
+            if !conflicts.contains(other) {
+                conflicts.push(other.clone());
+            }
+        }
+    }
+
+    for other in &conflicts {
+        tracing::info!("Stopping '{}' to make way for '{}'", other, name);
+        state
+            .docker
+            .stop_container(other, None::<StopContainerOptions>)
+            .await
+            .map_err(|e| format!("Failed to stop '{}': {}", other, e))?;
+    }
+    Ok(conflicts)
+}
+
+// ---------------------------------------------------------------------------
+// Recreate — stop, remove and create again from the existing configuration
+// ---------------------------------------------------------------------------
+
+/// Build a create request that reproduces an inspected container.
+fn recreate_config(inspect: &bollard::models::ContainerInspectResponse) -> Config<String> {
+    let mut config: Config<String> = inspect.config.clone().unwrap_or_default().into();
+
+    // A hostname equal to the old container ID is Docker's default; let the
+    // new container get its own
+    if let (Some(hostname), Some(id)) = (&config.hostname, &inspect.id) {
+        if id.starts_with(hostname.as_str()) {
+            config.hostname = None;
+        }
+    }
+
+    let mut host_config = inspect.host_config.clone().unwrap_or_default();
+
+    // Named and anonymous volumes that aren't declared as binds would be
+    // replaced by fresh empty volumes, so pin them explicitly
+    let mut binds = host_config.binds.take().unwrap_or_default();
+    for mount in inspect.mounts.iter().flatten() {
+        let is_volume = mount.typ == Some(bollard::models::MountPointTypeEnum::VOLUME);
+        let (Some(volume), Some(dest)) = (&mount.name, &mount.destination) else {
+            continue;
+        };
+        let already_bound = binds
+            .iter()
+            .any(|b| b.split(':').nth(1) == Some(dest.as_str()));
+        if is_volume && !already_bound {
+            let mode = if mount.rw == Some(false) { ":ro" } else { "" };
+            binds.push(format!("{}:{}{}", volume, dest, mode));
+        }
+    }
+    host_config.binds = (!binds.is_empty()).then_some(binds);
+    config.host_config = Some(host_config);
+
+    // Keep network membership and aliases; addresses are assigned on connect
+    let endpoints: HashMap<String, EndpointSettings> = inspect
+        .network_settings
+        .as_ref()
+        .and_then(|ns| ns.networks.as_ref())
+        .map(|networks| {
+            networks
+                .iter()
+                .map(|(net, ep)| {
+                    let settings = EndpointSettings {
+                        aliases: ep.aliases.clone(),
+                        links: ep.links.clone(),
+                        ipam_config: ep.ipam_config.clone(),
+                        ..Default::default()
+                    };
+                    (net.clone(), settings)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if !endpoints.is_empty() {
+        config.networking_config = Some(NetworkingConfig {
+            endpoints_config: endpoints,
+        });
+    }
+
+    config
+}
+
+/// Run one step of a multi-step action and record its outcome.
+fn record_step<T>(
+    steps: &mut Vec<StepReport>,
+    step: &'static str,
+    result: Result<T, bollard::errors::Error>,
+) -> Result<T, bollard::errors::Error> {
+    steps.push(StepReport {
+        step,
+        ok: result.is_ok(),
+        error: result.as_ref().err().map(|e| e.to_string()),
+    });
+    result
+}
+
+/// Recreate a container from its current image and configuration. The old
+/// container is renamed aside rather than removed until the new one has been
+/// created and started, so a failure restores it as it was.
+async fn recreate_container(
+    docker: &Docker,
+    name: &str,
+    steps: &mut Vec<StepReport>,
+) -> Result<String, bollard::errors::Error> {
+    let inspect = record_step(
+        steps,
+        "inspect",
+        docker
+            .inspect_container(name, None::<InspectContainerOptions>)
+            .await,
+    )?;
+    let was_running = inspect
+        .state
+        .as_ref()
+        .and_then(|s| s.running)
+        .unwrap_or(false);
+    let config = recreate_config(&inspect);
+
+    if was_running {
+        record_step(
+            steps,
+            "stop",
+            docker
+                .stop_container(name, None::<StopContainerOptions>)
+                .await,
+        )?;
+    }
+    let backup = format!("{}-recreate-{}", name, unix_now());
+    let renamed = docker
+        .rename_container(
+            name,
+            RenameContainerOptions {
+                name: backup.as_str(),
+            },
+        )
+        .await;
+    if let Err(e) = record_step(steps, "rename", renamed) {
+        if was_running {
+            let _ = docker
+                .start_container(name, None::<StartContainerOptions<String>>)
+                .await;
+        }
+        return Err(e);
+    }
+
+    let replaced = async {
+        let created = record_step(
+            steps,
+            "create",
+            docker
+                .create_container(
+                    Some(CreateContainerOptions {
+                        name: name.to_string(),
+                        platform: None,
+                    }),
+                    config,
+                )
+                .await,
+        )?;
+        if was_running {
+            let started = docker
+                .start_container(name, None::<StartContainerOptions<String>>)
+                .await;
+            if let Err(e) = record_step(steps, "start", started) {
+                let _ = docker
+                    .remove_container(
+                        &created.id,
+                        Some(RemoveContainerOptions {
+                            force: true,
+                            ..Default::default()
+                        }),
+                    )
+                    .await;
+                return Err(e);
+            }
+        }
+        Ok(created.id)
+    }
+    .await;
+
+    match replaced {
+        Ok(id) => {
+            let removed = docker
+                .remove_container(&backup, None::<RemoveContainerOptions>)
+                .await;
+            // The new container is running, so a leftover old one is only noted
+            if record_step(steps, "remove", removed).is_err() {
+                tracing::warn!(
+                    "Recreated '{}' but failed to remove the old container '{}'",
+                    name,
+                    backup
+                );
+            }
+            Ok(id)
+        }
+        Err(e) => {
+            tracing::warn!("Recreating '{}' failed, restoring the old container", name);
+            let restored = restore_container(docker, &backup, name, was_running).await;
+            // The step shows whether restoring worked; the answer is about why recreating failed
+            let _ = record_step(steps, "rollback", restored);
+            Err(e)
+        }
+    }
+}
+
+/// Put a container renamed aside by `recreate_container` back under its
+/// name, and start it again if it was running.
+async fn restore_container(
+    docker: &Docker,
+    backup: &str,
+    name: &str,
+    was_running: bool,
+) -> Result<(), bollard::errors::Error> {
+    docker
+        .rename_container(backup, RenameContainerOptions { name })
+        .await?;
+    if was_running {
+        docker
+            .start_container(name, None::<StartContainerOptions<String>>)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn recreate_container_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!("Recreate request for '{}' from {}", query.name, addr);
+
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "recreate") {
+        tracing::warn!("Action 'recreate' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+
+    // Recreated apart from the request, so neither the request timeout nor a
+    // client going away can stop it between moving the old container aside
+    // and starting the new one or restoring the old
+    let task = {
+        let docker = state.docker.clone();
+        let name = query.name.clone();
+        tokio::spawn(async move {
+            let mut steps = Vec::new();
+            let result = recreate_container(&docker, &name, &mut steps).await;
+            (result, steps)
+        })
+    };
+    let (result, steps) = match task.await {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            tracing::error!("Recreating '{}' failed: {}", query.name, e);
+            return container_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to recreate container",
+                &query.name,
+            );
+        }
+    };
+    match result {
+        Ok(id) => {
+            tracing::info!("Recreated '{}' as {}", query.name, id);
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({ "status": "recreated", "id": id, "steps": steps })),
+            )
+        }
+        Err(e) => {
+            tracing::error!("Failed to recreate '{}': {}", query.name, e);
+            let (status, Json(mut body)) = container_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("Failed to recreate container: {}", e),
+                &query.name,
+            );
+            body["steps"] = serde_json::to_value(&steps).unwrap_or_default();
+            (status, Json(body))
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Update — pull the latest image and recreate
+// ---------------------------------------------------------------------------
+
+/// Pull an image to completion, returning the first error Docker reports.
+async fn pull_image(docker: &Docker, reference: &str) -> Result<(), bollard::errors::Error> {
+    use futures_util::StreamExt;
+
+    let mut pull = Box::pin(docker.create_image(Some(pull_options(reference)), None, None));
+    while let Some(item) = pull.next().await {
+        if let Some(error) = item?.error {
+            return Err(bollard::errors::Error::DockerStreamError { error });
+        }
+    }
+    Ok(())
+}
+
+/// Pull a container's image and recreate the container if a newer image was
+/// pulled, or always with `force`.
+async fn update_container(
+    docker: &Docker,
+    name: &str,
+    force: bool,
+    steps: &mut Vec<StepReport>,
+) -> Result<serde_json::Value, bollard::errors::Error> {
+    let inspect = record_step(
+        steps,
+        "inspect",
+        docker
+            .inspect_container(name, None::<InspectContainerOptions>)
+            .await,
+    )?;
+    let reference = inspect
+        .config
+        .as_ref()
+        .and_then(|c| c.image.clone())
+        .unwrap_or_default();
+    let current_image = inspect.image.clone().unwrap_or_default();
+
+    record_step(steps, "pull", pull_image(docker, &reference).await)?;
+
+    let pulled = docker.inspect_image(&reference).await;
+    let pulled_image = record_step(steps, "inspect-image", pulled)?
+        .id
+        .unwrap_or_default();
+
+    if pulled_image == current_image && !force {
+        return Ok(serde_json::json!({ "status": "up-to-date", "image": reference }));
+    }
+
+    let id = recreate_container(docker, name, steps).await?;
+    Ok(serde_json::json!({ "status": "updated", "image": reference, "id": id }))
+}
+
+async fn update_container_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<UpdateQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!("Update request for '{}' from {}", query.name, addr);
+
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "update") {
+        tracing::warn!("Action 'update' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+
+    // Updated apart from the request, like in `recreate_container_handle`
+    let task = {
+        let state = state.clone();
+        let name = query.name.clone();
+        let force = query.force.unwrap_or(false);
+        tokio::spawn(async move {
+            let mut steps = Vec::new();
+            let result = update_container(&state.docker, &name, force, &mut steps).await;
+            if result.is_ok() {
+                state.update_cache.write().await.insert(name, false);
+            }
+            (result, steps)
+        })
+    };
+    let (result, steps) = match task.await {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            tracing::error!("Updating '{}' failed: {}", query.name, e);
+            return container_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to update container",
+                &query.name,
+            );
+        }
+    };
+
+    match result {
+        Ok(mut body) => {
+            tracing::info!("Update of '{}': {}", query.name, body["status"]);
+            body["steps"] = serde_json::to_value(&steps).unwrap_or_default();
+            (StatusCode::OK, Json(body))
+        }
+        Err(e) => {
+            tracing::error!("Failed to update '{}': {}", query.name, e);
+            let (status, Json(mut body)) = container_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("Failed to update container: {}", e),
+                &query.name,
+            );
+            body["steps"] = serde_json::to_value(&steps).unwrap_or_default();
+            (status, Json(body))
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Container logs
+// ---------------------------------------------------------------------------
+
+fn parse_tail(tail: Option<&str>) -> Result<String, String> {
+    match tail {
+        None => Ok("200".to_string()),
+        Some("all") => Ok("all".to_string()),
+        Some(n) => n
+            .parse::<u64>()
+            .map(|n| n.to_string())
+            .map_err(|_| format!("Invalid tail '{}', expected a number or 'all'", n)),
+    }
+}
+
+fn parse_since(since: Option<&str>) -> Result<i64, String> {
+    match since {
+        None => Ok(0),
+        Some(s) => s.parse::<i64>().or_else(|_| chrono_parse(s)).map_err(|_| {
+            format!(
+                "Invalid since '{}', expected a Unix timestamp or RFC3339",
+                s
+            )
+        }),
+    }
+}
+
+fn log_stream_name(output: &LogOutput) -> &'static str {
+    match output {
+        LogOutput::StdErr { .. } => "stderr",
+        LogOutput::StdOut { .. } => "stdout",
+        LogOutput::StdIn { .. } => "stdin",
+        LogOutput::Console { .. } => "console",
+    }
+}
+
+/// Render a timestamped log chunk as a JSON object.
+fn log_json_line(output: &LogOutput) -> serde_json::Value {
+    // With timestamps enabled Docker prefixes each line with "<RFC3339> "
+    let raw = output.to_string();
+    let raw = raw.trim_end_matches('\n');
+    let (time, message) = raw.split_once(' ').unwrap_or(("", raw));
+    serde_json::json!({
+        "time": time,
+        "stream": log_stream_name(output),
+        "message": message,
+    })
+}
+
+async fn logs_container_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<LogsQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> axum::response::Response {
+    use futures_util::StreamExt;
+
+    tracing::info!("Logs request for '{}' from {}", query.name, addr);
+
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name)
+            .into_response();
+    }
+    if !is_action_permitted(&state, &query.name, "logs") {
+        tracing::warn!("Action 'logs' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name)
+            .into_response();
+    }
+
+    let tail = match parse_tail(query.tail.as_deref()) {
+        Ok(t) => t,
+        Err(msg) => return json_error(StatusCode::BAD_REQUEST, &msg).into_response(),
+    };
+    let since = match parse_since(query.since.as_deref()) {
+        Ok(s) => s,
+        Err(msg) => return json_error(StatusCode::BAD_REQUEST, &msg).into_response(),
+    };
+    let as_json = match query.format.as_deref() {
+        None | Some("text") => false,
+        Some("json") => true,
+        Some(other) => {
+            return json_error(
+                StatusCode::BAD_REQUEST,
+                &format!("Invalid format '{}', expected 'text' or 'json'", other),
+            )
+            .into_response()
+        }
+    };
+
+    let options = LogsOptions {
+        follow: false,
+        stdout: true,
+        stderr: true,
+        since,
+        until: 0,
+        timestamps: as_json,
+        tail,
+    };
+    let mut stream = state.docker.logs(&query.name, Some(options));
+
+    let mut body = String::new();
+    while let Some(item) = stream.next().await {
+        match item {
+            Ok(output) => {
+                if as_json {
+                    body.push_str(&log_json_line(&output).to_string());
+                    body.push('\n');
+                } else {
+                    body.push_str(&output.to_string());
+                }
+            }
+            Err(e) => {
+                tracing::error!("Failed to read logs for '{}': {}", query.name, e);
+                return docker_error(&e, "Failed to read container logs", Some(&query.name))
+                    .into_response();
+            }
+        }
+    }
+
+    let content_type = if as_json {
+        "application/x-ndjson"
+    } else {
+        "text/plain; charset=utf-8"
+    };
+    (
+        StatusCode::OK,
+        [(axum::http::header::CONTENT_TYPE, content_type)],
+        body,
+    )
+        .into_response()
+}
+
+/// Log viewer page for one container, built on the logs and live logs routes.
+async fn logs_page_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+    headers: axum::http::HeaderMap,
+) -> axum::response::Response {
+    if !is_container_allowed(&state, &query.name) {
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name)
+            .into_response();
+    }
+    let (lang, t) = state.locales.pick(&headers);
+    let template = LogsTemplate {
+        title: state.title.clone(),
+        container: query.name,
+        accent_color: state.accent_color.clone().unwrap_or_default(),
+        theme: state.theme.clone(),
+        lang,
+        t,
+    };
+    Html(template.render().unwrap_or_default()).into_response()
+}
+
+async fn logs_stream_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<LogsQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: axum::http::HeaderMap,
+    ws: WebSocketUpgrade,
+) -> axum::response::Response {
+    tracing::info!("Log stream request for '{}' from {}", query.name, addr);
+
+    if !websocket_origin_allowed(&state, &headers) {
+        return websocket_origin_rejected(&headers);
+    }
+
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name)
+            .into_response();
+    }
+    if !is_action_permitted(&state, &query.name, "logs") {
+        tracing::warn!("Action 'logs' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name)
+            .into_response();
+    }
+
+    let tail = match parse_tail(query.tail.as_deref()) {
+        Ok(t) => t,
+        Err(msg) => return json_error(StatusCode::BAD_REQUEST, &msg).into_response(),
+    };
+    let since = match parse_since(query.since.as_deref()) {
+        Ok(s) => s,
+        Err(msg) => return json_error(StatusCode::BAD_REQUEST, &msg).into_response(),
+    };
+
+    ws.on_upgrade(move |socket| stream_logs(state, query.name, tail, since, socket))
+}
+
+/// Follow a container's logs and forward each chunk as a JSON text frame.
+///
+/// The next chunk is only pulled from Docker once the previous frame has been
+/// sent, so a slow client throttles the log stream instead of buffering it.
+/// The socket is closed when the container exits or the client disconnects.
+async fn stream_logs(
+    state: SharedState,
+    name: String,
+    tail: String,
+    since: i64,
+    mut socket: WebSocket,
+) {
+    use futures_util::StreamExt;
+
+    let options = LogsOptions {
+        follow: true,
+        stdout: true,
+        stderr: true,
+        since,
+        until: 0,
+        timestamps: true,
+        tail,
+    };
+    let mut stream = state.docker.logs(&name, Some(options));
+
+    let reason = loop {
+        tokio::select! {
+            item = stream.next() => match item {
+                Some(Ok(output)) => {
+                    let frame = Message::Text(log_json_line(&output).to_string().into());
+                    if socket.send(frame).await.is_err() {
+                        tracing::debug!("Log stream client for '{}' went away", name);
+                        return;
+                    }
+                }
+                Some(Err(e)) => {
+                    tracing::error!("Log stream for '{}' failed: {}", name, e);
+                    break "log stream error";
+                }
+                None => break "container exited",
+            },
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Close(_))) | None | Some(Err(_)) => {
+                    tracing::debug!("Log stream client for '{}' closed", name);
+                    return;
+                }
+                Some(Ok(_)) => {}
+            },
+        }
+    };
+
+    let _ = socket
+        .send(Message::Close(Some(CloseFrame {
+            code: close_code::NORMAL,
+            reason: reason.into(),
+        })))
+        .await;
+}
+
+// ---------------------------------------------------------------------------
+// Restricted exec — only commands predefined in the exec file can run
+// ---------------------------------------------------------------------------
+
+async fn run_exec(
+    docker: &Docker,
+    name: &str,
+    argv: &[String],
+) -> Result<(Option<i64>, String), bollard::errors::Error> {
+    use futures_util::StreamExt;
+
+    let exec = docker
+        .create_exec(
+            name,
+            CreateExecOptions {
+                attach_stdout: Some(true),
+                attach_stderr: Some(true),
+                cmd: Some(argv.to_vec()),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    let mut output = String::new();
+    if let StartExecResults::Attached {
+        output: mut stream, ..
+    } = docker
+        .start_exec(&exec.id, None::<StartExecOptions>)
+        .await?
+    {
+        while let Some(chunk) = stream.next().await {
+            output.push_str(&chunk?.to_string());
+        }
+    }
+
+    let exit_code = docker.inspect_exec(&exec.id).await?.exit_code;
+    Ok((exit_code, output))
+}
+
+async fn list_commands_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+) -> impl IntoResponse {
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "exec") {
+        tracing::warn!("Action 'exec' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+
+    let policy = current_policy(&state);
+    let mut commands: Vec<&String> = policy
+        .exec_commands
+        .get(&query.name)
+        .map(|c| c.keys().collect())
+        .unwrap_or_default();
+    commands.sort();
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({ "commands": commands })),
+    )
+}
+
+async fn exec_command_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ExecQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!(
+        "Exec '{}' request for '{}' from {}",
+        query.command,
+        query.name,
+        addr
+    );
+
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "exec") {
+        tracing::warn!("Action 'exec' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+
+    let policy = current_policy(&state);
+    let Some(argv) = policy
+        .exec_commands
+        .get(&query.name)
+        .and_then(|c| c.get(&query.command))
+    else {
+        tracing::warn!(
+            "Command '{}' not defined for '{}'",
+            query.command,
+            query.name
+        );
+        return container_error(StatusCode::FORBIDDEN, "Command not allowed", &query.name);
+    };
+
+    let result = tokio::time::timeout(
+        std::time::Duration::from_secs(60),
+        run_exec(&state.docker, &query.name, argv),
+    )
+    .await;
+
+    match result {
+        Ok(Ok((exit_code, output))) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "command": query.command,
+                "exit_code": exit_code,
+                "output": output,
+            })),
+        ),
+        Ok(Err(e)) => {
+            tracing::error!(
+                "Failed to exec '{}' in '{}': {}",
+                query.command,
+                query.name,
+                e
+            );
+            docker_error(&e, "Failed to run command", Some(&query.name))
+        }
+        Err(_) => {
+            tracing::error!("Exec '{}' in '{}' timed out", query.command, query.name);
+            json_error(StatusCode::GATEWAY_TIMEOUT, "Command timed out")
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Downloads — tar archives of predefined paths and full container exports
+// ---------------------------------------------------------------------------
+
+/// Stream a tar archive from Docker as an attachment.
+///
+/// The first chunk is awaited before responding so that errors such as a
+/// missing path are reported as JSON instead of an empty archive.
+async fn tar_response<S>(stream: S, filename: &str) -> axum::response::Response
+where
+    S: futures_util::Stream<Item = Result<axum::body::Bytes, bollard::errors::Error>>
+        + Send
+        + 'static,
+{
+    use futures_util::StreamExt;
+
+    let mut stream = Box::pin(stream);
+    let first = match stream.next().await {
+        Some(Ok(chunk)) => chunk,
+        Some(Err(e)) => {
+            tracing::error!("Failed to stream '{}': {}", filename, e);
+            return docker_error(&e, "Failed to read archive from container", None).into_response();
+        }
+        None => axum::body::Bytes::new(),
+    };
+
+    let body = axum::body::Body::from_stream(
+        futures_util::stream::once(async move { Ok(first) }).chain(stream),
+    );
+    let disposition = format!("attachment; filename=\"{}\"", filename);
+    (
+        StatusCode::OK,
+        [
+            (
+                axum::http::header::CONTENT_TYPE,
+                "application/x-tar".to_string(),
+            ),
+            (axum::http::header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+async fn list_downloads_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+) -> impl IntoResponse {
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "download") {
+        tracing::warn!("Action 'download' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
+
+    let policy = current_policy(&state);
+    let mut files: Vec<&String> = policy
+        .download_paths
+        .get(&query.name)
+        .map(|p| p.keys().collect())
+        .unwrap_or_default();
+    files.sort();
+    (StatusCode::OK, Json(serde_json::json!({ "files": files })))
+}
+
+async fn download_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<DownloadQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> axum::response::Response {
+    tracing::info!(
+        "Download '{}' request for '{}' from {}",
+        query.file,
+        query.name,
+        addr
+    );
+
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name)
+            .into_response();
+    }
+    if !is_action_permitted(&state, &query.name, "download") {
+        tracing::warn!("Action 'download' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name)
+            .into_response();
+    }
+
+    let policy = current_policy(&state);
+    let Some(path) = policy
+        .download_paths
+        .get(&query.name)
+        .and_then(|p| p.get(&query.file))
+        .cloned()
+    else {
+        tracing::warn!("Download '{}' not defined for '{}'", query.file, query.name);
+        return container_error(StatusCode::FORBIDDEN, "Download not allowed", &query.name)
+            .into_response();
+    };
+
+    let options = DownloadFromContainerOptions { path: path.clone() };
+    let stream = state
+        .docker
+        .download_from_container(&query.name, Some(options));
+    let filename = format!("{}-{}.tar", query.name, query.file);
+    tar_response(stream, &filename).await
+}
+
+async fn export_container_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> axum::response::Response {
+    tracing::info!("Export request for '{}' from {}", query.name, addr);
+
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name)
+            .into_response();
+    }
+    if !is_action_permitted(&state, &query.name, "download") {
+        tracing::warn!("Action 'download' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name)
+            .into_response();
+    }
+
+    let stream = state.docker.export_container(&query.name);
+    let filename = format!("{}-export.tar", query.name);
+    tar_response(stream, &filename).await
+}
+
+// ---------------------------------------------------------------------------
+// Volumes
+// ---------------------------------------------------------------------------
+
+/// Named volumes and bind mounts of each allowed container, from the cache.
+async fn volumes_handle(State(state): State<SharedState>) -> Json<serde_json::Value> {
+    let cache = state.container_cache.read().await;
+    let containers: Vec<serde_json::Value> = cache
+        .iter()
+        .filter(|c| caller_may_access(&state, &c.name))
+        .map(|c| serde_json::json!({ "container": c.name, "mounts": c.mounts }))
+        .collect();
+    Json(serde_json::json!(containers))
+}
+
+/// Stream a tar archive of a named volume through a container that mounts it.
+async fn volume_backup_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<VolumeBackupQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> axum::response::Response {
+    tracing::info!(
+        "Backup request for volume '{}' via '{}' from {}",
+        query.volume,
+        query.name,
+        addr
+    );
+
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name)
+            .into_response();
+    }
+    if !is_action_permitted(&state, &query.name, "backup") {
+        tracing::warn!("Action 'backup' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name)
+            .into_response();
+    }
+
+    let inspect = match state
+        .docker
+        .inspect_container(&query.name, None::<InspectContainerOptions>)
+        .await
+    {
+        Ok(inspect) => inspect,
+        Err(e) => {
+            tracing::error!("Failed to inspect '{}': {}", query.name, e);
+            return docker_error(&e, "Failed to inspect container", Some(&query.name))
+                .into_response();
+        }
+    };
+
+    // Only volumes the container actually mounts can be backed up
+    let Some(mount) = extract_mounts(&inspect)
+        .into_iter()
+        .find(|m| m.kind == "volume" && m.name == query.volume)
+    else {
+        return container_error(
+            StatusCode::NOT_FOUND,
+            "Volume is not mounted by this container",
+            &query.name,
+        )
+        .into_response();
+    };
+
+    let options = DownloadFromContainerOptions {
+        path: mount.destination,
+    };
+    let stream = state
+        .docker
+        .download_from_container(&query.name, Some(options));
+    let filename = format!("{}-backup.tar", query.volume);
+    tar_response(stream, &filename).await
+}
+
+// ---------------------------------------------------------------------------
+// Compose projects — containers grouped by their compose project label
+// ---------------------------------------------------------------------------
+
+const COMPOSE_PROJECT_LABEL: &str = "com.docker.compose.project";
+
+/// Group cached containers by compose project. Containers that were not
+/// started by compose are left out.
+fn group_projects(containers: &[ContainerInfo]) -> Vec<ProjectInfo> {
+    let mut projects: Vec<ProjectInfo> = Vec::new();
+    for c in containers {
+        let Some(project) = &c.project else {
+            continue;
+        };
+        let index = match projects.iter().position(|p| &p.name == project) {
+            Some(index) => index,
+            None => {
+                projects.push(ProjectInfo {
+                    name: project.clone(),
+                    running: 0,
+                    containers: Vec::new(),
+                });
+                projects.len() - 1
+            }
+        };
+        let entry = &mut projects[index];
+        if c.state == "running" {
+            entry.running += 1;
+        }
+        entry.containers.push(c.name.clone());
+    }
+    for p in &mut projects {
+        p.containers.sort();
+    }
+    projects.sort_by(|a, b| a.name.cmp(&b.name));
+    projects
+}
+
+async fn projects_handle(State(state): State<SharedState>) -> Json<Vec<ProjectInfo>> {
+    let cache = state.container_cache.read().await;
+    let visible: Vec<ContainerInfo> = cache
+        .iter()
+        .filter(|c| caller_may_access(&state, &c.name))
+        .cloned()
+        .collect();
+    Json(group_projects(&visible))
+}
+
+const COMPOSE_SERVICE_LABEL: &str = "com.docker.compose.service";
+const COMPOSE_DEPENDS_ON_LABEL: &str = "com.docker.compose.depends_on";
+
+/// Allowed containers of a compose project, ordered so every container comes
+/// after the services it depends on. Compose records dependencies in a label
+/// like `db:service_healthy:true,cache:service_started:false`.
+async fn project_start_order(docker: &Docker, allowed: &[String], project: &str) -> Vec<String> {
+    // (container, service, services it depends on)
+    let mut members: Vec<(String, String, Vec<String>)> = Vec::new();
+    for name in allowed {
+        let Ok(inspect) = docker
+            .inspect_container(name, None::<InspectContainerOptions>)
+            .await
+        else {
+            continue;
+        };
+        let labels = inspect.config.and_then(|c| c.labels).unwrap_or_default();
+        if labels.get(COMPOSE_PROJECT_LABEL).map(String::as_str) != Some(project) {
+            continue;
+        }
+        let service = labels
+            .get(COMPOSE_SERVICE_LABEL)
+            .cloned()
+            .unwrap_or_else(|| name.clone());
+        let depends_on = labels
+            .get(COMPOSE_DEPENDS_ON_LABEL)
+            .map(|d| {
+                d.split(',')
+                    .filter_map(|dep| dep.split(':').next())
+                    .filter(|dep| !dep.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        members.push((name.clone(), service, depends_on));
+    }
+    members.sort_by(|a, b| a.0.cmp(&b.0));
+
+    // Dependencies on services outside the allowlist can't be ordered; ignore them
+    let services: HashSet<String> = members.iter().map(|m| m.1.clone()).collect();
+    let mut placed: HashSet<String> = HashSet::new();
+    let mut order = Vec::new();
+    while !members.is_empty() {
+        let ready = members.iter().position(|(_, _, deps)| {
+            deps.iter()
+                .all(|d| !services.contains(d) || placed.contains(d))
+        });
+        // A dependency cycle: keep the remaining containers in name order
+        let (name, service, _) = members.remove(ready.unwrap_or(0));
+        placed.insert(service);
+        order.push(name);
+    }
+    order
+}
+
+async fn project_start_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!("Project start request for '{}' from {}", query.name, addr);
+
+    let allowed = accessible_containers(&state);
+    let order = project_start_order(&state.docker, &allowed, &query.name).await;
+    if order.is_empty() {
+        tracing::warn!("Project '{}' has no allowed containers", query.name);
+        return json_error(StatusCode::NOT_FOUND, "Project not found");
+    }
+
+    let mut results = Vec::new();
+    for name in &order {
+        if !is_action_permitted(&state, name, "start") {
+            results.push(serde_json::json!({ "name": name, "error": ACTION_NOT_PERMITTED }));
+            continue;
+        }
+        if let Err(e) = resolve_exclusions(&state, name).await {
+            tracing::warn!("{}", e);
+            results.push(serde_json::json!({ "name": name, "error": e }));
+            continue;
+        }
+        if let Err(e) = start_dependencies(&state, name).await {
+            tracing::error!("Dependencies of '{}' failed: {}", name, e);
+            results.push(serde_json::json!({ "name": name, "error": e }));
+            continue;
+        }
+        tracing::info!("Starting '{}'...", name);
+        match state
+            .docker
+            .start_container(name, None::<StartContainerOptions<String>>)
+            .await
+        {
+            Ok(_) => {
+                results.push(serde_json::json!({ "name": name, "status": "started" }));
+            }
+            Err(e) => {
+                tracing::error!("Failed to start '{}': {}", name, e);
+                results.push(serde_json::json!({ "name": name, "error": e.to_string() }));
+            }
+        }
+    }
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({ "results": results })),
+    )
+}
+
+async fn project_stop_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!("Project stop request for '{}' from {}", query.name, addr);
+
+    let allowed = accessible_containers(&state);
+    let order = project_start_order(&state.docker, &allowed, &query.name).await;
+    if order.is_empty() {
+        tracing::warn!("Project '{}' has no allowed containers", query.name);
+        return json_error(StatusCode::NOT_FOUND, "Project not found");
+    }
+
+    // Dependents stop before the services they rely on
+    let mut results = Vec::new();
+    for name in order.iter().rev() {
+        if !is_action_permitted(&state, name, "stop") {
+            results.push(serde_json::json!({ "name": name, "error": ACTION_NOT_PERMITTED }));
+            continue;
+        }
+        tracing::info!("Stopping '{}'...", name);
+        match state
+            .docker
+            .stop_container(name, None::<StopContainerOptions>)
+            .await
+        {
+            Ok(_) => {
+                results.push(serde_json::json!({ "name": name, "status": "stopped" }));
+            }
+            Err(e) => {
+                tracing::error!("Failed to stop '{}': {}", name, e);
+                results.push(serde_json::json!({ "name": name, "error": e.to_string() }));
+            }
+        }
+    }
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({ "results": results })),
+    )
+}
+
+// ---------------------------------------------------------------------------
+// Named groups — container sets declared in the groups file
+// ---------------------------------------------------------------------------
+
+async fn groups_handle(State(state): State<SharedState>) -> Json<Vec<GroupInfo>> {
+    let cache = state.container_cache.read().await;
+    let policy = current_policy(&state);
+    let mut groups: Vec<GroupInfo> = policy
+        .groups
+        .iter()
+        .filter(|(_, members)| members.iter().any(|m| caller_may_access(&state, m)))
+        .map(|(name, members)| GroupInfo {
+            name: name.clone(),
+            running: cache
+                .iter()
+                .filter(|c| members.contains(&c.name) && c.state == "running")
+                .count(),
+            containers: members.clone(),
+        })
+        .collect();
+    groups.sort_by(|a, b| a.name.cmp(&b.name));
+    Json(groups)
+}
+
+/// Start, stop or restart every allowed member of a group. Members start in
+/// the order they are listed and stop in reverse.
+async fn run_group_action(
+    state: &AppState,
+    group: &str,
+    members: &[String],
+    action: &str,
+) -> Vec<serde_json::Value> {
+    let mut members: Vec<&String> = members
+        .iter()
+        .filter(|name| is_container_allowed(state, name))
+        .collect();
+    if action == "stop" {
+        members.reverse();
+    }
+
+    let mut results = Vec::new();
+    for name in members {
+        if !is_action_permitted(state, name, action) {
+            results.push(serde_json::json!({ "name": name, "error": ACTION_NOT_PERMITTED }));
+            continue;
+        }
+        tracing::info!("Group '{}': {} '{}'", group, action, name);
+        let result = match action {
+            "start" => {
+                if let Err(e) = resolve_exclusions(state, name).await {
+                    tracing::warn!("{}", e);
+                    results.push(serde_json::json!({ "name": name, "error": e }));
+                    continue;
+                }
+                if let Err(e) = start_dependencies(state, name).await {
+                    tracing::error!("Dependencies of '{}' failed: {}", name, e);
+                    results.push(serde_json::json!({ "name": name, "error": e }));
+                    continue;
+                }
+                state
+                    .docker
+                    .start_container(name, None::<StartContainerOptions<String>>)
+                    .await
+                    .map(|_| "started")
+            }
+            "stop" => state
+                .docker
+                .stop_container(name, None::<StopContainerOptions>)
+                .await
+                .map(|_| "stopped"),
+            _ => state
+                .docker
+                .restart_container(
+                    name,
+                    Some(RestartContainerOptions {
+                        t: state.restart_timeout,
+                    }),
+                )
+                .await
+                .map(|_| "restarted"),
+        };
+        match result {
+            Ok(status) => results.push(serde_json::json!({ "name": name, "status": status })),
+            Err(e) => {
+                tracing::error!("Failed to {} '{}': {}", action, name, e);
+                results.push(serde_json::json!({ "name": name, "error": e.to_string() }));
+            }
+        }
+    }
+    results
+}
+
+async fn group_action_handle(
+    state: SharedState,
+    group: String,
+    action: &str,
+    addr: SocketAddr,
+) -> (StatusCode, Json<serde_json::Value>) {
+    tracing::info!("Group {} request for '{}' from {}", action, group, addr);
+
+    // One snapshot, so a reload removing the group meanwhile can't pull it away
+    let policy = current_policy(&state);
+    let Some(members) = policy.groups.get(&group) else {
+        tracing::warn!("Group '{}' not found", group);
+        return json_error(StatusCode::NOT_FOUND, "Group not found");
+    };
+
+    let results = run_group_action(&state, &group, members, action).await;
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({ "results": results })),
+    )
+}
+
+async fn group_start_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    group_action_handle(state, query.name, "start", addr).await
+}
+
+async fn group_stop_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    group_action_handle(state, query.name, "stop", addr).await
+}
+
+async fn group_restart_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    group_action_handle(state, query.name, "restart", addr).await
+}
+
+// ---------------------------------------------------------------------------
+// Profiles — switch between sets of containers
+// ---------------------------------------------------------------------------
+
+async fn profiles_handle(State(state): State<SharedState>) -> Json<Vec<GroupInfo>> {
+    let cache = state.container_cache.read().await;
+    let policy = current_policy(&state);
+    let mut profiles: Vec<GroupInfo> = policy
+        .profiles
+        .iter()
+        .filter(|(_, members)| members.iter().any(|m| caller_may_access(&state, m)))
+        .map(|(name, members)| GroupInfo {
+            name: name.clone(),
+            running: cache
+                .iter()
+                .filter(|c| members.contains(&c.name) && c.state == "running")
+                .count(),
+            containers: members.clone(),
+        })
+        .collect();
+    profiles.sort_by(|a, b| a.name.cmp(&b.name));
+    Json(profiles)
+}
+
+/// Stop every allowed container outside the profile, then start the
+/// profile's containers. Dependencies of profile members count as part of
+/// the profile so they aren't stopped only to be started again.
+async fn activate_profile_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!(
+        "Profile activate request for '{}' from {}",
+        query.name,
+        addr
+    );
+
+    let policy = current_policy(&state);
+    let Some(members) = policy.profiles.get(&query.name) else {
+        tracing::warn!("Profile '{}' not found", query.name);
+        return json_error(StatusCode::NOT_FOUND, "Profile not found");
+    };
+
+    let mut keep: HashSet<String> = members.iter().cloned().collect();
+    for name in members {
+        keep.extend(dependency_order(&policy.dependencies, name));
+    }
+
+    let mut results = Vec::new();
+    // Containers that may not be stopped are left running
+    let accessible = accessible_containers(&state);
+    let others = accessible
+        .iter()
+        .filter(|n| !keep.contains(*n) && is_action_permitted(&state, n, "stop"));
+    for name in others {
+        match state
+            .docker
+            .stop_container(name, None::<StopContainerOptions>)
+            .await
+        {
+            Ok(_) => {
+                tracing::info!("Profile '{}': stopped '{}'", query.name, name);
+                results.push(serde_json::json!({ "name": name, "status": "stopped" }));
+            }
+            Err(e) => {
+                tracing::error!("Failed to stop '{}': {}", name, e);
+                results.push(serde_json::json!({ "name": name, "error": e.to_string() }));
+            }
+        }
+    }
+
+    for name in members.iter().filter(|n| is_container_allowed(&state, n)) {
+        if !is_action_permitted(&state, name, "start") {
+            results.push(serde_json::json!({ "name": name, "error": ACTION_NOT_PERMITTED }));
+            continue;
+        }
+        if let Err(e) = resolve_exclusions(&state, name).await {
+            tracing::warn!("{}", e);
+            results.push(serde_json::json!({ "name": name, "error": e }));
+            continue;
+        }
+        if let Err(e) = start_dependencies(&state, name).await {
+            tracing::error!("Dependencies of '{}' failed: {}", name, e);
+            results.push(serde_json::json!({ "name": name, "error": e }));
+            continue;
+        }
+        match state
+            .docker
+            .start_container(name, None::<StartContainerOptions<String>>)
+            .await
+        {
+            Ok(_) => {
+                tracing::info!("Profile '{}': started '{}'", query.name, name);
+                results.push(serde_json::json!({ "name": name, "status": "started" }));
+            }
+            Err(e) => {
+                tracing::error!("Failed to start '{}': {}", name, e);
+                results.push(serde_json::json!({ "name": name, "error": e.to_string() }));
+            }
+        }
+    }
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({ "results": results })),
+    )
+}
+
+// ---------------------------------------------------------------------------
+// Swarm services — list and scale services named in the services allowlist
+// ---------------------------------------------------------------------------
+
+fn service_info(service: bollard::models::Service) -> Option<ServiceInfo> {
+    let spec = service.spec?;
+    let name = spec.name?;
+    let image = spec
+        .task_template
+        .and_then(|t| t.container_spec)
+        .and_then(|c| c.image)
+        .unwrap_or_default();
+    let replicas = spec
+        .mode
+        .as_ref()
+        .and_then(|m| m.replicated.as_ref())
+        .and_then(|r| r.replicas);
+    let mode = if spec.mode.as_ref().is_some_and(|m| m.global.is_some()) {
+        "global"
+    } else {
+        "replicated"
+    };
+    let status = service.service_status.unwrap_or_default();
+    Some(ServiceInfo {
+        name,
+        image,
+        mode: mode.to_string(),
+        replicas,
+        running_tasks: status.running_tasks.unwrap_or(0),
+        desired_tasks: status.desired_tasks.unwrap_or(0),
+    })
+}
+
+async fn services_handle(State(state): State<SharedState>) -> impl IntoResponse {
+    let policy = current_policy(&state);
+    if policy.allowed_services.is_empty() {
+        return (StatusCode::OK, Json(serde_json::json!([])));
+    }
 
-    match result {
-        Ok(Ok(response)) => {
-            let players: Vec<PlayerInfo> = response
-                .sample
-                .unwrap_or_default()
+    let options = ListServicesOptions::<String> {
+        status: true,
+        ..Default::default()
+    };
+    match state.docker.list_services(Some(options)).await {
+        Ok(services) => {
+            let mut services: Vec<ServiceInfo> = services
                 .into_iter()
-                .map(|p| PlayerInfo {
-                    name: p.name,
-                    x: None,
-                    y: None,
-                    z: None,
-                })
+                .filter_map(service_info)
+                .filter(|s| policy.allowed_services.contains(&s.name))
                 .collect();
+            services.sort_by(|a, b| a.name.cmp(&b.name));
+            (StatusCode::OK, Json(serde_json::json!(services)))
+        }
+        Err(e) => {
+            tracing::error!("Failed to list services: {}", e);
+            docker_error(&e, "Failed to list services", None)
+        }
+    }
+}
 
-            let mods = if let Some(mod_info) = &response.mod_info {
-                mod_info
-                    .mod_list
-                    .iter()
-                    .map(|m| ModEntry {
-                        id: m.mod_id.clone(),
-                        version: m.version.clone(),
-                    })
-                    .collect()
-            } else if let Some(forge_data) = &response.forge_data {
-                forge_data
-                    .mods
-                    .iter()
-                    .map(|m| ModEntry {
-                        id: m.mod_id.clone(),
-                        version: m.mod_marker.clone(),
-                    })
-                    .collect()
-            } else {
-                Vec::new()
-            };
+async fn scale_service_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ScaleQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!(
+        "Scale request for '{}' to {} from {}",
+        query.name,
+        query.replicas,
+        addr
+    );
 
-            Some(MinecraftInfo {
-                version: response.version,
-                player_count: response.online_players as i32,
-                max_players: response.max_players as i32,
-                players,
-                mods,
-                seed: seed.to_string(),
+    if !current_policy(&state)
+        .allowed_services
+        .contains(&query.name)
+    {
+        tracing::warn!("Service '{}' not allowed", query.name);
+        return json_error(StatusCode::FORBIDDEN, "Service not allowed");
+    }
+
+    let service = match state.docker.inspect_service(&query.name, None).await {
+        Ok(service) => service,
+        Err(e) => {
+            tracing::error!("Failed to inspect service '{}': {}", query.name, e);
+            return docker_error(&e, "Failed to inspect service", None);
+        }
+    };
+
+    let version = service.version.and_then(|v| v.index).unwrap_or(0);
+    let mut spec = service.spec.unwrap_or_default();
+    let Some(replicated) = spec.mode.as_mut().and_then(|m| m.replicated.as_mut()) else {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            "Only replicated services can be scaled",
+        );
+    };
+    replicated.replicas = Some(query.replicas as i64);
+
+    let options = UpdateServiceOptions {
+        version,
+        ..Default::default()
+    };
+    match state
+        .docker
+        .update_service(&query.name, spec, options, None)
+        .await
+    {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "status": "scaled", "replicas": query.replicas })),
+        ),
+        Err(e) => {
+            tracing::error!("Failed to scale '{}': {}", query.name, e);
+            docker_error(&e, "Failed to scale service", None)
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Stacks — deploy and tear down compose files through the Docker API
+// ---------------------------------------------------------------------------
+
+/// Marks everything created by a stack deploy, so teardown never touches
+/// containers, networks or volumes it didn't create.
+const STACK_LABEL: &str = "docker-direct.stack";
+
+fn stack_labels(project: &str) -> HashMap<String, String> {
+    HashMap::from([
+        (COMPOSE_PROJECT_LABEL.to_string(), project.to_string()),
+        (STACK_LABEL.to_string(), project.to_string()),
+    ])
+}
+
+/// Compose project names: lowercase letters, digits, dashes and underscores.
+fn is_valid_project_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// Names of a list-or-mapping value (`networks`, `depends_on`).
+fn compose_keys(value: &Option<ComposeListOrMap>) -> Vec<String> {
+    match value {
+        Some(ComposeListOrMap::List(items)) => items.clone(),
+        Some(ComposeListOrMap::Map(map)) => {
+            let mut keys: Vec<String> = map.keys().cloned().collect();
+            keys.sort();
+            keys
+        }
+        None => Vec::new(),
+    }
+}
+
+/// `KEY=value` pairs of a list-or-mapping value (`environment`, `labels`).
+fn compose_pairs(value: &Option<ComposeListOrMap>) -> Vec<(String, String)> {
+    match value {
+        Some(ComposeListOrMap::List(items)) => items
+            .iter()
+            .map(|item| match item.split_once('=') {
+                Some((key, value)) => (key.to_string(), value.to_string()),
+                None => (item.clone(), String::new()),
+            })
+            .collect(),
+        Some(ComposeListOrMap::Map(map)) => map
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    Some(serde_json::Value::String(s)) => s.clone(),
+                    Some(serde_json::Value::Null) | None => String::new(),
+                    Some(other) => other.to_string(),
+                };
+                (key.clone(), value)
             })
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Docker name of a top-level network or volume: external ones keep their
+/// own name, the rest are prefixed with the project like compose does.
+fn stack_resource_name(project: &str, key: &str, resource: Option<&ComposeResource>) -> String {
+    match resource {
+        Some(r) if r.name.is_some() => r.name.clone().unwrap_or_default(),
+        Some(r) if r.external => key.to_string(),
+        _ => format!("{}_{}", project, key),
+    }
+}
+
+/// Services in start order, each after the services it depends on.
+fn stack_service_order(compose: &ComposeFile) -> Result<Vec<String>, String> {
+    let mut dependencies = HashMap::new();
+    for (service, spec) in &compose.services {
+        let deps = compose_keys(&spec.depends_on);
+        if let Some(missing) = deps.iter().find(|d| !compose.services.contains_key(*d)) {
+            return Err(format!(
+                "Service '{}' depends on unknown service '{}'",
+                service, missing
+            ));
         }
-        Ok(Err(e)) => {
-            tracing::debug!("Minecraft ping failed on port {}: {}", host_port, e);
-            None
+        dependencies.insert(service.clone(), deps);
+    }
+    check_dependency_cycles(&dependencies).map_err(|e| e.to_string())?;
+
+    let mut services: Vec<&String> = compose.services.keys().collect();
+    services.sort();
+    let mut order = Vec::new();
+    for service in services {
+        for name in dependency_order(&dependencies, service)
+            .into_iter()
+            .chain(std::iter::once(service.clone()))
+        {
+            if !order.contains(&name) {
+                order.push(name);
+            }
         }
-        Err(_) => {
-            tracing::debug!("Minecraft ping timed out on port {}", host_port);
-            None
+    }
+    Ok(order)
+}
+
+/// Parse a compose port like `80`, `8080:80` or `127.0.0.1:8080:80/udp` into
+/// the container port key and an optional host binding.
+fn parse_compose_port(spec: &str) -> Result<(String, Option<PortBinding>), String> {
+    let (ports, protocol) = spec.split_once('/').unwrap_or((spec, "tcp"));
+    let parts: Vec<&str> = ports.split(':').collect();
+    let (host_ip, host_port, container_port) = match parts.as_slice() {
+        [container] => (None, None, *container),
+        [host, container] => (None, Some(*host), *container),
+        [ip, host, container] => (Some(*ip), Some(*host), *container),
+        _ => return Err(format!("Invalid port '{}'", spec)),
+    };
+    let valid = container_port.parse::<u16>().is_ok()
+        && host_port.is_none_or(|h| h.parse::<u16>().is_ok())
+        && matches!(protocol, "tcp" | "udp" | "sctp");
+    if !valid {
+        return Err(format!("Invalid port '{}'", spec));
+    }
+
+    let binding = host_port.map(|host| PortBinding {
+        host_ip: host_ip.map(String::from),
+        host_port: Some(host.to_string()),
+    });
+    Ok((format!("{}/{}", container_port, protocol), binding))
+}
+
+/// Turn a compose volume entry (`source:target[:mode]`) into a bind. Named
+/// volumes must be declared at the top level; relative host paths are
+/// resolved against the compose file's directory, which uploads don't have.
+fn compose_bind(
+    spec: &str,
+    project: &str,
+    compose: &ComposeFile,
+    base_dir: Option<&std::path::Path>,
+) -> Result<String, String> {
+    let mut parts = spec.splitn(3, ':');
+    let source = parts.next().unwrap_or_default();
+    let Some(target) = parts.next() else {
+        return Err(format!(
+            "Anonymous volume '{}' is not supported, declare a named volume",
+            spec
+        ));
+    };
+    let mode = parts.next().map(|m| format!(":{}", m)).unwrap_or_default();
+
+    let source = if source.starts_with('/') {
+        source.to_string()
+    } else if source.starts_with('.') {
+        let Some(base_dir) = base_dir else {
+            return Err(format!(
+                "Relative path '{}' is only allowed in compose files from --stacks-file",
+                source
+            ));
+        };
+        base_dir
+            .join(source.trim_start_matches("./"))
+            .to_string_lossy()
+            .into_owned()
+    } else if let Some(resource) = compose.volumes.get(source) {
+        stack_resource_name(project, source, resource.as_ref())
+    } else {
+        return Err(format!(
+            "Volume '{}' is not declared in the top-level volumes",
+            source
+        ));
+    };
+    Ok(format!("{}:{}{}", source, target, mode))
+}
+
+/// Build the create request for one service. Returns the config and the
+/// Docker networks to attach, the first of which is set at create time.
+fn stack_container_config(
+    project: &str,
+    service: &str,
+    spec: &ComposeService,
+    compose: &ComposeFile,
+    networks: &HashMap<String, String>,
+    base_dir: Option<&std::path::Path>,
+) -> Result<(Config<String>, Vec<String>), String> {
+    let env: Vec<String> = compose_pairs(&spec.environment)
+        .into_iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+
+    let mut labels: HashMap<String, String> = compose_pairs(&spec.labels).into_iter().collect();
+    labels.extend(stack_labels(project));
+    labels.insert(COMPOSE_SERVICE_LABEL.to_string(), service.to_string());
+    let depends_on = compose_keys(&spec.depends_on);
+    if !depends_on.is_empty() {
+        let value: Vec<String> = depends_on
+            .iter()
+            .map(|d| format!("{}:service_started:false", d))
+            .collect();
+        labels.insert(COMPOSE_DEPENDS_ON_LABEL.to_string(), value.join(","));
+    }
+
+    let cmd = spec.command.as_ref().map(|command| match command {
+        ComposeCommand::Line(line) => line.split_whitespace().map(String::from).collect(),
+        ComposeCommand::Args(args) => args.clone(),
+    });
+
+    let mut exposed_ports = HashMap::new();
+    let mut port_bindings: HashMap<String, Option<Vec<PortBinding>>> = HashMap::new();
+    for port in &spec.ports {
+        let (key, binding) = parse_compose_port(port)?;
+        exposed_ports.insert(key.clone(), HashMap::new());
+        if let Some(binding) = binding {
+            port_bindings
+                .entry(key)
+                .or_default()
+                .get_or_insert_with(Vec::new)
+                .push(binding);
         }
     }
+
+    let binds = spec
+        .volumes
+        .iter()
+        .map(|v| compose_bind(v, project, compose, base_dir))
+        .collect::<Result<Vec<String>, String>>()?;
+
+    let restart_policy =
+        match &spec.restart {
+            Some(restart) => Some(RestartPolicy {
+                name: Some(restart.parse::<RestartPolicyNameEnum>().map_err(|_| {
+                    format!("Invalid restart policy '{}' for '{}'", restart, service)
+                })?),
+                maximum_retry_count: None,
+            }),
+            None => None,
+        };
+
+    let mut network_keys = compose_keys(&spec.networks);
+    if network_keys.is_empty() {
+        network_keys.push("default".to_string());
+    }
+    let network_names = network_keys
+        .iter()
+        .map(|key| {
+            networks
+                .get(key)
+                .cloned()
+                .ok_or_else(|| format!("Service '{}' uses undeclared network '{}'", service, key))
+        })
+        .collect::<Result<Vec<String>, String>>()?;
+
+    let host_config = bollard::models::HostConfig {
+        binds: (!binds.is_empty()).then_some(binds),
+        port_bindings: (!port_bindings.is_empty()).then_some(port_bindings),
+        restart_policy,
+        network_mode: network_names.first().cloned(),
+        ..Default::default()
+    };
+    let endpoint = EndpointSettings {
+        aliases: Some(vec![service.to_string()]),
+        ..Default::default()
+    };
+
+    let config = Config {
+        image: Some(spec.image.clone()),
+        env: (!env.is_empty()).then_some(env),
+        cmd,
+        labels: Some(labels),
+        exposed_ports: (!exposed_ports.is_empty()).then_some(exposed_ports),
+        host_config: Some(host_config),
+        networking_config: Some(NetworkingConfig {
+            endpoints_config: HashMap::from([(network_names[0].clone(), endpoint)]),
+        }),
+        ..Default::default()
+    };
+    Ok((config, network_names))
+}
+
+fn stack_result(resource: String, status: &str) -> serde_json::Value {
+    serde_json::json!({ "resource": resource, "status": status })
+}
+
+/// Create the networks, volumes and containers of a compose file and start
+/// the containers in dependency order, recording each step in `results`.
+async fn deploy_stack(
+    docker: &Docker,
+    project: &str,
+    compose: &ComposeFile,
+    base_dir: Option<&std::path::Path>,
+    results: &mut Vec<serde_json::Value>,
+) -> Result<(), String> {
+    let order = stack_service_order(compose)?;
+
+    // Every declared network, plus `default` when a service doesn't pick one
+    let mut network_keys: Vec<String> = compose.networks.keys().cloned().collect();
+    let uses_default = compose
+        .services
+        .values()
+        .any(|s| compose_keys(&s.networks).is_empty());
+    if uses_default && !compose.networks.contains_key("default") {
+        network_keys.push("default".to_string());
+    }
+    network_keys.sort();
+
+    let mut networks = HashMap::new();
+    for key in network_keys {
+        let resource = compose.networks.get(&key).cloned().flatten();
+        let name = stack_resource_name(project, &key, resource.as_ref());
+        if !resource.is_some_and(|r| r.external) {
+            let options = CreateNetworkOptions {
+                name: name.clone(),
+                driver: "bridge".to_string(),
+                labels: stack_labels(project),
+                ..Default::default()
+            };
+            match docker.create_network(options).await {
+                Ok(_) => results.push(stack_result(format!("network {}", name), "created")),
+                Err(bollard::errors::Error::DockerResponseServerError {
+                    status_code: 409, ..
+                }) => results.push(stack_result(format!("network {}", name), "exists")),
+                Err(e) => return Err(format!("Failed to create network '{}': {}", name, e)),
+            }
+        }
+        networks.insert(key, name);
+    }
+
+    let mut volume_keys: Vec<&String> = compose.volumes.keys().collect();
+    volume_keys.sort();
+    for key in volume_keys {
+        let resource = compose.volumes[key].as_ref();
+        if resource.is_some_and(|r| r.external) {
+            continue;
+        }
+        let name = stack_resource_name(project, key, resource);
+        let options = CreateVolumeOptions {
+            name: name.clone(),
+            driver: "local".to_string(),
+            labels: stack_labels(project),
+            ..Default::default()
+        };
+        docker
+            .create_volume(options)
+            .await
+            .map_err(|e| format!("Failed to create volume '{}': {}", name, e))?;
+        results.push(stack_result(format!("volume {}", name), "ready"));
+    }
+
+    for service in &order {
+        let spec = &compose.services[service];
+        let container = spec
+            .container_name
+            .clone()
+            .unwrap_or_else(|| format!("{}-{}-1", project, service));
+        let (config, network_names) =
+            stack_container_config(project, service, spec, compose, &networks, base_dir)?;
+
+        if docker.inspect_image(&spec.image).await.is_err() {
+            pull_image(docker, &spec.image)
+                .await
+                .map_err(|e| format!("Failed to pull '{}': {}", spec.image, e))?;
+            results.push(stack_result(format!("image {}", spec.image), "pulled"));
+        }
+
+        // Replace a container from an earlier deploy of this stack, but never
+        // one that belongs to something else
+        if let Ok(existing) = docker
+            .inspect_container(&container, None::<InspectContainerOptions>)
+            .await
+        {
+            let owner = existing
+                .config
+                .and_then(|c| c.labels)
+                .and_then(|l| l.get(STACK_LABEL).cloned());
+            if owner.as_deref() != Some(project) {
+                return Err(format!(
+                    "Container '{}' already exists and is not part of stack '{}'",
+                    container, project
+                ));
+            }
+            let options = RemoveContainerOptions {
+                force: true,
+                ..Default::default()
+            };
+            docker
+                .remove_container(&container, Some(options))
+                .await
+                .map_err(|e| format!("Failed to remove old '{}': {}", container, e))?;
+        }
+
+        docker
+            .create_container(
+                Some(CreateContainerOptions {
+                    name: container.clone(),
+                    platform: None,
+                }),
+                config,
+            )
+            .await
+            .map_err(|e| format!("Failed to create '{}': {}", container, e))?;
+
+        // Only one network can be attached at create time
+        for network in network_names.iter().skip(1) {
+            let options = ConnectNetworkOptions {
+                container: container.as_str(),
+                endpoint_config: EndpointSettings {
+                    aliases: Some(vec![service.clone()]),
+                    ..Default::default()
+                },
+            };
+            docker
+                .connect_network(network, options)
+                .await
+                .map_err(|e| {
+                    format!("Failed to connect '{}' to '{}': {}", container, network, e)
+                })?;
+        }
+
+        docker
+            .start_container(&container, None::<StartContainerOptions<String>>)
+            .await
+            .map_err(|e| format!("Failed to start '{}': {}", container, e))?;
+        results.push(stack_result(format!("container {}", container), "started"));
+    }
+    Ok(())
+}
+
+/// Stop and remove everything a deploy of `project` created. Volumes hold
+/// data, so they are only removed when asked for.
+async fn teardown_stack(
+    docker: &Docker,
+    project: &str,
+    remove_volumes: bool,
+    results: &mut Vec<serde_json::Value>,
+) -> Result<(), String> {
+    let filters = HashMap::from([(
+        "label".to_string(),
+        vec![format!("{}={}", STACK_LABEL, project)],
+    )]);
+
+    let containers = docker
+        .list_containers(Some(ListContainersOptions {
+            all: true,
+            filters: filters.clone(),
+            ..Default::default()
+        }))
+        .await
+        .map_err(|e| format!("Failed to list containers: {}", e))?;
+    for container in containers {
+        let Some(name) = container
+            .names
+            .as_ref()
+            .and_then(|n| n.first())
+            .map(|n| n.trim_start_matches('/').to_string())
+        else {
+            continue;
+        };
+        docker
+            .stop_container(&name, None::<StopContainerOptions>)
+            .await
+            .map_err(|e| format!("Failed to stop '{}': {}", name, e))?;
+        docker
+            .remove_container(&name, None::<RemoveContainerOptions>)
+            .await
+            .map_err(|e| format!("Failed to remove '{}': {}", name, e))?;
+        results.push(stack_result(format!("container {}", name), "removed"));
+    }
+
+    let networks = docker
+        .list_networks(Some(ListNetworksOptions {
+            filters: filters.clone(),
+        }))
+        .await
+        .map_err(|e| format!("Failed to list networks: {}", e))?;
+    for name in networks.into_iter().filter_map(|n| n.name) {
+        docker
+            .remove_network(&name)
+            .await
+            .map_err(|e| format!("Failed to remove network '{}': {}", name, e))?;
+        results.push(stack_result(format!("network {}", name), "removed"));
+    }
+
+    if remove_volumes {
+        let volumes = docker
+            .list_volumes(Some(ListVolumesOptions { filters }))
+            .await
+            .map_err(|e| format!("Failed to list volumes: {}", e))?;
+        for volume in volumes.volumes.unwrap_or_default() {
+            docker
+                .remove_volume(&volume.name, None)
+                .await
+                .map_err(|e| format!("Failed to remove volume '{}': {}", volume.name, e))?;
+            results.push(stack_result(format!("volume {}", volume.name), "removed"));
+        }
+    }
+    Ok(())
+}
+
+fn stack_response(
+    project: &str,
+    action: &str,
+    result: Result<(), String>,
+    results: Vec<serde_json::Value>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    match result {
+        Ok(()) => {
+            tracing::info!("Stack '{}' {}", project, action);
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({ "status": action, "results": results })),
+            )
+        }
+        Err(e) => {
+            tracing::error!("Stack '{}' failed: {}", project, e);
+            let (status, Json(mut body)) = json_error(StatusCode::INTERNAL_SERVER_ERROR, &e);
+            body["results"] = serde_json::Value::Array(results);
+            (status, Json(body))
+        }
+    }
+}
+
+/// Configured stacks and stacks deployed earlier, with their containers.
+async fn stacks_handle(State(state): State<SharedState>) -> impl IntoResponse {
+    let options = ListContainersOptions {
+        all: true,
+        filters: HashMap::from([("label".to_string(), vec![STACK_LABEL.to_string()])]),
+        ..Default::default()
+    };
+    let containers = match state.docker.list_containers(Some(options)).await {
+        Ok(containers) => containers,
+        Err(e) => {
+            tracing::error!("Failed to list stack containers: {}", e);
+            return docker_error(&e, "Failed to list stacks", None);
+        }
+    };
+
+    // stack name -> (compose file, containers)
+    let policy = current_policy(&state);
+    let mut stacks: HashMap<String, (Option<String>, Vec<String>)> = policy
+        .stacks
+        .iter()
+        .map(|(name, file)| (name.clone(), (Some(file.clone()), Vec::new())))
+        .collect();
+    for c in containers {
+        let stack = c.labels.as_ref().and_then(|l| l.get(STACK_LABEL)).cloned();
+        let name = c
+            .names
+            .as_ref()
+            .and_then(|n| n.first())
+            .map(|n| n.trim_start_matches('/').to_string());
+        if let (Some(stack), Some(name)) = (stack, name) {
+            stacks.entry(stack).or_default().1.push(name);
+        }
+    }
+
+    let mut list: Vec<serde_json::Value> = stacks
+        .into_iter()
+        .map(|(name, (file, mut containers))| {
+            containers.sort();
+            serde_json::json!({ "name": name, "file": file, "containers": containers })
+        })
+        .collect();
+    list.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+    (StatusCode::OK, Json(serde_json::json!(list)))
+}
+
+/// Deploy a compose file listed in the stacks file.
+async fn deploy_stack_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!("Stack deploy request for '{}' from {}", query.name, addr);
+
+    let policy = current_policy(&state);
+    let Some(path) = policy.stacks.get(&query.name) else {
+        return json_error(StatusCode::NOT_FOUND, "Stack not found");
+    };
+    let compose = match read_compose_file(path) {
+        Ok(compose) => compose,
+        Err(e) => {
+            tracing::error!("{}", e);
+            return json_error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string());
+        }
+    };
+
+    let base_dir = std::path::Path::new(path).parent();
+    let mut results = Vec::new();
+    let result = deploy_stack(&state.docker, &query.name, &compose, base_dir, &mut results).await;
+    stack_response(&query.name, "deployed", result, results)
+}
+
+/// Deploy a compose file sent as the request body, in YAML or JSON, or the
+/// configured stack when the body is empty.
+async fn upload_stack_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    body: axum::body::Bytes,
+) -> axum::response::Response {
+    if body.is_empty() {
+        return deploy_stack_handle(State(state), Query(query), ConnectInfo(addr))
+            .await
+            .into_response();
+    }
+    tracing::info!("Stack upload for '{}' from {}", query.name, addr);
+
+    if !is_valid_project_name(&query.name) {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            "Stack names may only contain lowercase letters, digits, '-' and '_'",
+        )
+        .into_response();
+    }
+
+    let compose: ComposeFile = match serde_yaml::from_slice(&body) {
+        Ok(compose) => compose,
+        Err(e) => {
+            return json_error(
+                StatusCode::BAD_REQUEST,
+                &format!("Invalid compose file: {}", e),
+            )
+            .into_response()
+        }
+    };
+
+    let mut results = Vec::new();
+    let result = deploy_stack(&state.docker, &query.name, &compose, None, &mut results).await;
+    stack_response(&query.name, "deployed", result, results).into_response()
+}
+
+async fn teardown_stack_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<TeardownQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!("Stack teardown request for '{}' from {}", query.name, addr);
+
+    let remove_volumes = query.volumes.unwrap_or(false);
+    let mut results = Vec::new();
+    let result = teardown_stack(&state.docker, &query.name, remove_volumes, &mut results).await;
+    stack_response(&query.name, "removed", result, results)
 }
 
 // ---------------------------------------------------------------------------
-// RCON — fetch player coordinates
+// Networks
 // ---------------------------------------------------------------------------
 
-fn find_rcon_host_port(ports: &[PortMapping]) -> Option<u16> {
-    ports
+/// Network membership of each allowed container, plus the networks that can
+/// be connected or disconnected through the API.
+async fn networks_handle(State(state): State<SharedState>) -> Json<serde_json::Value> {
+    let cache = state.container_cache.read().await;
+    let containers: Vec<serde_json::Value> = cache
         .iter()
-        .find(|p| p.container_port == 25575)
-        .map(|p| p.host_port)
+        .filter(|c| caller_may_access(&state, &c.name))
+        .map(|c| serde_json::json!({ "container": c.name, "networks": c.networks }))
+        .collect();
+    Json(serde_json::json!({
+        "managed": current_policy(&state).managed_networks,
+        "containers": containers,
+    }))
 }
 
-/// Parse RCON response from `/data get entity <player> Pos`.
-/// Example: `PlayerName has the following entity data: [-123.45d, 64.0d, 789.12d]`
-fn parse_pos_response(response: &str) -> Option<(f64, f64, f64)> {
-    let bracket_start = response.find('[')?;
-    let bracket_end = response.find(']')?;
-    let inner = &response[bracket_start + 1..bracket_end];
-    let parts: Vec<&str> = inner.split(',').collect();
-    if parts.len() != 3 {
-        return None;
+/// Rejects the request unless both the container and the network are allowed.
+fn check_network_request(
+    state: &AppState,
+    query: &NetworkQuery,
+) -> Option<(StatusCode, Json<serde_json::Value>)> {
+    if !is_container_allowed(state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return Some(container_error(
+            StatusCode::FORBIDDEN,
+            "Container not allowed",
+            &query.name,
+        ));
     }
-    let x: f64 = parts[0].trim().trim_end_matches('d').parse().ok()?;
-    let y: f64 = parts[1].trim().trim_end_matches('d').parse().ok()?;
-    let z: f64 = parts[2].trim().trim_end_matches('d').parse().ok()?;
-    Some((x, y, z))
+    if !is_action_permitted(state, &query.name, "network") {
+        tracing::warn!("Action 'network' not permitted for '{}'", query.name);
+        return Some(container_error(
+            StatusCode::FORBIDDEN,
+            ACTION_NOT_PERMITTED,
+            &query.name,
+        ));
+    }
+    if !current_policy(state)
+        .managed_networks
+        .contains(&query.network)
+    {
+        tracing::warn!("Network '{}' not managed", query.network);
+        return Some(json_error(StatusCode::FORBIDDEN, "Network not allowed"));
+    }
+    None
 }
 
-async fn fetch_player_positions(
-    rcon_port: u16,
-    rcon_password: &str,
-    players: &mut [PlayerInfo],
-) {
-    if players.is_empty() || rcon_password.is_empty() {
-        return;
-    }
+async fn connect_network_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<NetworkQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!(
+        "Connect '{}' to network '{}' from {}",
+        query.name,
+        query.network,
+        addr
+    );
 
-    let conn_result = tokio::time::timeout(std::time::Duration::from_secs(3), async {
-        let stream =
-            tokio::net::TcpStream::connect(format!("127.0.0.1:{}", rcon_port)).await?;
-        rcon::Builder::new()
-            .enable_minecraft_quirks(true)
-            .handshake(stream, rcon_password)
-            .await
-    })
-    .await;
+    if let Some(rejection) = check_network_request(&state, &query) {
+        return rejection;
+    }
 
-    let mut conn = match conn_result {
-        Ok(Ok(c)) => c,
-        Ok(Err(e)) => {
-            tracing::debug!("RCON connect failed on port {}: {}", rcon_port, e);
-            return;
-        }
-        Err(_) => {
-            tracing::debug!("RCON connect timed out on port {}", rcon_port);
-            return;
+    let options = ConnectNetworkOptions {
+        container: query.name.as_str(),
+        endpoint_config: EndpointSettings::default(),
+    };
+    match state.docker.connect_network(&query.network, options).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "status": "connected" })),
+        ),
+        Err(e) => {
+            tracing::error!(
+                "Failed to connect '{}' to '{}': {}",
+                query.name,
+                query.network,
+                e
+            );
+            docker_error(&e, "Failed to connect network", Some(&query.name))
         }
+    }
+}
+
+async fn disconnect_network_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<NetworkQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!(
+        "Disconnect '{}' from network '{}' from {}",
+        query.name,
+        query.network,
+        addr
+    );
+
+    if let Some(rejection) = check_network_request(&state, &query) {
+        return rejection;
+    }
+
+    let options = DisconnectNetworkOptions {
+        container: query.name.as_str(),
+        force: false,
     };
+    match state
+        .docker
+        .disconnect_network(&query.network, options)
+        .await
+    {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "status": "disconnected" })),
+        ),
+        Err(e) => {
+            tracing::error!(
+                "Failed to disconnect '{}' from '{}': {}",
+                query.name,
+                query.network,
+                e
+            );
+            docker_error(&e, "Failed to disconnect network", Some(&query.name))
+        }
+    }
+}
 
-    for player in players.iter_mut() {
-        let cmd = format!("data get entity {} Pos", player.name);
-        match tokio::time::timeout(std::time::Duration::from_secs(2), conn.cmd(&cmd)).await {
-            Ok(Ok(response)) => {
-                if let Some((x, y, z)) = parse_pos_response(&response) {
-                    player.x = Some(x);
-                    player.y = Some(y);
-                    player.z = Some(z);
-                }
-            }
-            Ok(Err(e)) => {
-                tracing::debug!("RCON cmd failed for '{}': {}", player.name, e);
-            }
-            Err(_) => {
-                tracing::debug!("RCON cmd timed out for '{}'", player.name);
+// ---------------------------------------------------------------------------
+// Images
+// ---------------------------------------------------------------------------
+
+/// Split an image reference like `registry:5000/repo:tag` into repository and
+/// tag, ignoring any `@digest` suffix. Untagged references default to `latest`.
+fn split_image_reference(reference: &str) -> (String, String) {
+    let reference = reference.split('@').next().unwrap_or(reference);
+    match reference.rsplit_once(':') {
+        Some((repo, tag)) if !tag.contains('/') => (repo.to_string(), tag.to_string()),
+        _ => (reference.to_string(), "latest".to_string()),
+    }
+}
+
+/// Images used by allowed containers, one entry per image ID.
+async fn collect_images(docker: &Docker, containers: &[String]) -> Vec<ImageInfo> {
+    // image ID -> (reference used by the first container, container names)
+    let mut by_id: HashMap<String, (String, Vec<String>)> = HashMap::new();
+    for name in containers {
+        let Ok(inspect) = docker
+            .inspect_container(name, None::<InspectContainerOptions>)
+            .await
+        else {
+            continue;
+        };
+        let Some(id) = inspect.image.clone() else {
+            continue;
+        };
+        let reference = inspect
+            .config
+            .as_ref()
+            .and_then(|c| c.image.clone())
+            .unwrap_or_default();
+        by_id
+            .entry(id)
+            .or_insert_with(|| (reference, Vec::new()))
+            .1
+            .push(name.clone());
+    }
+
+    let mut images = Vec::new();
+    for (id, (reference, containers)) in by_id {
+        let image = match docker.inspect_image(&id).await {
+            Ok(image) => image,
+            Err(e) => {
+                tracing::debug!("Failed to inspect image {}: {}", id, e);
+                continue;
             }
-        }
+        };
+        let reference = if reference.is_empty() || reference.starts_with("sha256:") {
+            image
+                .repo_tags
+                .as_ref()
+                .and_then(|t| t.first().cloned())
+                .unwrap_or_default()
+        } else {
+            reference
+        };
+        let (repository, tag) = split_image_reference(&reference);
+        let digest = image
+            .repo_digests
+            .as_ref()
+            .and_then(|d| d.first())
+            .and_then(|d| d.split_once('@'))
+            .map(|(_, digest)| digest.to_string())
+            .unwrap_or_default();
+
+        images.push(ImageInfo {
+            id,
+            repository,
+            tag,
+            digest,
+            size: image.size.unwrap_or(0),
+            created: image.created.clone().unwrap_or_default(),
+            containers,
+        });
     }
+    images.sort_by(|a, b| a.repository.cmp(&b.repository).then(a.tag.cmp(&b.tag)));
+    images
 }
 
-async fn collect_container_info(docker: &Docker, name: &str) -> Option<ContainerInfo> {
-    // Inspect container
+async fn images_handle(State(state): State<SharedState>) -> Json<Vec<ImageInfo>> {
+    let allowed = accessible_containers(&state);
+    Json(collect_images(&state.docker, &allowed).await)
+}
+
+/// The image reference an allowed container was created from.
+async fn container_image_reference(
+    docker: &Docker,
+    name: &str,
+) -> Result<String, bollard::errors::Error> {
     let inspect = docker
         .inspect_container(name, None::<InspectContainerOptions>)
-        .await
-        .ok()?;
-
-    let state_obj = inspect.state.as_ref();
-    let state = state_obj
-        .and_then(|s| s.status)
-        .map(|s| format!("{:?}", s).to_lowercase())
-        .unwrap_or_else(|| "unknown".to_string());
+        .await?;
+    Ok(inspect.config.and_then(|c| c.image).unwrap_or_default())
+}
 
-    let status_str = if state == "running" {
-        state_obj
-            .and_then(|s| s.health.as_ref())
-            .and_then(|h| h.status)
-            .map(|s| format!("{:?}", s).to_lowercase())
-            .unwrap_or_else(|| state.clone())
+fn pull_options(reference: &str) -> CreateImageOptions<'static, String> {
+    // Digest references are pulled as-is; otherwise pass the tag separately
+    // so Docker doesn't pull every tag of the repository
+    let (from_image, tag) = if reference.contains('@') {
+        (reference.to_string(), String::new())
     } else {
-        state.clone()
+        split_image_reference(reference)
     };
+    CreateImageOptions {
+        from_image,
+        tag,
+        ..Default::default()
+    }
+}
 
-    let image = inspect
-        .config
-        .as_ref()
-        .and_then(|c| c.image.clone())
-        .unwrap_or_default();
+/// Pull the image of an allowed container, streaming Docker's per-layer
+/// progress as Server-Sent Events. Emits `progress` events followed by a
+/// single `done` or `error` event.
+async fn pull_image_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> axum::response::Response {
+    use futures_util::StreamExt;
 
-    let created = inspect.created.clone().unwrap_or_default();
+    tracing::info!("Pull request for image of '{}' from {}", query.name, addr);
 
-    let started_at = state_obj
-        .and_then(|s| s.started_at.clone())
-        .unwrap_or_default();
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name)
+            .into_response();
+    }
+    if !is_action_permitted(&state, &query.name, "update") {
+        tracing::warn!("Action 'update' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name)
+            .into_response();
+    }
 
-    let uptime = if state == "running" {
-        format_uptime(&started_at)
-    } else {
-        String::new()
+    let reference = match container_image_reference(&state.docker, &query.name).await {
+        Ok(r) if !r.is_empty() && !r.starts_with("sha256:") => r,
+        Ok(_) => {
+            return container_error(
+                StatusCode::CONFLICT,
+                "Container was created from an image ID, not a pullable reference",
+                &query.name,
+            )
+            .into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to inspect '{}': {}", query.name, e);
+            return docker_error(&e, "Failed to inspect container", Some(&query.name))
+                .into_response();
+        }
     };
 
-    let restart_count = inspect.restart_count.unwrap_or(0);
+    let pull = state
+        .docker
+        .create_image(Some(pull_options(&reference)), None, None);
 
-    let ports = extract_ports(&inspect);
+    // Set once an error event is sent so the stream doesn't also report `done`
+    let failed = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let failed_done = Arc::clone(&failed);
 
-    let running = state == "running";
+    let events = pull
+        .map(move |item| {
+            let error_event = |error: String| {
+                failed.store(true, std::sync::atomic::Ordering::Relaxed);
+                Event::default()
+                    .event("error")
+                    .json_data(serde_json::json!({ "error": error }))
+            };
+            let event = match item {
+                Ok(CreateImageInfo {
+                    error: Some(error), ..
+                }) => error_event(error),
+                Ok(info) => {
+                    let detail = info.progress_detail.unwrap_or_default();
+                    Event::default()
+                        .event("progress")
+                        .json_data(serde_json::json!({
+                            "id": info.id,
+                            "status": info.status,
+                            "current": detail.current,
+                            "total": detail.total,
+                        }))
+                }
+                Err(e) => error_event(e.to_string()),
+            };
+            Ok::<_, std::convert::Infallible>(event.unwrap_or_default())
+        })
+        .chain(futures_util::stream::once(async move {
+            if failed_done.load(std::sync::atomic::Ordering::Relaxed) {
+                return Ok(Event::default().comment("pull failed"));
+            }
+            tracing::info!("Pulled {}", reference);
+            Ok(Event::default()
+                .event("done")
+                .json_data(serde_json::json!({ "image": reference }))
+                .unwrap_or_default())
+        }));
 
-    // Only fetch stats for running containers — stats is expensive (cgroup reads)
-    let (cpu_percent, memory_usage, memory_limit) = if running {
-        get_container_stats(docker, name).await
-    } else {
-        (0.0, 0, 0)
-    };
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
 
-    // Extract env vars (itzg/minecraft-server convention)
-    let envs = inspect
-        .config
-        .as_ref()
-        .and_then(|c| c.env.as_ref());
+/// Layer history of the image behind an allowed container, newest layer first.
+async fn image_history_handle(
+    State(state): State<SharedState>,
+    Query(query): Query<ContainerName>,
+) -> impl IntoResponse {
+    if !is_container_allowed(&state, &query.name) {
+        tracing::warn!("Container '{}' not allowed", query.name);
+        return container_error(StatusCode::FORBIDDEN, "Container not allowed", &query.name);
+    }
+    if !is_action_permitted(&state, &query.name, "inspect") {
+        tracing::warn!("Action 'inspect' not permitted for '{}'", query.name);
+        return container_error(StatusCode::FORBIDDEN, ACTION_NOT_PERMITTED, &query.name);
+    }
 
-    let seed = envs
-        .and_then(|e| e.iter().find_map(|v| v.strip_prefix("SEED=").map(|s| s.to_string())))
-        .unwrap_or_default();
+    let result = async {
+        let inspect = state
+            .docker
+            .inspect_container(&query.name, None::<InspectContainerOptions>)
+            .await?;
+        let image = inspect.image.unwrap_or_default();
+        state.docker.image_history(&image).await
+    }
+    .await;
 
-    let rcon_password = envs
-        .and_then(|e| e.iter().find_map(|v| v.strip_prefix("RCON_PASSWORD=").map(|s| s.to_string())))
-        .unwrap_or_default();
+    match result {
+        Ok(history) => {
+            let layers: Vec<serde_json::Value> = history
+                .into_iter()
+                .map(|layer| {
+                    serde_json::json!({
+                        "id": layer.id,
+                        "created": layer.created,
+                        "created_by": layer.created_by,
+                        "size": layer.size,
+                        "tags": layer.tags,
+                        "comment": layer.comment,
+                    })
+                })
+                .collect();
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({ "layers": layers })),
+            )
+        }
+        Err(e) => {
+            tracing::error!("Failed to read image history for '{}': {}", query.name, e);
+            docker_error(&e, "Failed to read image history", Some(&query.name))
+        }
+    }
+}
 
-    // Mark as MC container (has port 25565) — actual ping data merged from mc_cache later
-    let is_mc = find_minecraft_host_port(&ports).is_some();
-    let minecraft = if is_mc {
-        Some(MinecraftInfo {
-            version: String::new(),
-            player_count: 0,
-            max_players: 0,
-            players: Vec::new(),
-            mods: Vec::new(),
-            seed,
-        })
-    } else {
-        None
-    };
+/// Remove dangling (untagged, unused) images. Admin only.
+async fn prune_images_handle(
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!("Image prune request from {}", addr);
 
-    Some(ContainerInfo {
-        name: name.to_string(),
-        state,
-        status: status_str,
-        image,
-        created,
-        uptime,
-        ports,
-        cpu_percent,
-        memory_usage,
-        memory_limit,
-        restart_count,
-        minecraft,
-        rcon_password,
-    })
+    let mut filters = HashMap::new();
+    filters.insert("dangling", vec!["true"]);
+    match state
+        .docker
+        .prune_images(Some(PruneImagesOptions { filters }))
+        .await
+    {
+        Ok(result) => {
+            let deleted = result.images_deleted.map(|d| d.len()).unwrap_or(0);
+            let reclaimed = result.space_reclaimed.unwrap_or(0);
+            tracing::info!("Pruned {} images, reclaimed {} bytes", deleted, reclaimed);
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({
+                    "images_deleted": deleted,
+                    "space_reclaimed": reclaimed,
+                })),
+            )
+        }
+        Err(e) => {
+            tracing::error!("Failed to prune images: {}", e);
+            docker_error(&e, "Failed to prune images", None)
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// System disk usage
+// ---------------------------------------------------------------------------
+
+/// Summarize `docker system df` data. Sizes Docker has not computed are
+/// reported as -1 and count as zero here.
+fn summarize_disk_usage(df: bollard::models::SystemDataUsageResponse) -> DiskUsage {
+    let mut images = DiskUsageEntry::default();
+    for image in df.images.unwrap_or_default() {
+        images.total += 1;
+        if image.containers > 0 {
+            images.active += 1;
+        } else {
+            images.reclaimable += image.size.max(0);
+        }
+    }
+    // Layers are shared between images, so the real total is the layer size
+    images.size = df.layers_size.unwrap_or(0);
+
+    let mut containers = DiskUsageEntry::default();
+    for container in df.containers.unwrap_or_default() {
+        let size = container.size_rw.unwrap_or(0).max(0);
+        containers.total += 1;
+        containers.size += size;
+        if container.state.as_deref() == Some("running") {
+            containers.active += 1;
+        } else {
+            containers.reclaimable += size;
+        }
+    }
+
+    let mut volumes = DiskUsageEntry::default();
+    for volume in df.volumes.unwrap_or_default() {
+        let (size, refs) = volume
+            .usage_data
+            .map(|u| (u.size.max(0), u.ref_count))
+            .unwrap_or((0, 0));
+        volumes.total += 1;
+        volumes.size += size;
+        if refs > 0 {
+            volumes.active += 1;
+        } else {
+            volumes.reclaimable += size;
+        }
+    }
+
+    let mut build_cache = DiskUsageEntry::default();
+    for cache in df.build_cache.unwrap_or_default() {
+        let size = cache.size.unwrap_or(0).max(0);
+        build_cache.total += 1;
+        build_cache.size += size;
+        if cache.in_use.unwrap_or(false) {
+            build_cache.active += 1;
+        } else if !cache.shared.unwrap_or(false) {
+            build_cache.reclaimable += size;
+        }
+    }
+
+    DiskUsage {
+        images,
+        containers,
+        volumes,
+        build_cache,
+    }
 }
 
-async fn get_container_stats(docker: &Docker, name: &str) -> (f64, u64, u64) {
-    use futures_util::StreamExt;
+async fn system_df_handle(
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!("Disk usage request from {}", addr);
 
-    let stats_result = docker
-        .stats(
-            name,
-            Some(StatsOptions {
-                stream: false,
-                one_shot: true,
-            }),
-        )
-        .next()
-        .await;
+    match state.docker.df().await {
+        Ok(df) => (
+            StatusCode::OK,
+            Json(serde_json::json!(summarize_disk_usage(df))),
+        ),
+        Err(e) => {
+            tracing::error!("Failed to get disk usage: {}", e);
+            docker_error(&e, "Failed to get disk usage", None)
+        }
+    }
+}
 
-    match stats_result {
-        Some(Ok(stats)) => {
-            // CPU calculation
-            let cpu_delta = stats.cpu_stats.cpu_usage.total_usage as f64
-                - stats.precpu_stats.cpu_usage.total_usage as f64;
-            let system_delta = stats.cpu_stats.system_cpu_usage.unwrap_or(0) as f64
-                - stats.precpu_stats.system_cpu_usage.unwrap_or(0) as f64;
-            let num_cpus = stats
-                .cpu_stats
-                .cpu_usage
-                .percpu_usage
-                .as_ref()
-                .map(|v| v.len())
-                .unwrap_or(1) as f64;
+// ---------------------------------------------------------------------------
+// Allowlist administration — change the allowlist without a restart
+// ---------------------------------------------------------------------------
 
-            let cpu_percent = if system_delta > 0.0 && cpu_delta > 0.0 {
-                (cpu_delta / system_delta) * num_cpus * 100.0
-            } else {
-                0.0
-            };
+/// Add `add` to and remove `remove` from `list`, keeping its order.
+fn apply_allowlist_change(list: &mut Vec<String>, add: &[String], remove: &[String]) {
+    list.retain(|name| !remove.contains(name));
+    for name in add {
+        if !list.contains(name) {
+            list.push(name.clone());
+        }
+    }
+}
 
-            let memory_usage = stats.memory_stats.usage.unwrap_or(0);
-            let memory_limit = stats.memory_stats.limit.unwrap_or(0);
+/// Apply a change to the allowlist's source so it survives restarts. Only
+/// the change is applied; entries in the source that are not in effect
+/// (such as containers that do not exist yet) are kept.
+fn persist_allowlist(
+    source: &AllowlistSource,
+    deny_mode: bool,
+    change: &AllowlistChange,
+) -> Result<()> {
+    // In deny-list mode, allowing a container means removing it from the list
+    let (add, remove) = if deny_mode {
+        (&change.remove, &change.add)
+    } else {
+        (&change.add, &change.remove)
+    };
 
-            (cpu_percent, memory_usage, memory_limit)
+    match source {
+        AllowlistSource::CommandLine => {
+            anyhow::bail!("The allowlist was not read from a file it can be written back to")
+        }
+        AllowlistSource::File(path) => {
+            let mut list: Vec<String> = load_file_containers(path)
+                .into_iter()
+                .filter(|line| !line.trim().is_empty())
+                .collect();
+            apply_allowlist_change(&mut list, add, remove);
+            let content: String = list.iter().map(|name| format!("{}\n", name)).collect();
+            std::fs::write(path, content)
+                .map_err(|e| anyhow::anyhow!("Failed to write '{}': {}", path, e))
+        }
+        AllowlistSource::Config(path) => {
+            let content = read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("Failed to read config file '{}': {}", path, e))?;
+            let mut table: toml::Table = content
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Failed to parse config file '{}': {}", path, e))?;
+            let key = if deny_mode { "denied" } else { "allowed" };
+            let mut list: Vec<String> = match table.remove(key) {
+                Some(value) => value
+                    .try_into()
+                    .map_err(|e| anyhow::anyhow!("Invalid '{}' in '{}': {}", key, path, e))?,
+                None => Vec::new(),
+            };
+            apply_allowlist_change(&mut list, add, remove);
+            table.insert(key.to_string(), toml::Value::try_from(list)?);
+            std::fs::write(path, toml::to_string(&table)?)
+                .map_err(|e| anyhow::anyhow!("Failed to write config file '{}': {}", path, e))
         }
-        _ => (0.0, 0, 0),
     }
 }
 
-// ---------------------------------------------------------------------------
-// Background refresh task
-// ---------------------------------------------------------------------------
+async fn allowlist_handle(
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    body: axum::body::Bytes,
+) -> impl IntoResponse {
+    tracing::info!("Allowlist change request from {}", addr);
 
-async fn background_refresh(state: SharedState) {
-    let allowed_set: HashSet<&str> = state.allowed_containers.iter().map(|s| s.as_str()).collect();
-    let mut mc_tick: u32 = 0; // counts 5s ticks; ping MC every 6 ticks (30s)
+    let change: AllowlistChange = match serde_json::from_slice(&body) {
+        Ok(change) => change,
+        Err(e) => {
+            return json_error(
+                StatusCode::BAD_REQUEST,
+                &format!("Invalid allowlist change: {}", e),
+            )
+        }
+    };
 
-    loop {
-        let docker = &state.docker;
+    let policy = current_policy(&state);
+    let deny_mode = policy.denied_containers.is_some();
 
+    // Persist first so a failed write leaves the running allowlist untouched
+    if change.persist {
+        if let Err(e) = persist_allowlist(&policy.allowlist_source, deny_mode, &change) {
+            tracing::error!("Failed to persist allowlist: {}", e);
+            return json_error(StatusCode::CONFLICT, &e.to_string());
+        }
+    }
+
+    let summaries = if policy.allow_label.is_some() || deny_mode {
         let options = ListContainersOptions {
             all: true,
             filters: state.common_filters.clone(),
             limit: Some(200),
             size: false,
         };
+        state
+            .docker
+            .list_containers(Some(options))
+            .await
+            .unwrap_or_default()
+    } else {
+        Vec::new()
+    };
 
-        let containers = match docker.list_containers(Some(options)).await {
-            Ok(list) => list,
-            Err(e) => {
-                tracing::error!("Failed to list containers: {}", e);
-                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-                continue;
+    let allowed = {
+        let mut guard = state.policy.write().expect("Policy lock poisoned");
+        let mut next = Policy::clone(&guard);
+        match &mut next.denied_containers {
+            Some(denied) => apply_allowlist_change(denied, &change.remove, &change.add),
+            None => {
+                apply_allowlist_change(&mut next.listed_containers, &change.add, &change.remove)
             }
+        }
+        next.allowed_containers = effective_allowlist(
+            &next.listed_containers,
+            next.allow_label.as_deref(),
+            next.denied_containers.as_deref(),
+            &summaries,
+        );
+        let allowed = next.allowed_containers.clone();
+        *guard = Arc::new(next);
+        allowed
+    };
+    tracing::info!("Allowed containers: {:?}", allowed);
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({ "allowed": allowed, "persisted": change.persist })),
+    )
+}
+
+async fn validate_allowlist_handle(
+    State(state): State<SharedState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> impl IntoResponse {
+    tracing::info!("Allowlist validation request from {}", addr);
+
+    let options = ListContainersOptions {
+        all: true,
+        filters: state.common_filters.clone(),
+        limit: None,
+        size: false,
+    };
+    let summaries = match state.docker.list_containers(Some(options)).await {
+        Ok(summaries) => summaries,
+        Err(e) => {
+            tracing::error!("Failed to list containers: {}", e);
+            return docker_error(&e, "Failed to list containers", None);
+        }
+    };
+
+    let policy = current_policy(&state);
+    let (mode, entries) = match &policy.denied_containers {
+        Some(denied) => ("deny", denied),
+        None => ("allow", &policy.listed_containers),
+    };
+    let unknown = unknown_entries(entries, &summaries);
+    for name in &unknown {
+        tracing::warn!("Allowlist entry '{}' matches no existing container", name);
+    }
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "mode": mode,
+            "entries": entries.len(),
+            "unknown": unknown,
+        })),
+    )
+}
+
+// ---------------------------------------------------------------------------
+// Initialization helpers
+// ---------------------------------------------------------------------------
+
+/// Read the allowlist from `-c`, `-f`, the config file or containers.txt.
+/// Entries are kept even if no such container exists yet, so containers
+/// created later can be managed without a reload; `unknown_entries` reports them.
+fn resolve_allowed_containers(args: &Args, config: &FileConfig) -> (Vec<String>, AllowlistSource) {
+    let containers_from_cli = args
+        .containers
+        .as_ref()
+        .and_then(|json| serde_json::from_str::<Vec<String>>(json).ok());
+
+    if let Some(containers) = containers_from_cli {
+        return (containers, AllowlistSource::CommandLine);
+    }
+
+    // An explicit -f wins over the config file's allowlist
+    if let (None, Some(allowed)) = (&args.file, &config.allowed) {
+        let source = match &args.config {
+            Some(path) => AllowlistSource::Config(path.clone()),
+            None => AllowlistSource::CommandLine,
         };
+        return (allowed.clone(), source);
+    }
 
-        let names: Vec<String> = containers
+    let filename = args.file.as_deref().unwrap_or("containers.txt");
+    let allowed = load_file_containers(filename)
+        .into_iter()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect();
+    (allowed, AllowlistSource::File(filename.to_string()))
+}
+
+/// Allowlist (or deny list) entries that match no existing container,
+/// stopped ones included.
+fn unknown_entries(
+    entries: &[String],
+    containers: &[bollard::models::ContainerSummary],
+) -> Vec<String> {
+    let existing: HashSet<&str> = containers
+        .iter()
+        .filter_map(|c| c.names.as_ref()?.first().map(|n| n.trim_start_matches('/')))
+        .collect();
+    entries
+        .iter()
+        .filter(|name| !existing.contains(name.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Names of the containers carrying `label=true`.
+fn labelled_containers(
+    containers: &[bollard::models::ContainerSummary],
+    label: &str,
+) -> Vec<String> {
+    let mut names: Vec<String> = containers
+        .iter()
+        .filter(|c| {
+            c.labels
+                .as_ref()
+                .and_then(|labels| labels.get(label))
+                .is_some_and(|value| value.eq_ignore_ascii_case("true"))
+        })
+        .filter_map(|c| {
+            c.names
+                .as_ref()?
+                .first()
+                .map(|n| n.trim_start_matches('/').to_string())
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// The allowlist given the current containers: in deny-list mode every
+/// container that is not denied, otherwise the listed containers followed by
+/// labelled ones not already listed.
+fn effective_allowlist(
+    listed: &[String],
+    allow_label: Option<&str>,
+    denied: Option<&[String]>,
+    containers: &[bollard::models::ContainerSummary],
+) -> Vec<String> {
+    if let Some(denied) = denied {
+        let mut names: Vec<String> = containers
             .iter()
             .filter_map(|c| {
-                c.names.as_ref().and_then(|names| {
-                    names
-                        .first()
-                        .map(|n| n.trim_start_matches('/').to_string())
-                })
+                c.names
+                    .as_ref()?
+                    .first()
+                    .map(|n| n.trim_start_matches('/').to_string())
             })
-            .filter(|n| allowed_set.contains(n.as_str()))
+            .filter(|n| !denied.contains(n))
             .collect();
+        names.sort();
+        return names;
+    }
 
-        let futures: Vec<_> = names
-            .iter()
-            .map(|name| collect_container_info(docker, name))
-            .collect();
-        let results = futures_util::future::join_all(futures).await;
+    let mut allowed = listed.to_vec();
+    if let Some(label) = allow_label {
+        for name in labelled_containers(containers, label) {
+            if !allowed.contains(&name) {
+                allowed.push(name);
+            }
+        }
+    }
+    allowed
+}
 
-        let mut all_infos: Vec<ContainerInfo> = results.into_iter().flatten().collect();
+/// Build the policy from the command line, the config file and the JSON
+/// files it points at. Used at startup and on every reload.
+async fn load_policy(args: &Args, config: &FileConfig, docker: &Docker) -> Result<Policy> {
+    let denied: Option<Vec<String>> = match &args.deny {
+        Some(json) => Some(
+            serde_json::from_str(json)
+                .map_err(|e| anyhow::anyhow!("Failed to parse --deny: {}", e))?,
+        ),
+        None => config.denied.clone(),
+    };
+    // Deny-list mode replaces the allowlist entirely
+    let (listed, allowlist_source) = match &denied {
+        Some(denied) => {
+            tracing::info!("Deny-list mode, denied containers: {:?}", denied);
+            let source = match (&args.deny, &args.config) {
+                (None, Some(path)) => AllowlistSource::Config(path.clone()),
+                _ => AllowlistSource::CommandLine,
+            };
+            (Vec::new(), source)
+        }
+        None => resolve_allowed_containers(args, config),
+    };
+    let allow_label = args
+        .allow_label
+        .clone()
+        .or_else(|| config.allow_label.clone());
 
-        let found_names: HashSet<String> = all_infos.iter().map(|i| i.name.clone()).collect();
-        for name in &state.allowed_containers {
-            if !found_names.contains(name) {
-                if let Some(info) = collect_container_info(docker, name).await {
-                    all_infos.push(info);
-                }
+    let options = ListContainersOptions {
+        all: true,
+        filters: common_filters(),
+        limit: None,
+        size: false,
+    };
+    let summaries = match docker.list_containers(Some(options)).await {
+        Ok(summaries) => {
+            let entries = denied.as_deref().unwrap_or(&listed);
+            for name in unknown_entries(entries, &summaries) {
+                tracing::warn!("Allowlist entry '{}' matches no existing container", name);
             }
+            summaries
         }
+        Err(e) => {
+            tracing::warn!("Failed to list containers, allowlist not validated: {}", e);
+            Vec::new()
+        }
+    };
+    let allowed = effective_allowlist(
+        &listed,
+        allow_label.as_deref(),
+        denied.as_deref(),
+        &summaries,
+    );
+    tracing::info!("Allowed containers: {:?}", allowed);
 
-        all_infos.sort_by(|a, b| a.name.cmp(&b.name));
+    let exec_commands = match &args.exec_file {
+        Some(path) => load_exec_commands(path)?,
+        None => split_exec_commands(per_container(config, |c| &c.commands)),
+    };
+    let download_paths = match &args.download_file {
+        Some(path) => load_download_paths(path)?,
+        None => {
+            let paths = per_container(config, |c| &c.downloads);
+            check_download_paths(&paths)?;
+            paths
+        }
+    };
 
-        // MC ping on a 30s cycle (every 6th tick), or on first tick
-        #[allow(clippy::manual_is_multiple_of)]
-        let do_mc_ping = mc_tick % 6 == 0;
-        if do_mc_ping {
-            let mc_cache = state.mc_cache.read().await;
-            // Determine which containers need a ping:
-            // - must be running
-            // - must be MC (has minecraft field)
-            // - either first ping (not in cache) or has players connected
-            // (container_name, mc_port, seed, rcon_port, rcon_password)
-            let to_ping: Vec<(String, u16, String, Option<u16>, String)> = all_infos
+    let dependencies = match &args.depends_file {
+        Some(path) => load_dependencies(path)?,
+        None => {
+            let dependencies = per_container(config, |c| &c.depends_on);
+            check_dependency_cycles(&dependencies)?;
+            dependencies
+        }
+    };
+
+    let stacks = match &args.stacks_file {
+        Some(path) => load_stacks(path)?,
+        None => {
+            check_stack_names(&config.stacks)?;
+            config.stacks.clone()
+        }
+    };
+
+    let exclusive_groups = match &args.exclusive_file {
+        Some(path) => load_exclusive_groups(path)?,
+        None => config.exclusive.clone(),
+    };
+
+    let profiles = match &args.profiles_file {
+        Some(path) => load_profiles(path)?,
+        None => config.profiles.clone(),
+    };
+    for (profile, members) in &profiles {
+        for name in members.iter().filter(|m| !allowed.contains(m)) {
+            tracing::warn!(
+                "Profile '{}' member '{}' is not an allowed container",
+                profile,
+                name
+            );
+        }
+    }
+
+    let groups = match &args.groups_file {
+        Some(path) => load_groups(path)?,
+        None => config.groups.clone(),
+    };
+    for (group, members) in &groups {
+        for name in members.iter().filter(|m| !allowed.contains(m)) {
+            tracing::warn!(
+                "Group '{}' member '{}' is not an allowed container",
+                group,
+                name
+            );
+        }
+    }
+
+    let allowed_services = list_option(args.services.as_deref(), "services", &config.services)?;
+    if !allowed_services.is_empty() {
+        tracing::info!("Allowed services: {:?}", allowed_services);
+    }
+
+    let managed_networks = list_option(args.networks.as_deref(), "networks", &config.networks)?;
+
+    let display = match &args.display_file {
+        Some(path) => load_display(path)?,
+        None => config
+            .containers
+            .iter()
+            .map(|(name, c)| {
+                let display = ContainerDisplay {
+                    display_name: c.display_name.clone(),
+                    description: c.description.clone(),
+                    category: c.category.clone(),
+                    icon: c.icon.clone(),
+                    link_scheme: c.link_scheme.clone(),
+                    link_host: c.link_host.clone(),
+                };
+                (name.clone(), display)
+            })
+            .collect(),
+    };
+    check_display(&display)?;
+
+    let permitted_actions = match &args.actions_file {
+        Some(path) => load_permitted_actions(path)?,
+        None => {
+            let actions = config
+                .containers
                 .iter()
-                .filter(|c| c.state == "running" && c.minecraft.is_some())
-                .filter_map(|c| {
-                    find_minecraft_host_port(&c.ports).map(|port| {
-                        let seed = c
-                            .minecraft
-                            .as_ref()
-                            .map(|m| m.seed.clone())
-                            .unwrap_or_default();
-                        let rcon_port = find_rcon_host_port(&c.ports);
-                        (c.name.clone(), port, seed, rcon_port, c.rcon_password.clone())
-                    })
-                })
-                .filter(|(name, _, _, _, _)| {
-                    match mc_cache.get(name.as_str()) {
-                        None => true,
-                        Some(cached) => cached.player_count > 0,
-                    }
+                .filter_map(|(name, c)| Some((name.clone(), c.actions.clone()?)))
+                .collect();
+            check_permitted_actions(&actions)?;
+            actions
+        }
+    };
+
+    Ok(Policy {
+        allowed_containers: allowed,
+        exec_commands,
+        download_paths,
+        allowed_services,
+        dependencies,
+        stacks,
+        exclusive_groups,
+        profiles,
+        groups,
+        managed_networks,
+        listed_containers: listed,
+        allow_label,
+        denied_containers: denied,
+        allowlist_source,
+        permitted_actions,
+        display,
+    })
+}
+
+fn read_json_file<T: serde::de::DeserializeOwned>(filename: &str, what: &str) -> Result<T> {
+    let content = read_to_string(filename)
+        .map_err(|e| anyhow::anyhow!("Failed to read {} file '{}': {}", what, filename, e))?;
+    serde_json::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("Failed to parse {} file '{}': {}", what, filename, e))
+}
+
+/// Read a compose file, in YAML like `docker compose` takes it or in JSON,
+/// which YAML includes.
+fn read_compose_file(filename: &str) -> Result<ComposeFile> {
+    let content = read_to_string(filename)
+        .map_err(|e| anyhow::anyhow!("Failed to read compose file '{}': {}", filename, e))?;
+    serde_yaml::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("Failed to parse compose file '{}': {}", filename, e))
+}
+
+/// Split predefined exec commands into argv. Commands are split on
+/// whitespace and run without a shell.
+fn split_exec_commands(
+    parsed: HashMap<String, HashMap<String, String>>,
+) -> HashMap<String, HashMap<String, Vec<String>>> {
+    parsed
+        .into_iter()
+        .map(|(container, commands)| {
+            let commands = commands
+                .into_iter()
+                .filter_map(|(name, line)| {
+                    let argv: Vec<String> = line.split_whitespace().map(String::from).collect();
+                    (!argv.is_empty()).then_some((name, argv))
                 })
                 .collect();
-            drop(mc_cache);
+            (container, commands)
+        })
+        .collect()
+}
 
-            for (name, port, seed, rcon_port, rcon_pass) in &to_ping {
-                if let Some(mut mc_info) = ping_minecraft(*port, seed).await {
-                    // Fetch player positions via RCON and log them
-                    if let Some(rp) = rcon_port {
-                        if !rcon_pass.is_empty() && !mc_info.players.is_empty() {
-                            fetch_player_positions(*rp, rcon_pass, &mut mc_info.players).await;
-                            for p in &mc_info.players {
-                                if let (Some(x), Some(y), Some(z)) = (p.x, p.y, p.z) {
-                                    tracing::info!(
-                                        "[{}] Player '{}' at x={:.1} y={:.1} z={:.1}",
-                                        name, p.name, x, y, z
-                                    );
-                                }
-                            }
-                        }
-                    }
-                    let mut mc_cache = state.mc_cache.write().await;
-                    mc_cache.insert(name.clone(), mc_info);
-                }
+/// Load predefined exec commands: `{ "<container>": { "<command>": "<argv>" } }`.
+fn load_exec_commands(filename: &str) -> Result<HashMap<String, HashMap<String, Vec<String>>>> {
+    let parsed: HashMap<String, HashMap<String, String>> = read_json_file(filename, "exec")?;
+    Ok(split_exec_commands(parsed))
+}
+
+fn check_download_paths(paths: &HashMap<String, HashMap<String, String>>) -> Result<()> {
+    for (container, paths) in paths {
+        for (name, path) in paths {
+            if !path.starts_with('/') {
+                anyhow::bail!(
+                    "Download path '{}' for '{}' must be absolute, got '{}'",
+                    name,
+                    container,
+                    path
+                );
             }
         }
-        mc_tick = mc_tick.wrapping_add(1);
+    }
+    Ok(())
+}
 
-        // Merge cached MC data into container infos
+/// Load downloadable paths: `{ "<container>": { "<name>": "<absolute path>" } }`.
+fn load_download_paths(filename: &str) -> Result<HashMap<String, HashMap<String, String>>> {
+    let parsed: HashMap<String, HashMap<String, String>> = read_json_file(filename, "download")?;
+    check_download_paths(&parsed)?;
+    Ok(parsed)
+}
+
+/// Load container dependencies: `{ "<container>": ["<dependency>", ...] }`.
+fn load_dependencies(filename: &str) -> Result<HashMap<String, Vec<String>>> {
+    let parsed: HashMap<String, Vec<String>> = read_json_file(filename, "dependencies")?;
+    check_dependency_cycles(&parsed)?;
+    Ok(parsed)
+}
+
+fn check_permitted_actions(actions: &HashMap<String, Vec<String>>) -> Result<()> {
+    for (name, actions) in actions {
+        if let Some(action) = actions
+            .iter()
+            .find(|a| !CONTAINER_ACTIONS.contains(&a.as_str()))
         {
-            let mc_cache = state.mc_cache.read().await;
-            for info in &mut all_infos {
-                if info.minecraft.is_some() {
-                    if let Some(cached) = mc_cache.get(&info.name) {
-                        // Preserve seed from inspect, overlay ping data
-                        let seed = info
-                            .minecraft
-                            .as_ref()
-                            .map(|m| m.seed.clone())
-                            .unwrap_or_default();
-                        info.minecraft = Some(MinecraftInfo {
-                            seed,
-                            ..cached.clone()
-                        });
-                    }
-                }
-            }
+            anyhow::bail!(
+                "Unknown action '{}' for container '{}'; expected one of {:?}",
+                action,
+                name,
+                CONTAINER_ACTIONS
+            );
         }
+    }
+    Ok(())
+}
+
+/// Load permitted actions: `{ "<container>": ["start", "logs", ...] }`.
+fn load_permitted_actions(filename: &str) -> Result<HashMap<String, Vec<String>>> {
+    let parsed: HashMap<String, Vec<String>> = read_json_file(filename, "actions")?;
+    check_permitted_actions(&parsed)?;
+    Ok(parsed)
+}
+
+fn check_stack_names(stacks: &HashMap<String, String>) -> Result<()> {
+    if let Some(name) = stacks.keys().find(|name| !is_valid_project_name(name)) {
+        anyhow::bail!(
+            "Stack name '{}' may only contain lowercase letters, digits, '-' and '_'",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Load stacks: `{ "<stack>": "<path to compose file>" }`.
+fn load_stacks(filename: &str) -> Result<HashMap<String, String>> {
+    let parsed: HashMap<String, String> = read_json_file(filename, "stacks")?;
+    check_stack_names(&parsed)?;
+    Ok(parsed)
+}
+
+/// Load exclusive groups:
+/// `{ "<group>": { "containers": ["<container>", ...], "on_conflict": "reject" | "stop" } }`.
+fn load_exclusive_groups(filename: &str) -> Result<HashMap<String, ExclusiveGroup>> {
+    read_json_file(filename, "exclusive")
+}
+
+/// Load profiles: `{ "<profile>": ["<container>", ...] }`.
+fn load_profiles(filename: &str) -> Result<HashMap<String, Vec<String>>> {
+    read_json_file(filename, "profiles")
+}
 
+/// Load named groups: `{ "<group>": ["<container>", ...] }`.
+fn load_groups(filename: &str) -> Result<HashMap<String, Vec<String>>> {
+    read_json_file(filename, "groups")
+}
+
+/// Load display settings:
+/// `{ "<container>": { "display_name": "...", "category": "...", "icon": "<url>", ... } }`.
+fn load_display(filename: &str) -> Result<HashMap<String, ContainerDisplay>> {
+    read_json_file(filename, "display")
+}
+
+fn check_display(display: &HashMap<String, ContainerDisplay>) -> Result<()> {
+    for (name, d) in display {
+        if let Some(scheme) = d
+            .link_scheme
+            .as_deref()
+            .filter(|s| !matches!(*s, "http" | "https"))
         {
-            let mut cache = state.container_cache.write().await;
-            *cache = all_infos;
+            anyhow::bail!(
+                "Invalid link scheme '{}' for container '{}'; expected http or https",
+                scheme,
+                name
+            );
         }
-        state
-            .cache_generation
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        if let Some(host) = d
+            .link_host
+            .as_deref()
+            .filter(|h| h.is_empty() || h.contains('/'))
+        {
+            anyhow::bail!("Invalid link host '{}' for container '{}'", host, name);
+        }
+    }
+    Ok(())
+}
 
-        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+fn load_file_containers(filename: &str) -> Vec<String> {
+    if std::fs::metadata(filename).is_ok() {
+        match read_to_string(filename) {
+            Ok(content) => content.lines().map(|l| l.to_string()).collect(),
+            Err(e) => {
+                tracing::warn!("Failed to read {}: {}", filename, e);
+                Vec::new()
+            }
+        }
+    } else {
+        tracing::warn!("File '{}' does not exist", filename);
+        Vec::new()
     }
 }
 
 // ---------------------------------------------------------------------------
-// Route handlers
+// Configuration file
 // ---------------------------------------------------------------------------
 
-async fn render_containers_html(State(state): State<SharedState>) -> Html<String> {
-    let cache = state.container_cache.read().await;
-    let containers: Vec<Container> = cache
-        .iter()
-        .map(|c| Container {
-            name: c.name.clone(),
-            status: c.status.clone(),
-            state: c.state.clone(),
-        })
-        .collect();
-    let template = ContainersTemplate {
-        containers,
-        port: state.port,
-    };
-    Html(template.render().unwrap_or_default())
+/// Settings read from the `--config` TOML file. Everything is optional, and
+/// command line flags take precedence over values from the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    /// Allowed containers; replaces containers.txt
+    allowed: Option<Vec<String>>,
+    /// Also allow containers with this label set to `true`
+    allow_label: Option<String>,
+    /// Allow every container except these; replaces the allowlist
+    denied: Option<Vec<String>>,
+    /// Swarm services that may be listed and scaled
+    services: Vec<String>,
+    /// Networks containers may be connected to or disconnected from
+    networks: Vec<String>,
+    server: ServerConfig,
+    auth: AuthConfig,
+    resources: ResourcesConfig,
+    /// Per-container options, keyed by container name
+    containers: HashMap<String, ContainerConfig>,
+    groups: HashMap<String, Vec<String>>,
+    profiles: HashMap<String, Vec<String>>,
+    exclusive: HashMap<String, ExclusiveGroup>,
+    stacks: HashMap<String, String>,
 }
 
-async fn get_container_statuses(
-    State(state): State<SharedState>,
-    headers: axum::http::HeaderMap,
-) -> impl IntoResponse {
-    let gen = state
-        .cache_generation
-        .load(std::sync::atomic::Ordering::Relaxed);
-    let etag = format!("\"{}\"", gen);
+/// A file in the `--config-dir` directory. Fragments may only add
+/// containers and definitions; server settings stay in the main config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFragment {
+    allowed: Vec<String>,
+    services: Vec<String>,
+    networks: Vec<String>,
+    containers: HashMap<String, ContainerConfig>,
+    groups: HashMap<String, Vec<String>>,
+    profiles: HashMap<String, Vec<String>>,
+    exclusive: HashMap<String, ExclusiveGroup>,
+    stacks: HashMap<String, String>,
+}
 
-    // If client already has this generation, skip the body
-    if let Some(prev) = headers.get(axum::http::header::IF_NONE_MATCH) {
-        if prev.as_bytes() == etag.as_bytes() {
-            return (
-                StatusCode::NOT_MODIFIED,
-                [(axum::http::header::ETAG, etag)],
-                String::new(),
-            );
-        }
-    }
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ServerConfig {
+    port: Option<u64>,
+    bind: Option<String>,
+    docker_host: Option<String>,
+    log_level: Option<String>,
+    restart_timeout: Option<isize>,
+    update_check_interval: Option<u64>,
+    dependency_timeout: Option<u64>,
+    show_stopped: Option<bool>,
+    /// Reject every change
+    read_only: Option<bool>,
+    /// Addresses or CIDR networks clients may connect from
+    allowed_clients: Vec<String>,
+    /// Addresses or CIDR networks of reverse proxies in front of docker-direct
+    trusted_proxies: Vec<String>,
+    /// Sites whose scripts may call the API, e.g. `https://grafana.example.com`
+    cors_origins: Vec<String>,
+    /// Methods those sites may use [default: GET, POST, PUT]
+    cors_methods: Vec<String>,
+    /// Request headers those sites may send
+    /// [default: authorization, content-type, x-api-key, idempotency-key]
+    cors_headers: Vec<String>,
+    /// Action requests per minute per client, 0 disables [default: 60]
+    rate_limit: Option<u64>,
+    /// Seconds before a request is given up, 0 disables [default: 300]
+    request_timeout: Option<u64>,
+    /// Requests handled at the same time, 0 disables [default: 64]
+    max_concurrent_requests: Option<usize>,
+    /// Failed sign-ins in a row before a lockout, 0 disables [default: 5]
+    login_attempts: Option<u32>,
+    /// Name shown in the web UI [default: Docker Direct]
+    title: Option<String>,
+    /// Hex color for names and highlights in the web UI, e.g. `#ff8800`
+    accent_color: Option<String>,
+    /// Web UI theme for browsers that haven't picked one [default: auto]
+    theme: Option<String>,
+    /// Web UI language for browsers asking for none there are messages for [default: en]
+    locale: Option<String>,
+    /// Directory of `<locale>.json` message catalogs for the web UI
+    locale_dir: Option<String>,
+    /// JSON Lines file recording every change
+    audit_log: Option<String>,
+    /// Key to sign action links with, so they survive restarts
+    link_secret: Option<String>,
+    /// PEM certificate chain; serve HTTPS when given along with `tls_key`
+    tls_cert: Option<String>,
+    /// PEM private key for `tls_cert`
+    tls_key: Option<String>,
+    /// Port on which plain HTTP requests are redirected to HTTPS
+    http_redirect_port: Option<u64>,
+    /// PEM CA certificates; clients must present a certificate signed by one
+    tls_client_ca: Option<String>,
+}
 
-    let cache = state.container_cache.read().await;
-    let body = serde_json::to_string(&*cache).unwrap_or_else(|_| "[]".to_string());
-    (StatusCode::OK, [(axum::http::header::ETAG, etag)], body)
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AuthConfig {
+    admin_token: Option<String>,
+    /// user name -> password or bcrypt hash, for HTTP Basic authentication
+    users: HashMap<String, String>,
+    /// htpasswd file with more users
+    htpasswd: Option<String>,
+    /// key name -> key, for scripts sending `X-Api-Key` or `Authorization: Bearer`
+    api_keys: HashMap<String, String>,
+    oidc: Option<OidcConfig>,
+    /// How long a browser stays signed in [default: 12]
+    session_hours: Option<u64>,
+    /// user or API key name -> viewer, operator or admin
+    roles: HashMap<String, Role>,
+    /// Role of users and API keys not listed in `roles` [default: operator]
+    default_role: Option<Role>,
+    /// user or API key name -> the only containers and groups it may access
+    permissions: HashMap<String, ContainerGrant>,
+    /// Trust user and group headers set by a forward-auth proxy
+    forward_auth: Option<ForwardAuthConfig>,
+    /// Check passwords against an LDAP directory
+    ldap: Option<LdapConfig>,
+    /// group name -> viewer, operator or admin
+    group_roles: HashMap<String, Role>,
 }
 
-async fn start_container_handle(
-    State(state): State<SharedState>,
-    Query(query): Query<ContainerName>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
-) -> impl IntoResponse {
-    tracing::info!("Start request for '{}' from {}", query.name, addr);
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ResourcesConfig {
+    min_cpu_shares: Option<isize>,
+    max_cpu_shares: Option<isize>,
+    min_memory_mb: Option<i64>,
+    max_memory_mb: Option<i64>,
+}
 
-    if !state.allowed_containers.contains(&query.name) {
-        tracing::warn!("Container '{}' not allowed", query.name);
-        return json_error(StatusCode::FORBIDDEN, "Container not allowed");
-    }
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ContainerConfig {
+    /// Named commands that may be executed, like the exec file
+    commands: HashMap<String, String>,
+    /// Named paths that may be downloaded, like the download file
+    downloads: HashMap<String, String>,
+    /// Containers to start before this one
+    depends_on: Vec<String>,
+    /// Actions permitted on this container; all when omitted
+    actions: Option<Vec<String>>,
+    /// Name shown in the dashboard instead of the container name
+    display_name: Option<String>,
+    description: Option<String>,
+    category: Option<String>,
+    /// URL of an icon image
+    icon: Option<String>,
+    /// `http` or `https` for links to published ports
+    link_scheme: Option<String>,
+    /// Host for links to published ports
+    link_host: Option<String>,
+}
 
-    match state
-        .docker
-        .start_container(&query.name, None::<StartContainerOptions<String>>)
-        .await
-    {
-        Ok(_) => (
-            StatusCode::OK,
-            Json(serde_json::json!({ "status": "started" })),
-        ),
-        Err(e) => {
-            tracing::error!("Failed to start '{}': {}", query.name, e);
-            json_error(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                &format!("Failed to start container: {}", e),
-            )
+fn load_config(filename: &str) -> Result<FileConfig> {
+    let content = read_to_string(filename)
+        .map_err(|e| anyhow::anyhow!("Failed to read config file '{}': {}", filename, e))?;
+    toml::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("Failed to parse config file '{}': {}", filename, e))
+}
+
+/// Read `--config` and merge the fragments from `--config-dir` into it.
+fn load_configuration(args: &Args) -> Result<FileConfig> {
+    let mut config = match &args.config {
+        Some(path) => load_config(path)?,
+        None => FileConfig::default(),
+    };
+    if let Some(dir) = &args.config_dir {
+        for (path, fragment) in load_config_fragments(dir)? {
+            merge_fragment(&mut config, fragment, &path)?;
         }
     }
+    Ok(config)
 }
 
-async fn stop_container_handle(
-    State(state): State<SharedState>,
-    Query(query): Query<ContainerName>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
-) -> impl IntoResponse {
-    tracing::info!("Stop request for '{}' from {}", query.name, addr);
+/// Read the fragments in `dir` in file name order: `*.toml` files in the
+/// config file format and `*.txt` files with one allowed container per line.
+/// Other files are ignored.
+fn load_config_fragments(dir: &str) -> Result<Vec<(String, ConfigFragment)>> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| anyhow::anyhow!("Failed to read config directory '{}': {}", dir, e))?;
+    let mut paths: Vec<std::path::PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
 
-    if !state.allowed_containers.contains(&query.name) {
-        tracing::warn!("Container '{}' not allowed", query.name);
-        return json_error(StatusCode::FORBIDDEN, "Container not allowed");
+    let mut fragments = Vec::new();
+    for path in paths {
+        let name = path.display().to_string();
+        let fragment = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => {
+                let content = read_to_string(&path)
+                    .map_err(|e| anyhow::anyhow!("Failed to read '{}': {}", name, e))?;
+                toml::from_str(&content)
+                    .map_err(|e| anyhow::anyhow!("Failed to parse '{}': {}", name, e))?
+            }
+            Some("txt") => ConfigFragment {
+                allowed: load_file_containers(&name)
+                    .into_iter()
+                    .map(|line| line.trim().to_string())
+                    .filter(|line| !line.is_empty())
+                    .collect(),
+                ..Default::default()
+            },
+            _ => continue,
+        };
+        fragments.push((name, fragment));
     }
+    Ok(fragments)
+}
 
-    match state
-        .docker
-        .stop_container(&query.name, None::<StopContainerOptions>)
-        .await
-    {
-        Ok(_) => (
-            StatusCode::OK,
-            Json(serde_json::json!({ "status": "stopped" })),
-        ),
-        Err(e) => {
-            tracing::error!("Failed to stop '{}': {}", query.name, e);
-            json_error(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                &format!("Failed to stop container: {}", e),
-            )
+/// Add a fragment to the configuration. Lists are appended; a container,
+/// group, profile, exclusivity group or stack defined twice is an error.
+fn merge_fragment(config: &mut FileConfig, fragment: ConfigFragment, path: &str) -> Result<()> {
+    fn merge_map<T>(
+        into: &mut HashMap<String, T>,
+        from: HashMap<String, T>,
+        what: &str,
+        path: &str,
+    ) -> Result<()> {
+        for (name, value) in from {
+            if into.contains_key(&name) {
+                anyhow::bail!("{} '{}' in '{}' is already defined", what, name, path);
+            }
+            into.insert(name, value);
         }
+        Ok(())
     }
-}
-
-async fn stop_all_handle(
-    State(state): State<SharedState>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
-) -> impl IntoResponse {
-    tracing::info!("Stop-all request from {}", addr);
 
-    let mut results = Vec::new();
-    for name in &state.allowed_containers {
-        tracing::info!("Stopping '{}'...", name);
-        match state
-            .docker
-            .stop_container(name, None::<StopContainerOptions>)
-            .await
-        {
-            Ok(_) => {
-                tracing::info!("Stopped '{}'", name);
-                results.push(serde_json::json!({ "name": name, "status": "stopped" }));
-            }
-            Err(e) => {
-                tracing::error!("Failed to stop '{}': {}", name, e);
-                results.push(serde_json::json!({ "name": name, "error": e.to_string() }));
+    if !fragment.allowed.is_empty() {
+        let allowed = config.allowed.get_or_insert_with(Vec::new);
+        for name in fragment.allowed {
+            if !allowed.contains(&name) {
+                allowed.push(name);
             }
         }
     }
+    config.services.extend(fragment.services);
+    config.networks.extend(fragment.networks);
+    merge_map(
+        &mut config.containers,
+        fragment.containers,
+        "Container",
+        path,
+    )?;
+    merge_map(&mut config.groups, fragment.groups, "Group", path)?;
+    merge_map(&mut config.profiles, fragment.profiles, "Profile", path)?;
+    merge_map(
+        &mut config.exclusive,
+        fragment.exclusive,
+        "Exclusivity group",
+        path,
+    )?;
+    merge_map(&mut config.stacks, fragment.stacks, "Stack", path)?;
+    Ok(())
+}
+
+/// Collect one per-container option from the config file.
+fn per_container<T: Clone>(
+    config: &FileConfig,
+    option: impl Fn(&ContainerConfig) -> &T,
+) -> HashMap<String, T> {
+    config
+        .containers
+        .iter()
+        .map(|(name, c)| (name.clone(), option(c).clone()))
+        .collect()
+}
 
-    (StatusCode::OK, Json(serde_json::json!({ "results": results })))
+/// Parse a JSON array given on the command line, falling back to the config file.
+fn list_option(json: Option<&str>, flag: &str, fallback: &[String]) -> Result<Vec<String>> {
+    match json {
+        Some(json) => serde_json::from_str(json)
+            .map_err(|e| anyhow::anyhow!("Failed to parse --{}: {}", flag, e)),
+        None => Ok(fallback.to_vec()),
+    }
 }
 
 // ---------------------------------------------------------------------------
-// Initialization helpers
+// CLI arguments
 // ---------------------------------------------------------------------------
 
-async fn resolve_allowed_containers(args: &Args, docker: &Docker) -> Vec<String> {
-    let containers_from_cli = args
-        .containers
-        .as_ref()
-        .and_then(|json| serde_json::from_str::<Vec<String>>(json).ok());
+/// Simple container management
+#[derive(Parser, Debug, Clone)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Containers specified on the command line (JSON format)
+    #[arg(short = 'c', long, value_parser, env = "DOCKER_DIRECT_CONTAINERS")]
+    containers: Option<String>,
 
-    if let Some(containers) = containers_from_cli {
-        return containers;
-    }
+    /// Filename to read allowed containers from file [default: containers.txt]
+    #[arg(short, long, env = "DOCKER_DIRECT_FILE")]
+    file: Option<String>,
+
+    /// TOML configuration file; command line flags override its values
+    #[arg(long, env = "DOCKER_DIRECT_CONFIG")]
+    config: Option<String>,
+
+    /// Directory of *.toml and *.txt fragments merged into the configuration
+    #[arg(long, env = "DOCKER_DIRECT_CONFIG_DIR")]
+    config_dir: Option<String>,
+
+    /// Also allow every container with this label set to `true`, e.g. docker-direct.enable
+    #[arg(long, env = "DOCKER_DIRECT_ALLOW_LABEL")]
+    allow_label: Option<String>,
+
+    /// Allow every container except these (JSON format); replaces the allowlist
+    #[arg(long, env = "DOCKER_DIRECT_DENY")]
+    deny: Option<String>,
+
+    /// Port number used for server [default: 1234]
+    #[arg(short, long, env = "DOCKER_DIRECT_PORT")]
+    port: Option<u64>,
+
+    /// Address to listen on, e.g. 127.0.0.1 or [::] [default: 0.0.0.0]
+    #[arg(long, env = "DOCKER_DIRECT_BIND")]
+    bind: Option<String>,
+
+    /// PEM certificate chain to serve HTTPS with; requires --tls-key
+    #[arg(long, env = "DOCKER_DIRECT_TLS_CERT")]
+    tls_cert: Option<String>,
+
+    /// PEM private key for --tls-cert
+    #[arg(long, env = "DOCKER_DIRECT_TLS_KEY")]
+    tls_key: Option<String>,
+
+    /// PEM CA certificates; with HTTPS, require client certificates signed by one
+    #[arg(long, env = "DOCKER_DIRECT_TLS_CLIENT_CA")]
+    tls_client_ca: Option<String>,
+
+    /// With HTTPS, also listen for plain HTTP on this port and redirect it to HTTPS
+    #[arg(long, env = "DOCKER_DIRECT_HTTP_REDIRECT_PORT")]
+    http_redirect_port: Option<u64>,
+
+    /// Docker daemon to connect to, e.g. unix:///var/run/docker.sock or tcp://host:2375
+    /// [default: the local socket]
+    #[arg(long, env = "DOCKER_DIRECT_DOCKER_HOST")]
+    docker_host: Option<String>,
+
+    /// Seconds to wait for a container to stop before killing it on restart [default: 10]
+    #[arg(long, env = "DOCKER_DIRECT_RESTART_TIMEOUT")]
+    restart_timeout: Option<isize>,
+
+    /// Swarm services that may be listed and scaled (JSON format)
+    #[arg(long, env = "DOCKER_DIRECT_SERVICES")]
+    services: Option<String>,
+
+    /// Networks containers may be connected to or disconnected from (JSON format)
+    #[arg(long, env = "DOCKER_DIRECT_NETWORKS")]
+    networks: Option<String>,
+
+    /// Lowest CPU shares value that may be set through the resources endpoint [default: 128]
+    #[arg(long, env = "DOCKER_DIRECT_MIN_CPU_SHARES")]
+    min_cpu_shares: Option<isize>,
+
+    /// Highest CPU shares value that may be set through the resources endpoint [default: 4096]
+    #[arg(long, env = "DOCKER_DIRECT_MAX_CPU_SHARES")]
+    max_cpu_shares: Option<isize>,
+
+    /// Lowest memory limit in MB that may be set through the resources endpoint [default: 64]
+    #[arg(long, env = "DOCKER_DIRECT_MIN_MEMORY_MB")]
+    min_memory_mb: Option<i64>,
+
+    /// Highest memory limit in MB that may be set through the resources endpoint [default: 16384]
+    #[arg(long, env = "DOCKER_DIRECT_MAX_MEMORY_MB")]
+    max_memory_mb: Option<i64>,
+
+    /// JSON file with named commands that may be executed per container
+    #[arg(long, env = "DOCKER_DIRECT_EXEC_FILE")]
+    exec_file: Option<String>,
+
+    /// JSON file with named paths that may be downloaded per container
+    #[arg(long, env = "DOCKER_DIRECT_DOWNLOAD_FILE")]
+    download_file: Option<String>,
+
+    /// JSON file declaring which containers each container depends on
+    #[arg(long, env = "DOCKER_DIRECT_DEPENDS_FILE")]
+    depends_file: Option<String>,
+
+    /// JSON file restricting which actions each container permits
+    #[arg(long, env = "DOCKER_DIRECT_ACTIONS_FILE")]
+    actions_file: Option<String>,
+
+    /// Seconds to wait for each dependency to become healthy before giving up [default: 120]
+    #[arg(long, env = "DOCKER_DIRECT_DEPENDENCY_TIMEOUT")]
+    dependency_timeout: Option<u64>,
+
+    /// JSON file mapping stack names to compose files that admins may deploy
+    #[arg(long, env = "DOCKER_DIRECT_STACKS_FILE")]
+    stacks_file: Option<String>,
+
+    /// JSON file with groups of containers of which only one may run at a time
+    #[arg(long, env = "DOCKER_DIRECT_EXCLUSIVE_FILE")]
+    exclusive_file: Option<String>,
+
+    /// JSON file with profiles: named sets of containers to switch between
+    #[arg(long, env = "DOCKER_DIRECT_PROFILES_FILE")]
+    profiles_file: Option<String>,
+
+    /// JSON file with named groups of containers that can be started and stopped together
+    #[arg(long, env = "DOCKER_DIRECT_GROUPS_FILE")]
+    groups_file: Option<String>,
+
+    /// JSON file with display names, descriptions, categories and icons per container
+    #[arg(long, env = "DOCKER_DIRECT_DISPLAY_FILE")]
+    display_file: Option<String>,
+
+    /// Minutes between checks of the registry for newer images, 0 disables [default: 360]
+    #[arg(long, env = "DOCKER_DIRECT_UPDATE_CHECK_INTERVAL")]
+    update_check_interval: Option<u64>,
+
+    /// Include stopped containers in the container list [default: true]
+    #[arg(long, env = "DOCKER_DIRECT_SHOW_STOPPED")]
+    show_stopped: Option<bool>,
+
+    /// Only show containers; reject every start, stop or other change
+    #[arg(long, env = "DOCKER_DIRECT_READ_ONLY")]
+    read_only: bool,
+
+    /// Action requests (start, stop, ...) per minute per client or API key, 0 disables
+    /// [default: 60]
+    #[arg(long, env = "DOCKER_DIRECT_RATE_LIMIT")]
+    rate_limit: Option<u64>,
+
+    /// Seconds a request may take before it is answered with 504, 0 disables [default: 300]
+    #[arg(long, env = "DOCKER_DIRECT_REQUEST_TIMEOUT")]
+    request_timeout: Option<u64>,
+
+    /// Requests handled at the same time; more are answered with 503, 0 disables
+    /// [default: 64]
+    #[arg(long, env = "DOCKER_DIRECT_MAX_CONCURRENT_REQUESTS")]
+    max_concurrent_requests: Option<usize>,
+
+    /// Failed sign-ins in a row before a client or user is locked out, 0 disables [default: 5]
+    #[arg(long, env = "DOCKER_DIRECT_LOGIN_ATTEMPTS")]
+    login_attempts: Option<u32>,
+
+    /// Name shown in the web UI's heading and browser tab [default: Docker Direct]
+    #[arg(long, env = "DOCKER_DIRECT_TITLE")]
+    title: Option<String>,
+
+    /// Hex color for container names and highlights in the web UI, e.g. "#ff8800"
+    /// [default: the theme's]
+    #[arg(long, env = "DOCKER_DIRECT_ACCENT_COLOR")]
+    accent_color: Option<String>,
+
+    /// Web UI theme for browsers that haven't picked one: auto, light, dark, cyberpunk or
+    /// kanagawa [default: auto]
+    #[arg(long, env = "DOCKER_DIRECT_THEME")]
+    theme: Option<String>,
+
+    /// Web UI language for browsers whose Accept-Language names none of the locales
+    /// [default: en]
+    #[arg(long, env = "DOCKER_DIRECT_LOCALE")]
+    locale: Option<String>,
+
+    /// Directory of <locale>.json message catalogs translating the web UI, e.g. de.json
+    #[arg(long, env = "DOCKER_DIRECT_LOCALE_DIR")]
+    locale_dir: Option<String>,
+
+    /// JSON Lines file to append a record of every change to [default: none]
+    #[arg(long, env = "DOCKER_DIRECT_AUDIT_LOG")]
+    audit_log: Option<String>,
+
+    /// Key to sign action links with [default: random, so links stop working on restart]
+    #[arg(long, env = "DOCKER_DIRECT_LINK_SECRET")]
+    link_secret: Option<String>,
+
+    /// Addresses or CIDR networks clients may connect from (JSON format) [default: any]
+    #[arg(long, env = "DOCKER_DIRECT_ALLOWED_CLIENTS")]
+    allowed_clients: Option<String>,
+
+    /// Reverse proxies whose X-Forwarded-For and X-Real-IP headers name the client (JSON format)
+    /// [default: none]
+    #[arg(long, env = "DOCKER_DIRECT_TRUSTED_PROXIES")]
+    trusted_proxies: Option<String>,
+
+    /// Sites whose scripts may call the API from the browser, or "*" (JSON format)
+    /// [default: none]
+    #[arg(long, env = "DOCKER_DIRECT_CORS_ORIGINS")]
+    cors_origins: Option<String>,
+
+    /// Methods those sites may use (JSON format) [default: ["GET", "POST", "PUT"]]
+    #[arg(long, env = "DOCKER_DIRECT_CORS_METHODS")]
+    cors_methods: Option<String>,
+
+    /// Request headers those sites may send (JSON format)
+    /// [default: ["authorization", "content-type", "x-api-key", "idempotency-key"]]
+    #[arg(long, env = "DOCKER_DIRECT_CORS_HEADERS")]
+    cors_headers: Option<String>,
+
+    /// Token required (as a Bearer token) for admin-only endpoints such as export
+    #[arg(long, env = "DOCKER_DIRECT_ADMIN_TOKEN", hide_env_values = true)]
+    admin_token: Option<String>,
 
-    let containers_from_file = load_file_containers(args.file.as_deref().unwrap_or("containers.txt"));
+    /// htpasswd file of users allowed to sign in with HTTP Basic authentication
+    #[arg(long, env = "DOCKER_DIRECT_HTPASSWD")]
+    htpasswd: Option<String>,
 
-    // Get all container names from Docker to validate
-    let options = ListContainersOptions {
-        all: true,
-        filters: common_filters(),
-        limit: Some(200),
-        size: false,
-    };
-    let docker_containers: HashSet<String> = docker
-        .list_containers(Some(options))
-        .await
-        .unwrap_or_default()
-        .iter()
-        .filter_map(|c| {
-            c.names.as_ref().and_then(|names| {
-                names
-                    .first()
-                    .map(|n| n.trim_start_matches('/').to_string())
-            })
-        })
-        .collect();
+    /// Logging level [default: info]
+    #[clap(short, long, env = "DOCKER_DIRECT_LOG_LEVEL")]
+    log_level: Option<String>,
+}
 
-    containers_from_file
-        .into_iter()
-        .filter(|c| docker_containers.contains(c))
-        .collect()
+/// Connect to the Docker daemon at `host`, a `unix://`, `tcp://` or `http://`
+/// address, or to the local socket when none is given.
+fn connect_docker(host: Option<&str>) -> Result<Docker> {
+    let docker = match host {
+        None => Docker::connect_with_local_defaults()?,
+        Some(host) if host.starts_with("tcp://") || host.starts_with("http://") => {
+            Docker::connect_with_http(host, 120, bollard::API_DEFAULT_VERSION)?
+        }
+        Some(host) => Docker::connect_with_unix(host, 120, bollard::API_DEFAULT_VERSION)?,
+    };
+    Ok(docker)
 }
 
-fn load_file_containers(filename: &str) -> Vec<String> {
-    if std::fs::metadata(filename).is_ok() {
-        match read_to_string(filename) {
-            Ok(content) => content.lines().map(|l| l.to_string()).collect(),
-            Err(e) => {
-                tracing::warn!("Failed to read {}: {}", filename, e);
-                Vec::new()
-            }
+/// Load the certificate and key to serve HTTPS with, if configured, and with
+/// `client_ca` require clients to present a certificate signed by it.
+async fn load_tls(
+    cert: Option<&str>,
+    key: Option<&str>,
+    client_ca: Option<&str>,
+) -> Result<Option<axum_server::tls_rustls::RustlsConfig>> {
+    let (cert, key) = match (cert, key) {
+        (Some(cert), Some(key)) => (cert, key),
+        (None, None) if client_ca.is_some() => {
+            anyhow::bail!("--tls-client-ca requires --tls-cert and --tls-key")
         }
-    } else {
-        tracing::warn!("File '{}' does not exist", filename);
-        Vec::new()
+        (None, None) => return Ok(None),
+        _ => anyhow::bail!("--tls-cert and --tls-key must be given together"),
+    };
+    let Some(client_ca) = client_ca else {
+        let tls = axum_server::tls_rustls::RustlsConfig::from_pem_file(cert, key)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to load TLS certificate '{}': {}", cert, e))?;
+        return Ok(Some(tls));
+    };
+
+    use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+    let mut roots = rustls::RootCertStore::empty();
+    for ca in CertificateDer::pem_file_iter(client_ca)
+        .map_err(|e| anyhow::anyhow!("Failed to read client CA '{}': {}", client_ca, e))?
+    {
+        roots.add(ca?)?;
     }
+    let verifier = rustls::server::WebPkiClientVerifier::builder(Arc::new(roots)).build()?;
+    let chain = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| anyhow::anyhow!("Failed to load TLS certificate '{}': {}", cert, e))?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| anyhow::anyhow!("Failed to load TLS key '{}': {}", key, e))?;
+    let mut config = rustls::ServerConfig::builder()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(chain, key)?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Some(axum_server::tls_rustls::RustlsConfig::from_config(
+        Arc::new(config),
+    )))
 }
 
-// ---------------------------------------------------------------------------
-// CLI arguments
-// ---------------------------------------------------------------------------
+/// The client certificate a connection was made with, when client
+/// certificates are required.
+#[derive(Clone, Debug)]
+struct ClientCertificate {
+    /// Subject common name, used as the user name
+    common_name: Option<String>,
+}
 
-/// Simple container management
-#[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
-struct Args {
-    /// Containers specified on the command line (JSON format)
-    #[arg(short = 'c', long, value_parser)]
-    containers: Option<String>,
+/// Accepts TLS connections like `RustlsAcceptor` and attaches the client's
+/// certificate to every request made over the connection.
+#[derive(Clone)]
+struct ClientCertificateAcceptor {
+    inner: axum_server::tls_rustls::RustlsAcceptor,
+}
 
-    /// Filename to read allowed containers from file
-    #[arg(short, long, default_value = "containers.txt")]
-    file: Option<String>,
+impl<I, S> axum_server::accept::Accept<I, S> for ClientCertificateAcceptor
+where
+    I: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    type Stream =
+        <axum_server::tls_rustls::RustlsAcceptor as axum_server::accept::Accept<I, S>>::Stream;
+    type Service = axum::middleware::AddExtension<S, ClientCertificate>;
+    type Future =
+        futures_util::future::BoxFuture<'static, std::io::Result<(Self::Stream, Self::Service)>>;
 
-    /// Port number used for server
-    #[arg(short, long, default_value_t = 1234)]
-    port: u64,
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let acceptor = self.inner.clone();
+        Box::pin(async move {
+            let (stream, service) = acceptor.accept(stream, service).await?;
+            let common_name = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|chain| chain.first())
+                .and_then(|cert| certificate_common_name(cert));
+            let certificate = ClientCertificate { common_name };
+            Ok((
+                stream,
+                tower::Layer::layer(&axum::Extension(certificate), service),
+            ))
+        })
+    }
+}
+
+fn certificate_common_name(der: &[u8]) -> Option<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+    let common_name = cert.subject().iter_common_name().next()?.as_str().ok()?;
+    Some(common_name.to_string())
+}
 
-    /// Logging level
-    #[clap(short, long, default_value = "info")]
-    log_level: String,
+/// A server that answers every plain HTTP request with a redirect to the
+/// same URL on the HTTPS port.
+fn https_redirect(https_port: u64) -> Router {
+    Router::new().fallback(
+        move |headers: axum::http::HeaderMap, uri: axum::http::Uri| async move {
+            let Some(host) = headers
+                .get(axum::http::header::HOST)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<axum::http::uri::Authority>().ok())
+            else {
+                return (StatusCode::BAD_REQUEST, "Missing Host header").into_response();
+            };
+            let path = uri.path_and_query().map_or("/", |p| p.as_str());
+            let location = if https_port == 443 {
+                format!("https://{}{}", host.host(), path)
+            } else {
+                format!("https://{}:{}{}", host.host(), https_port, path)
+            };
+            axum::response::Redirect::permanent(&location).into_response()
+        },
+    )
 }
 
 fn parse_log_level(log_level: &str) -> Level {
@@ -921,26 +10974,245 @@ fn parse_log_level(log_level: &str) -> Level {
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
+    let config = load_configuration(&args)?;
 
-    let log_level = parse_log_level(&args.log_level);
+    let log_level = args
+        .log_level
+        .as_deref()
+        .or(config.server.log_level.as_deref())
+        .unwrap_or("info");
     let subscriber = FmtSubscriber::builder()
-        .with_max_level(log_level)
+        .with_max_level(parse_log_level(log_level))
         .finish();
     tracing::subscriber::set_global_default(subscriber).expect("Failed to set tracing subscriber");
 
-    let docker = Docker::connect_with_local_defaults().expect("Failed to connect to Docker");
+    let docker_host = args
+        .docker_host
+        .as_deref()
+        .or(config.server.docker_host.as_deref());
+    let docker = connect_docker(docker_host).expect("Failed to connect to Docker");
 
-    let allowed = resolve_allowed_containers(&args, &docker).await;
-    tracing::info!("Allowed containers: {:?}", allowed);
+    let policy = load_policy(&args, &config, &docker).await?;
+
+    let port = args.port.or(config.server.port).unwrap_or(1234);
+
+    let mut users = config.auth.users.clone();
+    if let Some(path) = args.htpasswd.as_ref().or(config.auth.htpasswd.as_ref()) {
+        users.extend(load_htpasswd(path)?);
+    }
+    let client_certificates = args.tls_client_ca.is_some() || config.server.tls_client_ca.is_some();
+    if users.is_empty()
+        && config.auth.api_keys.is_empty()
+        && config.auth.oidc.is_none()
+        && config.auth.forward_auth.is_none()
+        && config.auth.ldap.is_none()
+        && !client_certificates
+    {
+        tracing::warn!("No users or API keys configured, the web UI and API are open to anyone");
+    }
+    let bind = args
+        .bind
+        .clone()
+        .or_else(|| config.server.bind.clone())
+        .unwrap_or_else(|| "0.0.0.0".to_string());
+    let tls_client_ca = args
+        .tls_client_ca
+        .as_deref()
+        .or(config.server.tls_client_ca.as_deref());
+    let tls = load_tls(
+        args.tls_cert
+            .as_deref()
+            .or(config.server.tls_cert.as_deref()),
+        args.tls_key.as_deref().or(config.server.tls_key.as_deref()),
+        tls_client_ca,
+    )
+    .await?;
+    let http_redirect_port = args.http_redirect_port.or(config.server.http_redirect_port);
+    let allowed_clients = parse_client_networks(&list_option(
+        args.allowed_clients.as_deref(),
+        "allowed-clients",
+        &config.server.allowed_clients,
+    )?)?;
+    let trusted_proxies = parse_client_networks(&list_option(
+        args.trusted_proxies.as_deref(),
+        "trusted-proxies",
+        &config.server.trusted_proxies,
+    )?)?;
+    let cors_origins = list_option(
+        args.cors_origins.as_deref(),
+        "cors-origins",
+        &config.server.cors_origins,
+    )?;
+    let cors = cors_layer(
+        &cors_origins,
+        &list_option(
+            args.cors_methods.as_deref(),
+            "cors-methods",
+            &config.server.cors_methods,
+        )?,
+        &list_option(
+            args.cors_headers.as_deref(),
+            "cors-headers",
+            &config.server.cors_headers,
+        )?,
+    )?;
+    let accent_color = args
+        .accent_color
+        .clone()
+        .or(config.server.accent_color.clone());
+    if let Some(color) = &accent_color {
+        check_accent_color(color)?;
+    }
+    let theme = args
+        .theme
+        .clone()
+        .or(config.server.theme.clone())
+        .unwrap_or_else(|| "auto".to_string());
+    if !THEMES.contains(&theme.as_str()) {
+        anyhow::bail!(
+            "Unknown theme '{}', expected one of {}",
+            theme,
+            THEMES.join(", ")
+        );
+    }
+    let locales = load_locales(
+        args.locale_dir
+            .as_deref()
+            .or(config.server.locale_dir.as_deref()),
+        args.locale
+            .as_deref()
+            .or(config.server.locale.as_deref())
+            .unwrap_or("en"),
+    )?;
+    if config.auth.forward_auth.is_some() && trusted_proxies.is_empty() {
+        anyhow::bail!("[auth.forward_auth] requires --trusted-proxies");
+    }
+    let audit_log = match args.audit_log.as_ref().or(config.server.audit_log.as_ref()) {
+        Some(path) => Some((
+            path.clone(),
+            tokio::sync::Mutex::new(open_audit_log(path).await?),
+        )),
+        None => None,
+    };
+    let used_links_file = audit_log.as_ref().map(|(path, _)| used_links_path(path));
+    let used_links = match &used_links_file {
+        Some(path) => load_used_links(path)?,
+        None => HashMap::new(),
+    };
+    let link_secret = match args
+        .link_secret
+        .as_ref()
+        .or(config.server.link_secret.as_ref())
+    {
+        Some(secret) if secret.len() < 16 => {
+            anyhow::bail!("--link-secret must be at least 16 characters long")
+        }
+        Some(secret) => secret.clone(),
+        None => random_token(),
+    };
+    let resources = &config.resources;
+    let max_concurrent_requests = args
+        .max_concurrent_requests
+        .or(config.server.max_concurrent_requests)
+        .unwrap_or(64);
+    let request_permits = (max_concurrent_requests > 0)
+        .then(|| Arc::new(tokio::sync::Semaphore::new(max_concurrent_requests)));
 
     let state = Arc::new(AppState {
         docker,
-        allowed_containers: allowed,
-        port: args.port,
+        policy: std::sync::RwLock::new(Arc::new(policy)),
+        port,
+        restart_timeout: args
+            .restart_timeout
+            .or(config.server.restart_timeout)
+            .unwrap_or(10),
+        resource_bounds: ResourceBounds {
+            min_cpu_shares: args
+                .min_cpu_shares
+                .or(resources.min_cpu_shares)
+                .unwrap_or(128),
+            max_cpu_shares: args
+                .max_cpu_shares
+                .or(resources.max_cpu_shares)
+                .unwrap_or(4096),
+            min_memory_mb: args.min_memory_mb.or(resources.min_memory_mb).unwrap_or(64),
+            max_memory_mb: args
+                .max_memory_mb
+                .or(resources.max_memory_mb)
+                .unwrap_or(16384),
+        },
+        dependency_timeout: args
+            .dependency_timeout
+            .or(config.server.dependency_timeout)
+            .unwrap_or(120),
+        admin_token: args.admin_token.clone().or(config.auth.admin_token.clone()),
+        auth: AuthSettings {
+            users,
+            api_keys: config.auth.api_keys.clone(),
+            roles: config.auth.roles.clone(),
+            default_role: config.auth.default_role.unwrap_or_default(),
+            permissions: config.auth.permissions.clone(),
+            client_certificates,
+            secure_cookies: tls.is_some(),
+            forward_auth: config.auth.forward_auth.clone(),
+            group_roles: config.auth.group_roles.clone(),
+            ldap: config.auth.ldap.clone(),
+            oidc: config.auth.oidc.clone(),
+            session_lifetime: std::time::Duration::from_secs(
+                config.auth.session_hours.unwrap_or(12) * 3600,
+            ),
+            ..Default::default()
+        },
         container_cache: RwLock::new(Vec::new()),
         cache_generation: std::sync::atomic::AtomicU64::new(0),
         common_filters: common_filters(),
         mc_cache: RwLock::new(HashMap::new()),
+        event_log: RwLock::new(VecDeque::with_capacity(EVENT_LOG_SIZE)),
+        event_tx: broadcast::channel(64).0,
+        refresh_now: tokio::sync::Notify::new(),
+        list_changed: broadcast::channel(16).0,
+        update_check_interval: args
+            .update_check_interval
+            .or(config.server.update_check_interval)
+            .unwrap_or(360),
+        update_cache: RwLock::new(HashMap::new()),
+        show_stopped: args
+            .show_stopped
+            .or(config.server.show_stopped)
+            .unwrap_or(true),
+        read_only: args.read_only || config.server.read_only.unwrap_or(false),
+        allowed_clients,
+        trusted_proxies,
+        rate_limit: args.rate_limit.or(config.server.rate_limit).unwrap_or(60),
+        rate_buckets: std::sync::Mutex::new(HashMap::new()),
+        request_timeout: args
+            .request_timeout
+            .or(config.server.request_timeout)
+            .unwrap_or(300),
+        request_permits,
+        idempotent_requests: std::sync::Mutex::new(HashMap::new()),
+        login_attempts: args
+            .login_attempts
+            .or(config.server.login_attempts)
+            .unwrap_or(5),
+        login_failures: std::sync::Mutex::new(HashMap::new()),
+        csrf_secret: random_token(),
+        title: args
+            .title
+            .clone()
+            .or(config.server.title.clone())
+            .unwrap_or_else(|| "Docker Direct".to_string()),
+        accent_color,
+        theme,
+        locales,
+        cors_origins: cors_origins
+            .iter()
+            .map(|o| o.trim_end_matches('/').to_string())
+            .collect(),
+        link_secret,
+        used_links: std::sync::Mutex::new(used_links),
+        used_links_file: used_links_file.map(|path| (path, tokio::sync::Mutex::new(()))),
+        audit_log,
     });
 
     // Spawn background refresh task
@@ -951,21 +11223,940 @@ async fn main() -> Result<()> {
         });
     }
 
+    // Spawn registry update checker
+    {
+        let state = Arc::clone(&state);
+        tokio::spawn(async move {
+            update_checker(state).await;
+        });
+    }
+
+    // Spawn Docker event watcher
+    {
+        let state = Arc::clone(&state);
+        tokio::spawn(async move {
+            watch_events(state).await;
+        });
+    }
+
+    // Reload the configuration on SIGHUP
+    {
+        let state = Arc::clone(&state);
+        let args = args.clone();
+        tokio::spawn(async move {
+            reload_on_sighup(state, args).await;
+        });
+    }
+
     let app = Router::new()
-        .route("/containers", get(render_containers_html))
+        .route("/containers", get(containers_handle))
+        .route("/logs", get(logs_page_handle))
+        .route("/stats", get(stats_page_handle))
+        .route("/assets/{*path}", get(asset_handle))
+        .route("/manifest.webmanifest", get(manifest_handle))
+        .route("/sw.js", get(service_worker_handle))
         .route("/containers/statuses", get(get_container_statuses))
-        .route("/containers/start", get(start_container_handle))
-        .route("/containers/stop", get(stop_container_handle))
-        .route("/containers/stop-all", get(stop_all_handle))
-        .with_state(state)
-        .into_make_service_with_connect_info::<SocketAddr>();
-
-    tracing::info!("Starting docker-direct on port {}", args.port);
+        .route("/containers/start", post(start_container_handle))
+        .route("/containers/stop", post(stop_container_handle))
+        .route("/containers/restart", post(restart_container_handle))
+        .route("/containers/pause", post(pause_container_handle))
+        .route("/containers/kill", post(kill_container_handle))
+        .route("/containers/recreate", post(recreate_container_handle))
+        .route("/containers/update", post(update_container_handle))
+        .route("/containers/wait", get(wait_container_handle))
+        .route("/containers/inspect", get(inspect_container_handle))
+        .route("/containers/health", get(health_container_handle))
+        .route("/containers/processes", get(processes_container_handle))
+        .route("/containers/diff", get(diff_container_handle))
+        .route("/containers/restart-policy", get(restart_policy_handle))
+        .route(
+            "/containers/restart-policy/set",
+            post(set_restart_policy_handle),
+        )
+        .route("/containers/commands", get(list_commands_handle))
+        .route("/containers/exec", post(exec_command_handle))
+        .route("/containers/resources", post(update_resources_handle))
+        .route("/containers/downloads", get(list_downloads_handle))
+        .route("/containers/download", get(download_handle))
+        .route("/containers/export", get(export_container_handle))
+        .route("/containers/stats", get(stats_container_handle))
+        .route("/containers/stats/stream", get(stats_stream_handle))
+        .route("/containers/logs", get(logs_container_handle))
+        .route("/containers/logs/stream", get(logs_stream_handle))
+        .route("/containers/unpause", post(unpause_container_handle))
+        .route("/containers/stop-all", post(stop_all_handle))
+        .route("/containers/batch", post(batch_handle))
+        .route("/volumes", get(volumes_handle))
+        .route("/volumes/backup", get(volume_backup_handle))
+        .route("/projects", get(projects_handle))
+        .route("/projects/start", post(project_start_handle))
+        .route("/projects/stop", post(project_stop_handle))
+        .route("/profiles", get(profiles_handle))
+        .route("/profiles/activate", post(activate_profile_handle))
+        .route("/groups", get(groups_handle))
+        .route("/groups/start", post(group_start_handle))
+        .route("/groups/stop", post(group_stop_handle))
+        .route("/groups/restart", post(group_restart_handle))
+        .route("/services", get(services_handle))
+        .route("/services/scale", post(scale_service_handle))
+        .route("/stacks", get(stacks_handle))
+        .route("/stacks/deploy", post(upload_stack_handle))
+        .route("/stacks/teardown", post(teardown_stack_handle))
+        .route("/admin/allowlist", post(allowlist_handle))
+        .route("/auth/oidc/login", get(oidc_login_handle))
+        .route("/auth/oidc/callback", get(oidc_callback_handle))
+        .route("/login", get(login_page_handle).post(login_handle))
+        .route("/logout", post(logout_handle))
+        .route("/admin/allowlist/validate", get(validate_allowlist_handle))
+        .route("/networks", get(networks_handle))
+        .route("/networks/connect", post(connect_network_handle))
+        .route("/networks/disconnect", post(disconnect_network_handle))
+        .route("/images", get(images_handle))
+        .route("/images/pull", post(pull_image_handle))
+        .route("/images/prune", post(prune_images_handle))
+        .route("/images/history", get(image_history_handle))
+        .route("/system/df", get(system_df_handle))
+        .route("/events", get(events_handle))
+        .route("/events/stream", get(events_stream_handle))
+        .route("/ws/containers", get(containers_ws_handle))
+        .route("/audit", get(audit_handle))
+        .route("/admin/links", post(create_link_handle))
+        .route("/link", get(link_page_handle).post(link_handle))
+        .route("/api/docs", get(api_docs_handle))
+        .route("/api/openapi.json", get(openapi_handle))
+        // Innermost, so requests it cuts off are still audited
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            limit_requests,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            audit_actions,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            rate_limit_actions,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            replay_idempotent,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            protect_from_csrf,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            require_auth,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            restrict_clients,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            resolve_forwarded_client,
+        ))
+        .with_state(state.clone());
+    // In front of the routes above, since it decides which of them serves a request
+    let mut app = Router::new()
+        .fallback_service(app)
+        .layer(axum::middleware::from_fn(route_api_v1))
+        // gzip or brotli as the client accepts; event streams are left alone
+        .layer(tower_http::compression::CompressionLayer::new());
+    // Outermost, so preflight requests are answered before authentication
+    if let Some(cors) = cors {
+        app = app.layer(cors);
+    }
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
 
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", args.port))
+    let listener = tokio::net::TcpListener::bind(format!("{}:{}", bind, port))
         .await
         .expect("Failed to bind listener");
-    axum::serve(listener, app).await?;
+    // Tells both servers below, and the grace period, that a signal arrived
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        let _ = shutdown_tx.send(true);
+    });
+    let shutdown = |mut rx: tokio::sync::watch::Receiver<bool>| async move {
+        let _ = rx.wait_for(|stopping| *stopping).await;
+    };
+
+    let Some(tls) = tls else {
+        let grace_period = {
+            let shutdown = shutdown(shutdown_rx.clone());
+            async move {
+                shutdown.await;
+                tokio::time::sleep(std::time::Duration::from_secs(SHUTDOWN_GRACE_SECS)).await;
+            }
+        };
+        tracing::info!("Starting docker-direct on {}:{}", bind, port);
+        let server = axum::serve(listener, app).with_graceful_shutdown(shutdown(shutdown_rx));
+        tokio::select! {
+            result = async { server.await } => result?,
+            _ = grace_period => tracing::warn!("Requests still running, exiting anyway"),
+        }
+        flush_audit_log(&state).await;
+        tracing::info!("Stopped");
+        return Ok(());
+    };
+
+    if let Some(redirect_port) = http_redirect_port {
+        let redirect_listener =
+            tokio::net::TcpListener::bind(format!("{}:{}", bind, redirect_port))
+                .await
+                .expect("Failed to bind HTTP redirect listener");
+        tracing::info!("Redirecting HTTP on {}:{} to HTTPS", bind, redirect_port);
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(redirect_listener, https_redirect(port)).await {
+                tracing::error!("HTTP redirect server failed: {}", e);
+            }
+        });
+    }
+
+    // axum-server enforces the grace period itself
+    let handle = axum_server::Handle::new();
+    {
+        let handle = handle.clone();
+        let shutdown = shutdown(shutdown_rx);
+        tokio::spawn(async move {
+            shutdown.await;
+            handle.graceful_shutdown(Some(std::time::Duration::from_secs(SHUTDOWN_GRACE_SECS)));
+        });
+    }
+
+    tracing::info!("Starting docker-direct on {}:{} with HTTPS", bind, port);
+    if tls_client_ca.is_some() {
+        let acceptor = ClientCertificateAcceptor {
+            inner: axum_server::tls_rustls::RustlsAcceptor::new(tls),
+        };
+        axum_server::from_tcp(listener.into_std()?)
+            .acceptor(acceptor)
+            .handle(handle)
+            .serve(app)
+            .await?;
+    } else {
+        axum_server::from_tcp_rustls(listener.into_std()?, tls)
+            .handle(handle)
+            .serve(app)
+            .await?;
+    }
+    flush_audit_log(&state).await;
+    tracing::info!("Stopped");
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+
+    /// State with every feature off, for testing helpers that need one.
+    fn test_state() -> AppState {
+        AppState {
+            docker: Docker::connect_with_http(
+                "http://127.0.0.1:2375",
+                1,
+                bollard::API_DEFAULT_VERSION,
+            )
+            .expect("HTTP client without a connection"),
+            policy: std::sync::RwLock::new(Arc::new(Policy::default())),
+            port: 8080,
+            restart_timeout: 10,
+            resource_bounds: ResourceBounds {
+                min_cpu_shares: 128,
+                max_cpu_shares: 4096,
+                min_memory_mb: 64,
+                max_memory_mb: 16384,
+            },
+            dependency_timeout: 120,
+            admin_token: None,
+            auth: AuthSettings::default(),
+            container_cache: RwLock::new(Vec::new()),
+            cache_generation: std::sync::atomic::AtomicU64::new(0),
+            common_filters: HashMap::new(),
+            mc_cache: RwLock::new(HashMap::new()),
+            event_log: RwLock::new(VecDeque::new()),
+            event_tx: broadcast::channel(1).0,
+            refresh_now: tokio::sync::Notify::new(),
+            list_changed: broadcast::channel(1).0,
+            update_check_interval: 0,
+            update_cache: RwLock::new(HashMap::new()),
+            show_stopped: true,
+            read_only: false,
+            allowed_clients: Vec::new(),
+            trusted_proxies: Vec::new(),
+            rate_limit: 0,
+            rate_buckets: std::sync::Mutex::new(HashMap::new()),
+            idempotent_requests: std::sync::Mutex::new(HashMap::new()),
+            request_timeout: 0,
+            request_permits: None,
+            login_attempts: 5,
+            login_failures: std::sync::Mutex::new(HashMap::new()),
+            csrf_secret: "csrf-secret".to_string(),
+            title: "Docker Direct".to_string(),
+            accent_color: None,
+            theme: "auto".to_string(),
+            locales: Locales {
+                catalogs: HashMap::new(),
+                default: "en".to_string(),
+            },
+            cors_origins: Vec::new(),
+            audit_log: None,
+            link_secret: "link-secret".to_string(),
+            used_links: std::sync::Mutex::new(HashMap::new()),
+            used_links_file: None,
+        }
+    }
+
+    fn networks(entries: &[&str]) -> Vec<ipnet::IpNet> {
+        parse_client_networks(&entries.iter().map(|e| e.to_string()).collect::<Vec<_>>())
+            .expect("valid networks")
+    }
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().expect("valid address")
+    }
+
+    fn header_map(pairs: &[(&'static str, &str)]) -> axum::http::HeaderMap {
+        let mut headers = axum::http::HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, value.parse().expect("valid header value"));
+        }
+        headers
+    }
+
+    #[test]
+    fn client_networks_accept_networks_and_addresses() {
+        let parsed = networks(&["192.168.1.0/24", "10.0.0.5", "fd00::/8", "::1"]);
+        let expected: Vec<ipnet::IpNet> = ["192.168.1.0/24", "10.0.0.5/32", "fd00::/8", "::1/128"]
+            .iter()
+            .map(|n| n.parse().unwrap())
+            .collect();
+        assert_eq!(parsed, expected);
+    }
+
+    #[test]
+    fn client_networks_reject_invalid_entries() {
+        for entry in ["", "example.com", "192.168.1.0/33", "10.0.0.256"] {
+            assert!(
+                parse_client_networks(&[entry.to_string()]).is_err(),
+                "accepted {entry:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn untrusted_peer_is_the_client() {
+        let proxies = networks(&["10.0.0.1"]);
+        let headers = header_map(&[("x-forwarded-for", "1.2.3.4"), ("x-real-ip", "5.6.7.8")]);
+        assert_eq!(
+            forwarded_client(&proxies, ip("192.168.1.10"), &headers),
+            ip("192.168.1.10")
+        );
+        assert_eq!(
+            forwarded_client(&[], ip("10.0.0.1"), &headers),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn trusted_peer_forwards_the_client() {
+        let proxies = networks(&["10.0.0.1"]);
+        let headers = header_map(&[("x-forwarded-for", "1.2.3.4")]);
+        assert_eq!(
+            forwarded_client(&proxies, ip("10.0.0.1"), &headers),
+            ip("1.2.3.4")
+        );
+    }
+
+    #[test]
+    fn multi_hop_forwarding_stops_at_the_first_untrusted_address() {
+        let proxies = networks(&["10.0.0.0/24"]);
+        // The client claims to be 9.9.9.9; only addresses appended by trusted proxies count
+        let headers = header_map(&[
+            ("x-forwarded-for", "9.9.9.9, 1.2.3.4"),
+            ("x-forwarded-for", "10.0.0.2"),
+        ]);
+        assert_eq!(
+            forwarded_client(&proxies, ip("10.0.0.1"), &headers),
+            ip("1.2.3.4")
+        );
+
+        let headers = header_map(&[("x-forwarded-for", "10.0.0.3, 10.0.0.2")]);
+        assert_eq!(
+            forwarded_client(&proxies, ip("10.0.0.1"), &headers),
+            ip("10.0.0.3")
+        );
+    }
+
+    #[test]
+    fn real_ip_is_the_fallback() {
+        let proxies = networks(&["10.0.0.1"]);
+        let headers = header_map(&[("x-real-ip", " 1.2.3.4 ")]);
+        assert_eq!(
+            forwarded_client(&proxies, ip("10.0.0.1"), &headers),
+            ip("1.2.3.4")
+        );
+
+        let headers = header_map(&[("x-forwarded-for", "1.2.3.4"), ("x-real-ip", "5.6.7.8")]);
+        assert_eq!(
+            forwarded_client(&proxies, ip("10.0.0.1"), &headers),
+            ip("1.2.3.4")
+        );
+
+        let headers = header_map(&[("x-real-ip", "not an address")]);
+        assert_eq!(
+            forwarded_client(&proxies, ip("10.0.0.1"), &headers),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn ipv4_mapped_peers_match_ipv4_networks() {
+        let proxies = networks(&["127.0.0.1"]);
+        let headers = header_map(&[("x-forwarded-for", "::ffff:127.0.0.1, 1.2.3.4")]);
+        assert_eq!(
+            forwarded_client(&proxies, ip("::ffff:127.0.0.1"), &headers),
+            ip("1.2.3.4")
+        );
+
+        let headers = header_map(&[("x-forwarded-for", "1.2.3.4, ::ffff:127.0.0.1")]);
+        assert_eq!(
+            forwarded_client(&proxies, ip("::ffff:127.0.0.1"), &headers),
+            ip("1.2.3.4")
+        );
+        assert_eq!(
+            forwarded_client(&proxies, ip("::ffff:192.168.1.10"), &headers),
+            ip("::ffff:192.168.1.10")
+        );
+    }
+
+    #[test]
+    fn api_paths_match_segment_by_segment() {
+        assert_eq!(match_api_path("/containers", "/containers"), Some(vec![]));
+        assert_eq!(
+            match_api_path("/containers/{name}/start", "/containers/plex/start"),
+            Some(vec!["name=plex".to_string()])
+        );
+        assert_eq!(
+            match_api_path("/containers/{name}/start", "/containers//start"),
+            None
+        );
+        assert_eq!(
+            match_api_path("/containers/{name}/start", "/containers/plex/stop"),
+            None
+        );
+        assert_eq!(
+            match_api_path("/containers/{name}", "/containers/plex/start"),
+            None
+        );
+        assert_eq!(match_api_path("/containers/{name}", "/containers"), None);
+    }
+
+    #[test]
+    fn api_path_parameters_cannot_add_query_pairs() {
+        assert_eq!(
+            match_api_path("/containers/{name}", "/containers/a&name=b"),
+            Some(vec!["name=a%26name%3Db".to_string()])
+        );
+        assert_eq!(
+            match_api_path("/containers/{name}", "/containers/c++"),
+            Some(vec!["name=c%2B%2B".to_string()])
+        );
+        // Already encoded characters are passed on as they are
+        assert_eq!(
+            match_api_path("/containers/{name}", "/containers/my%20app"),
+            Some(vec!["name=my%20app".to_string()])
+        );
+    }
+
+    /// Send a request through `route_api_v1` to a handler answering with
+    /// the method and URI it got in an `x-routed` header, which survives
+    /// `HEAD` requests.
+    async fn route(method: &str, uri: &str) -> axum::response::Response {
+        use tower::ServiceExt;
+
+        let app = Router::new()
+            .fallback(|request: axum::extract::Request| async move {
+                [(
+                    "x-routed",
+                    format!("{} {}", request.method(), request.uri()),
+                )]
+            })
+            .layer(axum::middleware::from_fn(route_api_v1));
+        let request = axum::http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(axum::body::Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap()
+    }
+
+    async fn body_text(response: axum::response::Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn api_v1_requests_reach_their_routes() {
+        let response = route("POST", "/api/v1/containers/plex/start?wait_for=running").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()["x-routed"],
+            "POST /containers/start?name=plex&wait_for=running"
+        );
+
+        let response = route("PUT", "/api/v1/services/web/replicas?replicas=2").await;
+        assert_eq!(
+            response.headers()["x-routed"],
+            "POST /services/scale?name=web&replicas=2"
+        );
+
+        let response = route("GET", "/api/v1/containers").await;
+        assert_eq!(response.headers()["x-routed"], "GET /containers/statuses");
+
+        let response = route("POST", "/api/v1/containers/a&name=b+c/stop").await;
+        assert_eq!(
+            response.headers()["x-routed"],
+            "POST /containers/stop?name=a%26name%3Db%2Bc"
+        );
+    }
+
+    #[tokio::test]
+    async fn api_v1_head_requests_are_served_by_get_routes() {
+        let response = route("HEAD", "/api/v1/containers/plex").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()["x-routed"],
+            "HEAD /containers/inspect?name=plex"
+        );
+
+        let response = route("HEAD", "/api/v1/containers/plex/start").await;
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[tokio::test]
+    async fn api_v1_wrong_methods_list_the_allowed_ones() {
+        let response = route("PUT", "/api/v1/stacks/media").await;
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(
+            response.headers()[axum::http::header::ALLOW],
+            "POST, DELETE"
+        );
+        assert!(body_text(response).await.contains("\"method_not_allowed\""));
+
+        let response = route("DELETE", "/api/v1/containers/plex").await;
+        assert_eq!(response.headers()[axum::http::header::ALLOW], "GET");
+
+        let response = route("GET", "/api/v1/no-such-route").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn original_routes_are_marked_deprecated() {
+        let response = route("POST", "/containers/start?name=plex").await;
+        assert_eq!(response.headers()["deprecation"], "true");
+        let response = route("GET", "/containers").await;
+        assert!(!response.headers().contains_key("deprecation"));
+    }
+
+    #[tokio::test]
+    async fn malformed_parameters_are_invalid_requests() {
+        use tower::ServiceExt;
+
+        let app = Router::new()
+            .route(
+                "/query",
+                get(|Query(query): Query<ContainerName>| async move { query.name }),
+            )
+            .route(
+                "/form",
+                post(|Form(form): Form<LinkToken>| async move { form.token }),
+            );
+        let send = |request: axum::http::Request<axum::body::Body>| app.clone().oneshot(request);
+
+        let response = send(
+            axum::http::Request::get("/query?name=plex")
+                .body(Default::default())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(body_text(response).await, "plex");
+
+        let response = send(
+            axum::http::Request::get("/query")
+                .body(Default::default())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+        assert_eq!(body["code"], "invalid_request");
+        assert!(body["message"].as_str().unwrap().contains("name"));
+
+        let request = axum::http::Request::post("/form")
+            .header(
+                axum::http::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body(axum::body::Body::from("other=1"))
+            .unwrap();
+        let response = send(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+        assert_eq!(body["code"], "invalid_request");
+
+        let request = axum::http::Request::post("/form")
+            .body(axum::body::Body::from("token=1"))
+            .unwrap();
+        let response = send(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let body: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+        assert_eq!(body["code"], "invalid_request");
+    }
+
+    /// Send a `POST` with an `Idempotency-Key` through `replay_idempotent` to
+    /// `handler`.
+    async fn send_idempotent(
+        state: &SharedState,
+        handler: axum::routing::MethodRouter<SharedState>,
+        body: &'static str,
+    ) -> axum::response::Response {
+        use tower::ServiceExt;
+
+        let app = Router::new()
+            .route("/containers/batch", handler)
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                replay_idempotent,
+            ))
+            .with_state(state.clone());
+        let mut request = axum::http::Request::post("/containers/batch")
+            .header(IDEMPOTENCY_HEADER, "key")
+            .body(axum::body::Body::from(body))
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([192, 168, 1, 10], 50000))));
+        app.oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn idempotent_requests_replay_only_the_same_body() {
+        let state: SharedState = Arc::new(test_state());
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let handler = {
+            let calls = calls.clone();
+            post(move |body: String| async move {
+                calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                body
+            })
+        };
+
+        let response = send_idempotent(&state, handler.clone(), "plex").await;
+        assert_eq!(body_text(response).await, "plex");
+        let response = send_idempotent(&state, handler.clone(), "plex").await;
+        assert_eq!(response.headers()["idempotent-replayed"], "true");
+        assert_eq!(body_text(response).await, "plex");
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let response = send_idempotent(&state, handler, "jellyfin").await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn cancelled_idempotent_requests_are_forgotten() {
+        let state: SharedState = Arc::new(test_state());
+        let hanging = post(std::future::pending::<String>);
+        let cancelled = tokio::time::timeout(
+            std::time::Duration::from_millis(50),
+            send_idempotent(&state, hanging, "plex"),
+        )
+        .await;
+        assert!(cancelled.is_err());
+        assert!(state.idempotent_requests.lock().unwrap().is_empty());
+
+        let response = send_idempotent(&state, post(|| async { "done" }), "plex").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_text(response).await, "done");
+    }
+
+    #[tokio::test]
+    async fn failed_idempotent_requests_are_forgotten() {
+        let state: SharedState = Arc::new(test_state());
+        let failing = post(|| async { StatusCode::SERVICE_UNAVAILABLE });
+        let response = send_idempotent(&state, failing, "plex").await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(state.idempotent_requests.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn websockets_open_only_from_this_site_or_cors_origins() {
+        let mut state = test_state();
+        state.cors_origins = vec!["https://grafana.example.com".to_string(), "*".to_string()];
+        let allowed =
+            |pairs: &[(&'static str, &str)]| websocket_origin_allowed(&state, &header_map(pairs));
+
+        assert!(allowed(&[("host", "docker.lan:8080")]));
+        assert!(allowed(&[
+            ("host", "docker.lan:8080"),
+            ("origin", "http://docker.lan:8080")
+        ]));
+        assert!(allowed(&[
+            ("host", "docker.lan"),
+            ("origin", "https://grafana.example.com")
+        ]));
+        assert!(!allowed(&[
+            ("host", "docker.lan:8080"),
+            ("origin", "https://evil.example")
+        ]));
+        assert!(!allowed(&[
+            ("host", "docker.lan:8080"),
+            ("origin", "http://docker.lan")
+        ]));
+        assert!(!allowed(&[("origin", "http://docker.lan:8080")]));
+        assert!(!allowed(&[("host", "docker.lan"), ("origin", "null")]));
+    }
+
+    #[test]
+    fn error_codes_are_the_documented_ones() {
+        for status in 400..600 {
+            let status = StatusCode::from_u16(status).unwrap();
+            assert!(ERROR_CODES.contains(&error_code(status)), "{}", status);
+        }
+        let readme = include_str!("../README.md");
+        let listed = readme
+            .lines()
+            .find(|line| line.starts_with("Errors are answered with"))
+            .unwrap();
+        for code in ERROR_CODES {
+            assert!(
+                listed.contains(&format!("`{}`", code)),
+                "{} not in the README",
+                code
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn signing_out_needs_the_csrf_token() {
+        use tower::ServiceExt;
+
+        let state: SharedState = Arc::new(test_state());
+        let token = csrf_token(&state, &axum::http::HeaderMap::new());
+        let app = Router::new()
+            .route("/logout", post(logout_handle))
+            .with_state(state);
+        let sign_out = |body: String| {
+            axum::http::Request::post("/logout")
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    "application/x-www-form-urlencoded",
+                )
+                .body(axum::body::Body::from(body))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(sign_out("csrf_token=wrong".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(!response
+            .headers()
+            .contains_key(axum::http::header::SET_COOKIE));
+
+        let response = app
+            .clone()
+            .oneshot(sign_out(format!("csrf_token={}", token)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()[axum::http::header::SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .contains("Max-Age=0"));
+
+        let request = axum::http::Request::get("/logout")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    fn login_failure(
+        count: u32,
+        last: std::time::Instant,
+        locked_until: Option<std::time::Instant>,
+    ) -> LoginFailures {
+        LoginFailures {
+            count,
+            last,
+            locked_until,
+        }
+    }
+
+    #[test]
+    fn login_failures_evict_oldest_unlocked_entries_first() {
+        // Far enough from the clock's start that every time below exists
+        let second = std::time::Duration::from_secs(1);
+        let now = std::time::Instant::now() + LOGIN_FAILURE_MEMORY + 60 * second;
+        let mut failures = HashMap::new();
+        failures.insert(
+            "client:old-locked".to_string(),
+            login_failure(5, now - 30 * second, Some(now + 60 * second)),
+        );
+        failures.insert(
+            "client:old".to_string(),
+            login_failure(1, now - 20 * second, None),
+        );
+        failures.insert(
+            "client:expired-lock".to_string(),
+            login_failure(5, now - 15 * second, Some(now - second)),
+        );
+        failures.insert(
+            "client:new".to_string(),
+            login_failure(1, now - 10 * second, None),
+        );
+        failures.insert(
+            "client:forgotten".to_string(),
+            login_failure(1, now - LOGIN_FAILURE_MEMORY, None),
+        );
+
+        evict_login_failures(&mut failures, now, 3);
+        let mut kept: Vec<&str> = failures.keys().map(String::as_str).collect();
+        kept.sort();
+        assert_eq!(
+            kept,
+            ["client:expired-lock", "client:new", "client:old-locked"]
+        );
+
+        evict_login_failures(&mut failures, now, 1);
+        assert_eq!(failures.keys().collect::<Vec<_>>(), ["client:old-locked"]);
+
+        evict_login_failures(&mut failures, now, 0);
+        assert!(failures.is_empty());
+    }
+
+    #[tokio::test]
+    async fn login_failures_stay_within_the_limit() {
+        let state = test_state();
+        for i in 0..MAX_LOGIN_FAILURES + 10 {
+            record_login_failure(
+                &state,
+                &format!("10.0.{}.{}", i / 256, i % 256),
+                None,
+                "/login",
+            )
+            .await;
+        }
+        assert_eq!(
+            state.login_failures.lock().unwrap().len(),
+            MAX_LOGIN_FAILURES
+        );
+
+        record_login_failure(&state, "192.168.1.10", Some("alice"), "/login").await;
+        let failures = state.login_failures.lock().unwrap();
+        assert_eq!(failures.len(), MAX_LOGIN_FAILURES);
+        assert!(failures.contains_key("client:192.168.1.10"));
+        assert!(failures.contains_key("user:alice"));
+        assert!(!failures.contains_key("client:10.0.0.0"));
+    }
+
+    fn link(expires: i64) -> ActionLink {
+        ActionLink {
+            container: "minecraft".to_string(),
+            action: "start".to_string(),
+            expires,
+            nonce: "nonce".to_string(),
+            created_by: "admin".to_string(),
+        }
+    }
+
+    #[test]
+    fn signed_links_verify() {
+        let state = test_state();
+        let token = sign_link(&state, &link(unix_now() + 60));
+        let verified = verify_link(&state, &token).expect("valid link");
+        assert_eq!(verified.container, "minecraft");
+        assert_eq!(verified.action, "start");
+        assert_eq!(verified.nonce, "nonce");
+    }
+
+    #[test]
+    fn tampered_links_are_rejected() {
+        use base64::Engine;
+
+        let state = test_state();
+        let token = sign_link(&state, &link(unix_now() + 60));
+        let (_, signature) = token.split_once('.').unwrap();
+        let mut other = link(unix_now() + 60);
+        other.action = "stop".to_string();
+        let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .encode(serde_json::to_string(&other).unwrap());
+        let forged = format!("{}.{}", payload, signature);
+        assert_eq!(
+            verify_link(&state, &forged).unwrap_err(),
+            "This link is not valid."
+        );
+        assert!(verify_link(&state, "no-signature").is_err());
+
+        let mut other_key = test_state();
+        other_key.link_secret = "other-secret".to_string();
+        assert!(verify_link(&other_key, &token).is_err());
+    }
+
+    #[test]
+    fn expired_and_used_links_are_rejected() {
+        let state = test_state();
+        let token = sign_link(&state, &link(unix_now() - 1));
+        assert_eq!(
+            verify_link(&state, &token).unwrap_err(),
+            "This link has expired."
+        );
+
+        let token = sign_link(&state, &link(unix_now() + 60));
+        state
+            .used_links
+            .lock()
+            .unwrap()
+            .insert("nonce".to_string(), unix_now() + 60);
+        assert_eq!(
+            verify_link(&state, &token).unwrap_err(),
+            "This link has already been used."
+        );
+    }
+
+    #[tokio::test]
+    async fn used_links_survive_a_restart() {
+        let dir = std::env::temp_dir().join(format!("docker-direct-test-{}", random_token()));
+        std::fs::create_dir(&dir).unwrap();
+        let path = used_links_path(&dir.join("audit.jsonl").to_string_lossy());
+        assert_eq!(path, dir.join("used-links.json").to_string_lossy());
+        assert!(load_used_links(&path).unwrap().is_empty());
+
+        let mut state = test_state();
+        state.used_links_file = Some((path.clone(), tokio::sync::Mutex::new(())));
+        {
+            let mut used = state.used_links.lock().unwrap();
+            used.insert("used".to_string(), unix_now() + 60);
+            used.insert("expired".to_string(), unix_now() - 1);
+        }
+        save_used_links(&state).await;
+        let loaded = load_used_links(&path).unwrap();
+        assert_eq!(loaded.keys().collect::<Vec<_>>(), ["used"]);
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(load_used_links(&path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}